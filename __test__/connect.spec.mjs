@@ -76,6 +76,123 @@ test("forward https", async () => {
   await validateShutdown(httpServer, url);
 });
 
+test("forward url", async () => {
+  const httpServer = await makeHttp();
+  const listener = await ngrok.forward({
+    addr: httpServer.listenTo,
+    authtoken: process.env["NGROK_AUTHTOKEN"],
+    url: "tcp://",
+  });
+  const url = listener.url();
+
+  expect(url.startsWith("tcp://")).toBeTruthy();
+  await validateShutdown(httpServer, url.replace("tcp:", "http:"));
+});
+
+test("forward server addrs string", async () => {
+  const httpServer = await makeHttp();
+  const statuses = [];
+  const listener = await ngrok.forward({
+    addr: httpServer.listenTo,
+    authtoken: process.env["NGROK_AUTHTOKEN"],
+    force_new_session: true,
+    server_addrs: "connect.ngrok-agent.com:443",
+    onStatusChange: (status) => statuses.push(status),
+  });
+
+  expect(statuses).toContain("connected");
+  await validateShutdown(httpServer, listener.url());
+});
+
+test("forward from env", async () => {
+  const httpServer = await makeHttp();
+  process.env["NGROK_ADDR"] = httpServer.listenTo;
+  process.env["NGROK_METADATA"] = "from env";
+  process.env["NGROK_SCHEMES"] = "HTTPS";
+  try {
+    const listener = await ngrok.forward({ from_env: true, metadata: "explicit" });
+
+    expect(listener.metadata()).toBe("explicit");
+    expect(listener.url().startsWith("https://")).toBeTruthy();
+    await validateShutdown(httpServer, listener.url());
+  } finally {
+    delete process.env["NGROK_ADDR"];
+    delete process.env["NGROK_METADATA"];
+    delete process.env["NGROK_SCHEMES"];
+  }
+});
+
+test("config from env", async () => {
+  process.env["APP_TUNNEL_DOMAIN"] = "example.ngrok.app";
+  process.env["APP_TUNNEL_BASIC_AUTH"] = "ngrok:online1line; user:password1";
+  process.env["APP_TUNNEL_COMPRESSION"] = "true";
+  process.env["APP_TUNNEL_PORT"] = "8080";
+  try {
+    const config = ngrok.configFromEnv("APP_TUNNEL_", ";");
+
+    expect(config.domain).toBe("example.ngrok.app");
+    expect(config.basic_auth).toEqual(["ngrok:online1line", "user:password1"]);
+    expect(config.compression).toBe(true);
+    expect(config.port).toBe(8080);
+    process.env["APP_TUNNEL_PORT"] = "eighty";
+    expect(() => ngrok.configFromEnv("APP_TUNNEL_")).toThrow(/Invalid value for APP_TUNNEL_PORT/);
+  } finally {
+    for (const key of ["DOMAIN", "BASIC_AUTH", "COMPRESSION", "PORT"]) delete process.env["APP_TUNNEL_" + key];
+  }
+});
+
+test("forward multiple schemes", async () => {
+  const httpServer = await makeHttp();
+  const listener = await ngrok.forward({
+    addr: httpServer.listenTo,
+    authtoken: process.env["NGROK_AUTHTOKEN"],
+    schemes: ["HTTP", "HTTPS"],
+  });
+  const urls = listener.urls();
+
+  expect(urls.length).toBe(2);
+  expect(urls[0].startsWith("http://")).toBeTruthy();
+  expect(urls[1].startsWith("https://")).toBeTruthy();
+  expect(listener.listeners().map((l) => l.url())).toEqual(urls);
+  await validateHttpRequest(urls[0]);
+  await validateHttpRequest(urls[1]);
+
+  await listener.close();
+  expect(await ngrok.getListenerByUrl(urls[1])).toBeNull();
+  httpServer.socket.close();
+});
+
+test("forward multiple schemes later", async () => {
+  const httpServer = await makeHttp();
+  const listener = await ngrok.forward({
+    authtoken: process.env["NGROK_AUTHTOKEN"],
+    schemes: ["HTTP", "HTTPS"],
+  });
+  listener.forward(httpServer.listenTo);
+  const urls = listener.urls();
+
+  await validateHttpRequest(urls[0]);
+  await validateHttpRequest(urls[1]);
+
+  await listener.close();
+  httpServer.socket.close();
+});
+
+test("disconnect results", async () => {
+  const httpServer = await makeHttp();
+  const listener = await ngrok.forward({
+    addr: httpServer.listenTo,
+    authtoken: process.env["NGROK_AUTHTOKEN"],
+  });
+  const url = listener.url();
+  await validateHttpRequest(url);
+
+  const results = await ngrok.disconnect(url, { timeout: 10000 });
+  expect(results).toEqual([{ id: listener.id(), url: url }]);
+  expect(await ngrok.disconnect(url)).toEqual([]);
+  httpServer.socket.close();
+});
+
 test("forward http2", async () => {
   const httpServer = await makeHttp({ useHttp2: true });
   const listener = await ngrok.forward({
@@ -344,3 +461,27 @@ test("traffic policy", async () => {
   const response = await validateShutdown(httpServer, url);
   expect("bar").toBe(response.headers["foo"]);
 });
+
+// last, as it closes every listener and session
+test("shutdown on signals", async () => {
+  const httpServer = await makeHttp();
+  const listener = await ngrok.forward({
+    addr: httpServer.listenTo,
+    authtoken: process.env["NGROK_AUTHTOKEN"],
+  });
+  await validateHttpRequest(listener.url());
+  // served before shutdownOnSignals, so its own signal handlers have to be removed
+  await ngrok.listen(createHttpServer({}));
+  const handlers = process.listenerCount("SIGTERM");
+
+  const cleanedUp = new Promise((resolve) => {
+    ngrok.shutdownOnSignals({ timeout: 1000, exit: false }, resolve);
+  });
+  expect(process.listenerCount("SIGTERM")).toBeLessThan(handlers);
+  expect(() => ngrok.shutdownOnSignals()).toThrow();
+  process.kill(process.pid, "SIGTERM");
+
+  expect(await cleanedUp).toBe("SIGTERM");
+  expect(await ngrok.listeners()).toEqual([]);
+  httpServer.socket.close();
+});
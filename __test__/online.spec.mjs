@@ -8,6 +8,8 @@ import * as net from "net";
 import * as retry from "./retry-config.mjs";
 import * as path from "path";
 import * as os from "os";
+import * as tls from "tls";
+import { Transform } from "stream";
 
 axiosRetry(axios, retry.retryConfig);
 const expected = "Hello";
@@ -45,6 +47,62 @@ async function makeSession() {
     .connect();
 }
 
+// A proxy to the ngrok service which terminates TLS like a man-in-the-middle proxy would,
+// so tests can cut the session's connection to make it reconnect.
+// replace same-length placeholder strings in the traffic to the service, recording those seen
+function rewriter(rewrites, seen) {
+  return new Transform({
+    transform(chunk, _encoding, callback) {
+      let data = chunk.toString("latin1");
+      for (const [from, to] of Object.entries(rewrites)) {
+        if (data.includes(from)) {
+          seen.push(from);
+          data = data.replaceAll(from, to);
+        }
+      }
+      callback(null, Buffer.from(data, "latin1"));
+    },
+  });
+}
+
+async function makeServiceProxy(rewrites = {}) {
+  const host = "connect.ngrok-agent.com";
+  const sockets = new Set();
+  const seen = [];
+  const track = (socket) => {
+    sockets.add(socket);
+    socket.on("close", () => sockets.delete(socket));
+    socket.on("error", () => {});
+  };
+  const server = tls.createServer(
+    {
+      key: fs.readFileSync("__test__/tls/localhost.key"),
+      cert: fs.readFileSync("__test__/tls/localhost.crt"),
+    },
+    (client) => {
+      const upstream = tls.connect({ host, port: 443, servername: host });
+      track(client);
+      track(upstream);
+      // messages are small enough to arrive whole, the auth message in particular
+      client.pipe(rewriter(rewrites, seen)).pipe(upstream).pipe(client);
+    },
+  );
+  await new Promise((resolve) => server.listen(0, resolve));
+  return {
+    addr: "localhost:" + server.address().port,
+    caCert: fs.readFileSync("__test__/tls/ca.crt"),
+    connections: () => sockets.size / 2,
+    // the placeholders which were rewritten, in order
+    seen,
+    // drop every connection through the proxy
+    cut: () => sockets.forEach((socket) => socket.destroy()),
+    close: () => {
+      server.close();
+      sockets.forEach((socket) => socket.destroy());
+    },
+  };
+}
+
 async function makeHttpAndSession(useUnixSocket) {
   return [await makeHttp(useUnixSocket), await makeSession()];
 }
@@ -335,6 +393,19 @@ test("tcp listener", async () => {
   await forwardValidateShutdown(httpServer, listener, listener.url().replace("tcp:", "http:"));
 });
 
+test("endpoint url", async () => {
+  const [httpServer, session] = await makeHttpAndSession();
+  expect(() => session.endpoint("ftp://example.com")).toThrow(/scheme/);
+  expect(session.endpoint("tls://example.com")).toBeInstanceOf(ngrok.TlsListenerBuilder);
+  expect(session.endpoint("https://example.com").toConfig().domain).toBe("example.com");
+
+  const builder = session.endpoint("tcp://");
+  expect(builder).toBeInstanceOf(ngrok.TcpListenerBuilder);
+  const listener = await builder.listen();
+  expect(listener.url().startsWith("tcp://")).toBeTruthy();
+  await forwardValidateShutdown(httpServer, listener, listener.url().replace("tcp:", "http:"));
+});
+
 test("tls listener", async () => {
   const [httpServer, session] = await makeHttpAndSession();
   const listener = await session
@@ -415,6 +486,141 @@ test("console log", async () => {
   ngrok.loggingCallback();
 });
 
+// run serially so other tests are not logging
+test("structured log", async () => {
+  const records = [];
+  ngrok.structuredLoggingCallback((record) => records.push(record));
+  const [httpServer, session] = await makeHttpAndSession();
+  const listener = await session.httpEndpoint().listen();
+  await forwardValidateShutdown(httpServer, listener, listener.url());
+  ngrok.structuredLoggingCallback();
+
+  const created = records.find((record) => record.fields.listener_id == listener.id());
+  expect(created.level).toBe("INFO");
+  expect(created.message).toContain("Created listener");
+  expect(created.fields.url).toBe(listener.url());
+  expect(created.timestamp).toBeLessThanOrEqual(Date.now());
+  expect(Array.isArray(created.spans)).toBeTruthy();
+});
+
+// run serially so other tests are not logging
+test("set log level", async () => {
+  const records = [];
+  ngrok.structuredLoggingCallback((record) => records.push(record), "INFO");
+  const session = await makeSession();
+  ngrok.setLogLevel("debug");
+  await (await session.httpEndpoint().listen()).close();
+  ngrok.setLogLevel("info");
+  await (await session.httpEndpoint().listen()).close();
+  ngrok.structuredLoggingCallback();
+
+  const closing = records.filter((record) => record.message.startsWith("Listener closing"));
+  expect(closing.length).toBe(1);
+  expect(closing[0].level).toBe("DEBUG");
+  expect(() => ngrok.setLogLevel("!!")).toThrow(/Unknown log level/);
+});
+
+// run serially so other tests are not logging
+test("log directives", async () => {
+  const records = [];
+  ngrok.structuredLoggingCallback((record) => records.push(record), "info,ngrok_javascript=debug");
+  const session = await makeSession();
+  await (await session.httpEndpoint().listen()).close();
+  ngrok.setLogLevel("info");
+  ngrok.structuredLoggingCallback();
+
+  const debug = records.filter((record) => record.level == "DEBUG");
+  expect(debug.length).toBeGreaterThan(0);
+  expect(debug.every((record) => record.target.startsWith("ngrok_javascript"))).toBeTruthy();
+  expect(() => ngrok.setLogLevel("ngrok=loud")).toThrow(/Invalid log directives/);
+
+  // a bare target enables all of its levels
+  const traced = [];
+  ngrok.structuredLoggingCallback((record) => traced.push(record), "ngrok_javascript");
+  await (await session.httpEndpoint().listen()).close();
+  ngrok.setLogLevel("info");
+  ngrok.structuredLoggingCallback();
+  expect(traced.some((record) => record.level == "DEBUG")).toBeTruthy();
+  expect(traced.every((record) => record.target.startsWith("ngrok_javascript"))).toBeTruthy();
+});
+
+test("log to file", async () => {
+  const dir = fs.mkdtempSync(path.join(os.tmpdir(), "ngrok-log-"));
+  const logFile = path.join(dir, "ngrok.log");
+  ngrok.logToFile(logFile, { format: "json", maxSize: 512, maxFiles: 2, level: "debug" });
+  const session = await makeSession();
+  await (await session.httpEndpoint().listen()).close();
+  ngrok.removeLogSink(logFile);
+
+  const lines = [logFile, logFile + ".1"].flatMap((file) => fs.readFileSync(file, "utf8").trim().split("\n"));
+  expect(lines.every((line) => JSON.parse(line).level)).toBeTruthy();
+  // the sink's level is its own, the global level stays at info
+  expect(lines.some((line) => JSON.parse(line).level == "DEBUG")).toBeTruthy();
+  expect(fs.existsSync(logFile + ".1")).toBeTruthy();
+  expect(fs.existsSync(logFile + ".3")).toBeFalsy();
+  expect(() => ngrok.logToStderr({ format: "xml" })).toThrow(/Unknown log format/);
+});
+
+test("redacted logs", async () => {
+  const records = [];
+  ngrok.structuredLoggingCallback((record) => records.push(record), "trace");
+  const session = await makeSession();
+  const listener = await session.httpEndpoint().basicAuth("ngrok", "online1line").listen();
+  await listener.close();
+  ngrok.setLogLevel("info");
+  ngrok.structuredLoggingCallback();
+
+  expect(records.length).toBeGreaterThan(0);
+  const text = JSON.stringify(records);
+  expect(text).not.toContain(process.env["NGROK_AUTHTOKEN"]);
+  expect(text).not.toContain("online1line");
+});
+
+test("log spans", async () => {
+  const records = [];
+  ngrok.structuredLoggingCallback((record) => records.push(record), "info,ngrok_javascript=debug");
+  const [httpServer, session] = await makeHttpAndSession();
+  const listener = await session.httpEndpoint().listenAndForward("tcp://" + httpServer.listenTo);
+  await validateHttpRequest(listener.url());
+  await shutdown(listener, httpServer.socket);
+  ngrok.setLogLevel("info");
+  ngrok.structuredLoggingCallback();
+
+  const conn = records.find((record) => record.spans.some((span) => span.name == "connection"));
+  expect(conn).toBeDefined();
+  expect(conn.spans.map((span) => span.name)).toEqual(["session", "listener", "connection"]);
+  expect(conn.spans[0].fields.session_id).toBeDefined();
+  expect(conn.spans[1].fields.listener_id).toBe(listener.id());
+  expect(conn.spans[1].fields.url).toBe(listener.url());
+  expect(conn.spans[2].fields.remote_addr).toBeDefined();
+});
+
+test("export telemetry", async () => {
+  // a stand-in for an OTLP collector, which accepts whatever it is sent
+  const exported = [];
+  const collector = http.createServer((req, res) => {
+    exported.push([req.method, req.url, req.headers["content-type"]]);
+    req.resume();
+    req.on("end", () => res.writeHead(200, { "content-type": "application/x-protobuf" }).end());
+  });
+  await new Promise((resolve) => collector.listen(0, "127.0.0.1", resolve));
+  await ngrok.exportTelemetry({
+    endpoint: `http://127.0.0.1:${collector.address().port}`,
+    protocol: "http",
+    serviceName: "ngrok-test",
+    resourceAttributes: { "deployment.environment": "test" },
+  });
+  const session = await makeSession();
+  await (await session.httpEndpoint().listen()).close();
+  await session.close();
+  await ngrok.stopTelemetry();
+  collector.close();
+
+  expect(exported).toContainEqual(["POST", "/v1/traces", "application/x-protobuf"]);
+  expect(exported).toContainEqual(["POST", "/v1/metrics", "application/x-protobuf"]);
+  await expect(ngrok.exportTelemetry({ protocol: "udp" })).rejects.toThrow(/Unknown telemetry protocol/);
+});
+
 test("listen and forward multipass", async () => {
   const [httpServer, session1] = await makeHttpAndSession();
   const session2 = await makeSession();
@@ -466,6 +672,32 @@ test("tcp multipass", async () => {
   await session2.close();
 });
 
+test("listener drain", async () => {
+  const server = http.createServer(function (req, res) {
+    setTimeout(() => {
+      res.writeHead(200);
+      res.end(expected);
+    }, 1000);
+  });
+  server.listen(0);
+  const session = await makeSession();
+  const listener = await session.httpEndpoint().listenAndForward("http://localhost:" + server.address().port);
+  await validateHttpRequest(listener.url());
+
+  const request = axios.get(listener.url());
+  await new Promise((r) => setTimeout(r, 500));
+  expect(0).toBe(await listener.drain({ timeout: 5000 }));
+  expect(expected).toBe((await request).data);
+  expect(await ngrok.getListener(listener.id())).toBeUndefined();
+
+  const listener2 = await session.httpEndpoint().listenAndForward("http://localhost:" + server.address().port);
+  axios.get(listener2.url()).catch(() => {});
+  await new Promise((r) => setTimeout(r, 500));
+  expect(1).toBe(await listener2.drain({ timeout: 10 }));
+  server.close();
+  await session.close();
+});
+
 // test("unix multipass", async () => {
 //   const httpServer = createHttpServer();
 //   const session1 = await makeSession();
@@ -506,6 +738,100 @@ test("connect heartbeat callbacks", async () => {
   expect(undefined).toBe(disconn_addr);
 });
 
+test("session server addrs failover", async () => {
+  var statuses = [];
+  const builder = new ngrok.SessionBuilder().authtoken(process.env["NGROK_AUTHTOKEN"]);
+  builder
+    .serverAddrs(["localhost:1", "connect.ngrok-agent.com:443"])
+    .handleConnection((status, err, addr) => {
+      statuses.push([status, err, addr]);
+    });
+  const session = await builder.connect();
+  expect(session.serverAddr()).toBe("connect.ngrok-agent.com:443");
+  expect([["connected", undefined, "connect.ngrok-agent.com:443"]]).toEqual(statuses);
+  await session.close();
+});
+
+test("session events", async () => {
+  const session = await makeSession();
+  const events = session.events();
+  const listener = await session.httpEndpoint().listen();
+  const id = listener.id();
+  await listener.close();
+  await session.close();
+
+  const types = [];
+  for await (const event of events) {
+    types.push(event.type);
+    if (event.type == "listener-closed") {
+      expect(event.listenerId).toBe(id);
+    }
+  }
+  expect(types).toContain("listener-closed");
+});
+
+test("session events end when the session fails", async () => {
+  const proxy = await makeServiceProxy();
+  try {
+    const session = await new ngrok.SessionBuilder()
+      .authtokenFromEnv()
+      .serverAddr(proxy.addr)
+      .caCert(proxy.caCert)
+      .handleDisconnection(() => false)
+      .connect();
+    const events = session.events();
+    proxy.cut();
+
+    const types = [];
+    for await (const event of events) {
+      types.push(event.type);
+    }
+    expect(types).toContain("disconnected");
+  } finally {
+    proxy.close();
+  }
+});
+
+test("session restart recreates listeners", async () => {
+  const token = process.env["NGROK_AUTHTOKEN"];
+  // rewritten to the real token by the proxy, so it is only a different token to the session
+  const rotated = "rotated".padEnd(token.length, "x");
+  const proxy = await makeServiceProxy({ [rotated]: token });
+  const httpServer = await makeHttp();
+  try {
+    const tokens = [token, rotated];
+    const session = await new ngrok.SessionBuilder()
+      .authtokenProvider(async () => tokens[0])
+      .serverAddr(proxy.addr)
+      .caCert(proxy.caCert)
+      .connect();
+    const events = session.events();
+    const listener = await session.httpEndpoint().listenAndForward(httpServer.listenTo);
+    const previousId = listener.id();
+
+    // rotating the token restarts the session on reconnect
+    tokens.shift();
+    proxy.cut();
+
+    let restarted;
+    for await (const event of events) {
+      if (event.type == "listener-restarted") {
+        restarted = event;
+        break;
+      }
+    }
+    expect(restarted.previousListenerId).toBe(previousId);
+    expect(restarted.listenerId).not.toBe(previousId);
+    expect(listener.id()).toBe(restarted.listenerId);
+    expect(proxy.seen).toContain(rotated);
+    await validateHttpRequest(listener.url());
+    await session.close();
+  } finally {
+    proxy.close();
+    httpServer.socket.close();
+  }
+});
+
 test("session ca_cert", async () => {
   const builder = new ngrok.SessionBuilder();
   try {
@@ -524,6 +850,126 @@ test("session incorrect authtoken", async () => {
   }
 });
 
+test("session authtoken from file", async () => {
+  const dir = fs.mkdtempSync(path.join(os.tmpdir(), "ngrok-authtoken-"));
+  const tokenFile = path.join(dir, "authtoken");
+  fs.writeFileSync(tokenFile, process.env["NGROK_AUTHTOKEN"] + "\n");
+  const session = await new ngrok.SessionBuilder().authtokenFromFile(tokenFile).connect();
+  await session.close();
+
+  expect(() => new ngrok.SessionBuilder().authtokenFromFile(path.join(dir, "missing"))).toThrow(
+    /Failed to read authtoken file/,
+  );
+});
+
+test("session authtoken from agent config", async () => {
+  const dir = fs.mkdtempSync(path.join(os.tmpdir(), "ngrok-agent-"));
+  const agentConfig = path.join(dir, "ngrok.yml");
+  fs.writeFileSync(agentConfig, `version: "3"\nagent:\n  authtoken: ${process.env["NGROK_AUTHTOKEN"]}\n`);
+  const session = await new ngrok.SessionBuilder().authtokenFromAgentConfig(agentConfig).connect();
+  await session.close();
+
+  fs.writeFileSync(agentConfig, "version: 2\nregion: us\n");
+  expect(() => new ngrok.SessionBuilder().authtokenFromAgentConfig(agentConfig)).toThrow(/No authtoken/);
+});
+
+test("session authtoken provider", async () => {
+  let calls = 0;
+  const session = await new ngrok.SessionBuilder()
+    .authtokenProvider(async () => {
+      calls++;
+      return process.env["NGROK_AUTHTOKEN"];
+    })
+    .connect();
+  expect(calls).toEqual(1);
+  await session.close();
+
+  const failing = new ngrok.SessionBuilder().authtokenProvider(async () => {
+    throw new Error("vault sealed");
+  });
+  await expect(failing.connect()).rejects.toThrow(/Authtoken provider failed/);
+});
+
+test("session authtoken provider on reconnect", async () => {
+  const token = process.env["NGROK_AUTHTOKEN"];
+  // rewritten to the real token by the proxy, so it is only a different token to the session
+  const rotated = "rotated".padEnd(token.length, "x");
+  const proxy = await makeServiceProxy({ [rotated]: token });
+  try {
+    let calls = 0;
+    const tokens = [token, rotated];
+    const session = await new ngrok.SessionBuilder()
+      .authtokenProvider(async () => {
+        calls++;
+        return tokens[0];
+      })
+      .serverAddr(proxy.addr)
+      .caCert(proxy.caCert)
+      .connect();
+    const events = session.events();
+    tokens.shift();
+    proxy.cut();
+
+    const types = [];
+    for await (const event of events) {
+      types.push(event.type);
+      if (event.type == "connected") {
+        break;
+      }
+    }
+    expect(types).toEqual(["disconnected", "connected"]);
+    // once to connect and once for the disconnect, the restart reuses the token
+    expect(calls).toEqual(2);
+    expect(proxy.seen).toEqual([rotated]);
+    await session.close();
+  } finally {
+    proxy.close();
+  }
+});
+
+test("session authtoken accounts", async () => {
+  await ngrok.authtoken(process.env["NGROK_AUTHTOKEN"], { account: "team-b" });
+  expect(await ngrok.authtokenAccounts()).toContain("team-b");
+  const session = await new ngrok.SessionBuilder().account("team-b").connect();
+  await session.close();
+  await expect(
+    new ngrok.SessionBuilder().authtokenFromEnv().account("team-b").connect(),
+  ).rejects.toThrow(/Both an authtoken and the account "team-b" were configured/);
+
+  expect(await ngrok.removeAuthtoken({ account: "team-b" })).toBeTruthy();
+  expect(await ngrok.removeAuthtoken({ account: "team-b" })).toBeFalsy();
+  expect(await ngrok.authtokenAccounts()).not.toContain("team-b");
+  await expect(new ngrok.SessionBuilder().account("team-b").connect()).rejects.toThrow(
+    /No authtoken stored for account "team-b"/,
+  );
+});
+
+test("session server cert pins", async () => {
+  const host = "connect.ngrok-agent.com";
+  const fingerprint = await new Promise((resolve, reject) => {
+    const socket = tls.connect({ host, port: 443, servername: host }, () => {
+      resolve(socket.getPeerCertificate().fingerprint256);
+      socket.end();
+    });
+    socket.on("error", reject);
+  });
+  const unknown = "sha256/" + Buffer.alloc(32).toString("base64");
+
+  // a backup pin is enough
+  const session = await new ngrok.SessionBuilder()
+    .authtokenFromEnv()
+    .serverCertPins([unknown], [fingerprint])
+    .connect();
+  await session.close();
+
+  await expect(
+    new ngrok.SessionBuilder().authtokenFromEnv().serverCertPins([unknown]).connect(),
+  ).rejects.toThrow(
+    /tls handshake error: .*matched none of the pinned certificates, its public key is sha256\//,
+  );
+  expect(() => new ngrok.SessionBuilder().serverCertPins(["not a pin"])).toThrow(/Invalid certificate pin/);
+});
+
 test("listener invalid domain", async () => {
   const session = await makeSession();
   try {
@@ -550,3 +996,104 @@ test("traffic policy", async () => {
   const response = await forwardValidateShutdown(httpServer, listener, listener.url());
   expect("bar").toBe(response.headers["foo"]);
 });
+
+test("traffic policy object", async () => {
+  const trafficPolicy = new ngrok.TrafficPolicy()
+    .onHttpResponse({
+      name: "add foo",
+      actions: [{ type: "add-headers", config: { headers: { foo: "bar" } } }],
+    })
+    .customResponse(418, "teapot", { "content-type": "text/plain" }, ["req.url.path == '/tea'"]);
+  expect(JSON.parse(trafficPolicy.toString()).on_http_request[0].actions[0].type).toBe("custom-response");
+
+  const [httpServer, session] = await makeHttpAndSession();
+  const listener = await session.httpEndpoint().trafficPolicy(trafficPolicy).listen();
+  const teapot = await axios.get(listener.url() + "/tea", { validateStatus: () => true });
+  expect(teapot.status).toBe(418);
+  expect(teapot.data).toBe("teapot");
+  const response = await forwardValidateShutdown(httpServer, listener, listener.url());
+  expect("bar").toBe(response.headers["foo"]);
+});
+
+test("traffic policy file", async () => {
+  const dir = fs.mkdtempSync(path.join(os.tmpdir(), "ngrok-policy-"));
+  const bad = path.join(dir, "bad.yml");
+  fs.writeFileSync(bad, "on_http_request:\n  - actions:\n      - type: custom-response\n        config:\n          content: hi\n");
+  const [httpServer, session] = await makeHttpAndSession();
+  expect(() => session.httpEndpoint().trafficPolicyFile(bad)).toThrow(/status_code.*line 5 column 11/);
+
+  const good = path.join(dir, "good.yml");
+  fs.writeFileSync(good, "on_http_response:\n  - actions:\n      - type: add-headers\n        config:\n          headers:\n            foo: bar\n");
+  const listener = await session.httpEndpoint().trafficPolicyFile(good).listen();
+  const response = await forwardValidateShutdown(httpServer, listener, listener.url());
+  expect("bar").toBe(response.headers["foo"]);
+});
+
+test("traffic policy evaluation", async () => {
+  const trafficPolicy = new ngrok.TrafficPolicy()
+    .restrictIps(null, ["10.0.0.0/8"], "on_tcp_connect")
+    .customResponse(418, "teapot", null, ["req.url.path == '/tea' && 'earl' in req.headers['x-kind']"])
+    .onHttpResponse({ name: "add foo", actions: [{ type: "add-headers", config: { headers: { foo: "bar" } } }] });
+
+  const tea = ngrok.evaluateTrafficPolicy(trafficPolicy, { path: "/tea", headers: { "X-Kind": "earl" } });
+  expect(tea.actions).toEqual(["restrict-ips", "custom-response"]);
+  expect(tea.terminatedBy).toBe("custom-response");
+
+  const other = ngrok.evaluateTrafficPolicy(trafficPolicy.toString(), { method: "POST", path: "/coffee" });
+  expect(other.actions).toEqual(["restrict-ips", "add-headers"]);
+  expect(other.rules.map((rule) => rule.matched)).toEqual([true, false, true]);
+  expect(other.terminatedBy).toBeUndefined();
+
+  const denied = ngrok.evaluateTrafficPolicy(trafficPolicy, { clientIp: "10.1.2.3", path: "/tea" });
+  expect(denied.terminatedBy).toBe("restrict-ips");
+  expect(denied.rules.length).toBe(1);
+
+  const policy = '{"on_http_request": [{"expressions": ["req.nope"], "actions": [{"type": "deny"}]}]}';
+  const broken = ngrok.evaluateTrafficPolicy(policy, { path: "/" });
+  expect(broken.rules[0].error).toContain("no such key");
+
+  // pathological expressions are errors rather than crashing the process
+  const expressions = ["(".repeat(5000) + "true" + ")".repeat(5000), "-(-9223372036854775807 - 1) > 0"];
+  const rules = expressions.map((expression) => ({ expressions: [expression], actions: [{ type: "deny" }] }));
+  const pathological = ngrok.evaluateTrafficPolicy(JSON.stringify({ on_http_request: rules }), { path: "/" });
+  expect(pathological.rules[0].error).toContain("nested more than 100 levels deep");
+  expect(pathological.rules[1].error).toContain("overflow");
+
+  // action types unknown to the local checks are left to the service
+  const unknown = '{"on_http_request": [{"actions": [{"type": "owasp-crs-request", "config": {"on_error": "halt"}}]}]}';
+  expect(ngrok.evaluateTrafficPolicy(unknown, { path: "/" }).actions).toEqual(["owasp-crs-request"]);
+});
+
+test("builder config round trip", async () => {
+  const session = await makeSession();
+  const builder = session
+    .httpEndpoint()
+    .metadata("meta")
+    .scheme("HTTPS")
+    .allowCidr("0.0.0.0/0")
+    .requestHeader("X-Req", "yes");
+  const config = builder.toConfig();
+  expect(config.proto).toBe("http");
+  expect(config.metadata).toBe("meta");
+  expect(config.ip_restriction_allow_cidrs).toEqual(["0.0.0.0/0"]);
+  expect(config.request_header_add).toEqual(["X-Req:yes"]);
+
+  const copy = session.httpEndpoint().fromConfig(config);
+  expect(copy.toConfig()).toEqual(config);
+  expect(() => session.tcpEndpoint().fromConfig(config)).toThrow();
+  await session.close();
+});
+
+test("builder clone", async () => {
+  const session = await makeSession();
+  const builder = session.httpEndpoint().metadata("original");
+  const copy = builder.clone().metadata("copy");
+  expect(builder.toConfig().metadata).toBe("original");
+  expect(copy.toConfig().metadata).toBe("copy");
+
+  const listener = await builder.listen();
+  const copyListener = await copy.listen();
+  expect(listener.metadata()).toBe("original");
+  expect(copyListener.metadata()).toBe("copy");
+  await session.close();
+});
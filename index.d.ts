@@ -9,6 +9,12 @@
  * @group Functions
  */
 export interface Config {
+  /**
+   * The name of the account whose authtoken, stored with {@link authtoken}, the
+   * session authenticates with. Each account gets its own session.
+   * It cannot be combined with `authtoken` or `authtoken_from_env`.
+   */
+  account?: string
   /**
    * Port, network address, url, or named pipe. Defaults to 80.
    * Examples: "80", "localhost:8080", "https://192.168.1.100:8443", "unix:/tmp/my.sock", "pipe://./my-pipe"
@@ -34,6 +40,16 @@ export interface Config {
    * NGROK_AUTHTOKEN environment variable.
    */
   authtoken_from_env?: boolean
+  /**
+   * Shortcut for calling [SessionBuilder::authtoken] with the contents of the file
+   * at this path, such as a mounted Kubernetes secret.
+   */
+  authtoken_from_file?: string
+  /**
+   * Shortcut for calling [SessionBuilder::authtoken] with the authtoken saved by
+   * `ngrok config add-authtoken` in the default ngrok agent config file.
+   */
+  authtoken_from_agent_config?: boolean
   /** Credentials for basic authentication, with username and password colon separated. */
   basic_auth?: string|Array<string>
   /** Unused, will warn and be ignored */
@@ -78,6 +94,12 @@ export interface Config {
   forwards_to?: string
   /** Force a new session connection to be made. */
   force_new_session?: boolean
+  /**
+   * Fill in the options not given here from NGROK_* environment variables, named after
+   * the option in upper case, e.g. NGROK_DOMAIN or NGROK_TRAFFIC_POLICY.
+   * Lists are comma separated. See {@link configFromEnv} for a different prefix or delimiter.
+   */
+  from_env?: boolean
   /** Unused, will warn and be ignored */
   host_header?: string
   /**
@@ -126,11 +148,12 @@ export interface Config {
   /**
    * Certificates to use for client authentication at the ngrok edge.
    * Only used if "proto" is "tls" or "http".
+   * Each is a string, or the raw bytes of a Buffer or Uint8Array.
    * See [Mutual TLS] in the ngrok docs for additional details.
    *
    * [Mutual TLS]: https://ngrok.com/docs/cloud-edge/modules/mutual-tls/
    */
-  mutual_tls_cas?: string|Array<string>
+  mutual_tls_cas?: string|Uint8Array|Array<string|Uint8Array>
   /** Unused, will warn and be ignored */
   name?: string
   /**
@@ -223,6 +246,11 @@ export interface Config {
   onStatusChange?: (status: string) => void
   /** DEPRECATED: use TrafficPolicy instead. */
   policy?: string
+  /**
+   * Enable endpoint pooling for this listener.
+   * Only used if proto is "http", "tcp", or "tls".
+   */
+  pooling_enabled?: boolean
   /**
    * The port for the listener to forward to.
    * Only used if addr is not defined.
@@ -284,7 +312,8 @@ export interface Config {
   /**
    * The scheme that this edge should use.
    * "HTTPS" or "HTTP", defaults to "HTTPS".
-   * If multiple are given only the last one is used.
+   * If multiple are given, {@link forward} starts a listener for each, grouped into the
+   * one {@link Listener} it returns. Listener builders only use the last one.
    */
   schemes?: string|Array<string>
   /**
@@ -319,12 +348,31 @@ export interface Config {
    * [server_addr parameter in the ngrok docs]: https://ngrok.com/docs/ngrok-agent/config#server_addr
    */
   server_addr?: string
+  /**
+   * Configures an ordered list of network addresses to dial to connect to the ngrok service,
+   * tried on connect and on every reconnect until one succeeds. Overrides server_addr.
+   * A single address is accepted as a string.
+   */
+  server_addrs?: string | Array<string>
+  /** How to choose between the server_addrs, "ordered" or "latency", defaults to "ordered". */
+  server_addr_strategy?: string
   /** Unused, use domain instead, will warn and be ignored */
   subdomain?: string
   /** Unused, will warn and be ignored */
   terminate_at?: string
-  /** The Traffic Policy to use for this endpoint. */
-  traffic_policy?: string
+  /** The Traffic Policy to use for this endpoint, as a JSON or YAML string, or a {@link TrafficPolicy}. */
+  traffic_policy?: string | TrafficPolicy
+  /**
+   * A path to a JSON or YAML Traffic Policy file to use for this endpoint.
+   * The file is checked locally before listening, reporting the line and column of any error.
+   */
+  traffic_policy_file?: string
+  /**
+   * The URL of the endpoint, such as "https://example.ngrok.app", "tls://example.ngrok.app"
+   * or "tcp://1.tcp.ngrok.io:12345". The proto, and the domain or remote_addr, are inferred
+   * from it unless given separately.
+   */
+  url?: string
   /** Whether to disable certificate verification for this listener */
   verify_upstream_tls?: boolean
   /**
@@ -352,6 +400,12 @@ export interface Config {
    */
   binding?: string
 }
+/**
+ * Read a Config from environment variables named after its options in upper case, after
+ * the prefix, e.g. NGROK_DOMAIN, NGROK_TRAFFIC_POLICY or NGROK_BASIC_AUTH with the default
+ * "NGROK_" prefix. Lists such as NGROK_SCHEMES are split on the delimiter, "," by default.
+ */
+export declare function configFromEnv(prefix?: string | undefined | null, delimiter?: string | undefined | null): Config
 /**
  * Alias for {@link forward}.
  *
@@ -368,24 +422,384 @@ export declare function connect(config: Config|string|number): Promise<Listener>
  * listener = await ngrok.forward({addr: "unix:///path/to/unix.socket", basic_auth: "ngrok:online1line", authtoken_from_env: true});
  */
 export declare function forward(config: Config|string|number): Promise<Listener>
-/** Close a listener with the given url, or all listeners if no url is defined. */
-export declare function disconnect(url?: string | undefined | null): Promise<void>
+/**
+ * Options for {@link disconnect}.
+ *
+ * @group Functions
+ */
+export interface DisconnectOptions {
+  /** How long to wait in milliseconds for each listener to close before giving up on it. */
+  timeout?: number
+}
+/**
+ * Close the listeners with the given url, or all listeners if no url is defined.
+ * The listeners are closed concurrently, and a result is returned for each of them
+ * rather than failing on the first error.
+ */
+export declare function disconnect(url?: string | undefined | null, options?: DisconnectOptions | undefined | null): Promise<Array<CloseResult>>
 /** Close all listeners. */
-export declare function kill(): Promise<void>
+export declare function kill(): Promise<Array<CloseResult>>
+/**
+ * An event in the life of a session, as yielded by {@link Session.events}.
+ *
+ * @group Listener and Sessions
+ */
+export interface SessionEvent {
+  /** The kind of event. */
+  type: "connected" | "disconnected" | "heartbeat" | "command" | "listener-closed" | "listener-restarted"
+  /** The server address for "connected" and "disconnected" events. */
+  addr?: string
+  /** The error message for "disconnected" events. */
+  error?: string
+  /** The heartbeat latency in milliseconds for "heartbeat" events, absent if the heartbeat timed out. */
+  latency?: number
+  /** The command for "command" events, one of "stop", "restart", or "update". */
+  command?: string
+  /** The update request for "update" commands. */
+  update?: UpdateRequest
+  /** The listener id for "listener-closed" and "listener-restarted" events. */
+  listenerId?: string
+  /** The id the listener had before the session restarted, for "listener-restarted" events. */
+  previousListenerId?: string
+}
+/**
+ * Options for {@link Listener.drain}.
+ *
+ * @group Listener and Sessions
+ */
+export interface DrainOptions {
+  /**
+   * How long to wait in milliseconds for active connections to finish before cutting them off.
+   * Waits indefinitely if not set, which never resolves while a connection stays open.
+   */
+  timeout?: number
+}
+/**
+ * The outcome of closing one of the listeners in {@link disconnect}.
+ *
+ * @group Listener and Sessions
+ */
+export interface CloseResult {
+  /** The id of the listener. */
+  id: string
+  /** The url of the listener, if it has one. */
+  url?: string
+  /** Why the listener could not be closed, absent if it was closed. */
+  error?: string
+}
 /** Retrieve a list of non-closed listeners, in no particular order. */
 export declare function listeners(): Promise<Array<Listener>>
 /** Retrieve listener using the id */
 export declare function getListener(id: string): Promise<Listener | null>
 /** Retrieve listener using the url */
 export declare function getListenerByUrl(url: string): Promise<Listener | null>
+/**
+ * Options for {@link logToStderr} and {@link logToFile}.
+ *
+ * @group Functions
+ */
+export interface LogSinkOptions {
+  /** The format of each line, one of "full", "compact", "pretty", or "json". Defaults to "full". */
+  format?: string
+  /** Whether to start each line with a timestamp, defaults to true. */
+  timestamps?: boolean
+  /** Whether to use ANSI colors. Defaults to true for stderr if it is a terminal, false otherwise. */
+  ansi?: boolean
+  /**
+   * The log level of this sink, or `RUST_LOG` style directives, which leaves the level of
+   * the other sinks and the callbacks as is. Follows {@link setLogLevel} if not set.
+   */
+  level?: string
+  /** For files, start a new file once the current one would exceed this many bytes. */
+  maxSize?: number
+  /** For files, start a new file each day, in UTC. */
+  rotateDaily?: boolean
+  /** For files, how many previous files to keep, named with suffixes ".1" (newest) to ".N". Defaults to 5. */
+  maxFiles?: number
+}
+/**
+ * Write log events to stderr natively, without passing them through javascript,
+ * so they are written even while the nodejs event loop is blocked.
+ * Calling this again replaces the previous options.
+ */
+export declare function logToStderr(options?: LogSinkOptions | undefined | null): void
+/**
+ * Write log events to the file at the given path natively, without passing them through
+ * javascript, rotating it by size or by day if configured.
+ * Calling this again with the same path replaces the previous options.
+ */
+export declare function logToFile(path: string, options?: LogSinkOptions | undefined | null): void
+/** Stop writing log events to stderr, if "stderr" is given, or to the file at the given path. */
+export declare function removeLogSink(sink: string): void
+/**
+ * A log event, as passed to the {@link structuredLoggingCallback} callback.
+ *
+ * @group Functions
+ */
+export interface LogRecord {
+  /** When the event happened, in milliseconds since the Unix epoch, as with `Date.now()`. */
+  timestamp: number
+  /** The level of the event, one of ERROR, WARN, INFO, DEBUG, or TRACE. */
+  level: string
+  /** The module the event came from, e.g. "ngrok::session". */
+  target: string
+  /** The message of the event. */
+  message: string
+  /** The other fields recorded with the event. */
+  fields: Record<string, any>
+  /** The spans the event happened within, outermost first. */
+  spans: Array<LogSpan>
+}
+/**
+ * A span in the {@link LogRecord.spans} stack.
+ *
+ * @group Functions
+ */
+export interface LogSpan {
+  /** The name of the span, e.g. "listener". */
+  name: string
+  /** The fields recorded with the span, e.g. the listener id. */
+  fields: Record<string, any>
+}
 /**
  * Register a callback function that will receive logging event information.
  * An absent callback will unregister an existing callback function.
- * The log level defaults to INFO, it can be set to one of ERROR, WARN, INFO, DEBUG, or TRACE.
+ * The log level defaults to the `RUST_LOG` environment variable, or INFO if that is not set.
+ * It can be set to one of ERROR, WARN, INFO, DEBUG, or TRACE, or to per-target directives
+ * as with `RUST_LOG`, and changed later with {@link setLogLevel}.
  */
 export declare function loggingCallback(callback?: (level: string, target: string, message: string) => void, level?: string): void
-/** Set the default auth token to use for any future sessions. */
-export declare function authtoken(authtoken: string): Promise<void>
+/**
+ * Register a callback function that will receive each logging event as a {@link LogRecord},
+ * with all of its fields and the spans it happened within, such as for JSON loggers like
+ * pino or winston. It can be used alongside {@link loggingCallback}.
+ * An absent callback will unregister an existing callback function.
+ * The log level defaults to the `RUST_LOG` environment variable, or INFO if that is not set.
+ * It can be set to one of ERROR, WARN, INFO, DEBUG, or TRACE, or to per-target directives
+ * as with `RUST_LOG`, and changed later with {@link setLogLevel}.
+ */
+export declare function structuredLoggingCallback(callback?: (record: LogRecord) => void, level?: string): void
+/**
+ * Change the level of the log events passed to the logging callbacks, taking effect immediately.
+ * The level can be one of ERROR, WARN, INFO, DEBUG, TRACE, or OFF, or comma separated
+ * directives setting the level per target, as with `RUST_LOG`, e.g. "info,ngrok=debug,muxado=warn".
+ * A bare target such as "ngrok" enables every level for that target only.
+ */
+export declare function setLogLevel(level: string): void
+/**
+ * A sample HTTP request or TCP connection for {@link evaluateTrafficPolicy}.
+ * Leave `method` and `path` unset for a TCP connection.
+ *
+ * @group Functions
+ */
+export interface TrafficPolicySample {
+  /** The HTTP request method, defaults to "GET" if `path` is set. */
+  method?: string
+  /**
+   * The HTTP request path, with an optional query string, e.g. "/api/items?page=2".
+   * Defaults to "/" if `method` is set.
+   */
+  path?: string
+  /** The host of the HTTP request, defaults to "example.ngrok.app". */
+  host?: string
+  /** The scheme of the HTTP request, "http" or "https", defaults to "https". */
+  scheme?: string
+  /** The HTTP request headers. Names are lowercased, as in `req.headers['user-agent']`. */
+  headers?: Record<string, string>
+  /** The IP address of the client, defaults to "127.0.0.1". */
+  clientIp?: string
+  /** The port of the client. */
+  clientPort?: number
+  /** The port of the endpoint, defaults to 80 for the "http" scheme and 443 otherwise. */
+  serverPort?: number
+  /** The status code of the upstream response, for "on_http_response" rules, defaults to 200. */
+  statusCode?: number
+  /** The headers of the upstream response, for "on_http_response" rules. */
+  responseHeaders?: Record<string, string>
+}
+/**
+ * How a rule fared in {@link evaluateTrafficPolicy}.
+ *
+ * @group Functions
+ */
+export interface TrafficPolicyRuleResult {
+  /** The phase of the rule, e.g. "on_http_request". */
+  phase: string
+  /** The position of the rule within its phase. */
+  index: number
+  /** The name of the rule, if it has one. */
+  name?: string
+  /** Whether all of the rule's expressions matched. */
+  matched: boolean
+  /** The types of the actions which would run, in order. Empty if the rule did not match. */
+  actions: Array<string>
+  /** Why the rule's expressions could not be evaluated, in which case it did not match. */
+  error?: string
+}
+/**
+ * The outcome of {@link evaluateTrafficPolicy}.
+ *
+ * @group Functions
+ */
+export interface TrafficPolicyEvaluation {
+  /** The rules which were evaluated, in order. Rules after a terminating action are not evaluated. */
+  rules: Array<TrafficPolicyRuleResult>
+  /** The types of all of the actions which would run, in order. */
+  actions: Array<string>
+  /**
+   * The action which would end processing, such as "deny", "custom-response", "redirect",
+   * or an enforced "restrict-ips" rejecting the client.
+   */
+  terminatedBy?: string
+}
+/**
+ * Evaluate a traffic policy locally against a sample HTTP request or TCP connection, reporting
+ * which rules match and which actions would run, such as to unit test a policy without a listener.
+ *
+ * The "on_tcp_connect" rules are evaluated for every sample, followed by the "on_http_request"
+ * and "on_http_response" rules for HTTP requests. Expressions may use the common subset of CEL:
+ * the `req`, `res` and `conn` variables, literals, lists and maps, the usual operators and `in`,
+ * `has()`, `size()`, `int()`, `double()`, `string()`, `inCidrRange()`, `inCidrRanges()`, the string
+ * methods `startsWith`, `endsWith`, `contains`, `matches`, `lowerAscii` and `upperAscii`, and the
+ * `exists`, `all`, `exists_one`, `filter` and `map` macros. Actions depending on state or other
+ * services, such as "rate-limit" or "oauth", are reported as running without being evaluated.
+ */
+export declare function evaluateTrafficPolicy(policy: string | TrafficPolicy, sample: TrafficPolicySample): TrafficPolicyEvaluation
+/**
+ * Turn masking of secrets in log events and error messages on or off.
+ * Authtokens, passwords, client secrets, webhook secrets and private keys
+ * are replaced with "[REDACTED]". On by default.
+ */
+export declare function setRedaction(enabled: boolean): void
+/**
+ * Options for {@link authtoken} and {@link removeAuthtoken}.
+ *
+ * @group Functions
+ */
+export interface AuthtokenOptions {
+  /**
+   * The name of the ngrok account the authtoken belongs to. Sessions use it when
+   * built with {@link SessionBuilder.account}, or by `forward()` with the `account` option.
+   */
+  account?: string
+}
+/**
+ * Set the default auth token to use for any future sessions, or the auth token of
+ * the named account.
+ */
+export declare function authtoken(authtoken: string, options?: AuthtokenOptions | undefined | null): Promise<void>
+/** List the names of the accounts with a stored auth token. */
+export declare function authtokenAccounts(): Promise<Array<string>>
+/**
+ * Remove the default auth token, or the auth token of the named account.
+ * Returns whether there was one to remove.
+ */
+export declare function removeAuthtoken(options?: AuthtokenOptions | undefined | null): Promise<boolean>
+/**
+ * Options for {@link shutdownOnSignals}.
+ *
+ * @group Functions
+ */
+export interface ShutdownOptions {
+  /**
+   * How long to wait in milliseconds for active connections to finish before
+   * cutting them off, defaults to 10 seconds.
+   */
+  timeout?: number
+  /**
+   * Whether to exit the process once shut down, defaults to true.
+   * When false, control is handed back to nodejs after the cleanup hook has run.
+   */
+  exit?: boolean
+}
+/**
+ * Gracefully shut down on SIGINT or SIGTERM (Ctrl-C on Windows): drain and close every
+ * listener, close every session, then run the optional cleanup hook and exit.
+ * A second signal during shutdown exits immediately, as does a signal after a shutdown
+ * which did not exit.
+ *
+ * The signals are caught natively, so this should not be combined with
+ * `process.on("SIGINT")` or `process.on("SIGTERM")` handlers, which take precedence once registered.
+ */
+export declare function shutdownOnSignals(options?: ShutdownOptions, cleanup?: (signal: string) => void | Promise<void>): void
+/**
+ * Options for {@link exportTelemetry}.
+ *
+ * @group Functions
+ */
+export interface TelemetryOptions {
+  /**
+   * The OTLP endpoint of the collector, e.g. "http://localhost:4317" for grpc, or
+   * "http://localhost:4318" for http. Defaults to the `OTEL_EXPORTER_OTLP_ENDPOINT`
+   * environment variable, or the standard local endpoint.
+   */
+  endpoint?: string
+  /** The OTLP protocol, "grpc" or "http" (protobuf over http). Defaults to "grpc". */
+  protocol?: string
+  /** The `service.name` resource attribute, defaults to "ngrok-javascript". */
+  serviceName?: string
+  /** Other resource attributes to describe this process with, e.g. `deployment.environment`. */
+  resourceAttributes?: Record<string, string>
+  /** How often to export metrics in milliseconds, defaults to 60 seconds. */
+  metricsInterval?: number
+}
+/**
+ * Export the tracing spans of sessions, listeners and connections, and metrics about them,
+ * to an OpenTelemetry collector over OTLP. The spans are only exported for the enabled
+ * log levels, as set with {@link setLogLevel}. Secrets in their fields and events are
+ * masked as in the logs, see {@link setRedaction}.
+ * Calling this again replaces the previous export.
+ */
+export declare function exportTelemetry(options?: TelemetryOptions | undefined | null): Promise<void>
+/**
+ * Stop exporting to the OpenTelemetry collector, after sending any spans and metrics
+ * which have not been exported yet.
+ */
+export declare function stopTelemetry(): Promise<void>
+/**
+ * A rule of a {@link TrafficPolicy}: the actions to run when all of its expressions match.
+ *
+ * @group Listener Builders
+ */
+export interface TrafficPolicyRule {
+  /** A name for the rule, to identify it in logs and dashboards. */
+  name?: string
+  /**
+   * CEL expressions which must all match for the actions to run, e.g. "req.url.path.startsWith('/api')".
+   * The actions always run if there are none.
+   */
+  expressions?: Array<string>
+  /** The actions to run, in order. */
+  actions: Array<TrafficPolicyAction>
+}
+/**
+ * An action of a {@link TrafficPolicyRule}.
+ * See [Traffic Policy Actions] in the ngrok docs for the action types and their configuration.
+ *
+ * [Traffic Policy Actions]: https://ngrok.com/docs/traffic-policy/actions/
+ *
+ * @group Listener Builders
+ */
+export interface TrafficPolicyAction {
+  /** The type of action, e.g. "restrict-ips" or "custom-response". */
+  type: string
+  /** The configuration of the action. */
+  config?: Record<string, any>
+}
+/**
+ * An async iterator of {@link SessionEvent}s, in the order they occurred.
+ *
+ * @group Listener and Sessions
+ */
+export declare class SessionEvents {
+  /**
+   * Wait for the next event. Returns null once the session has been closed,
+   * stopped by the ngrok service, has failed to reconnect, or was dropped.
+   */
+  recv(): Promise<SessionEvent | null>
+  /** Stop receiving events, ending the stream. */
+  close(): void
+}
 /**
  * An ngrok listener.
  *
@@ -394,11 +808,25 @@ export declare function authtoken(authtoken: string): Promise<void>
 export declare class Listener {
   /** The URL that this listener backs. */
   url(): string | null
+  /**
+   * The URLs of this listener and of those started along with it, such as when
+   * {@link forward} is given several `schemes`.
+   */
+  urls(): Array<string>
+  /**
+   * This listener and those started along with it, such as when {@link forward} is given
+   * several `schemes`, as separate listeners. The {@link forward}, {@link join}, {@link close}
+   * and {@link drain} methods of this listener apply to all of them.
+   */
+  listeners(): Array<Listener>
   /** The protocol of the endpoint that this listener backs. */
   proto(): string | null
   /** The labels this listener was started with. */
   labels(): Record<string, string>
-  /** Returns a listener's unique ID. */
+  /**
+   * Returns a listener's unique ID.
+   * The ID changes if the listener is recreated by {@link SessionBuilder.restartListeners}.
+   */
   id(): string
   /**
    * Returns a human-readable string presented in the ngrok dashboard
@@ -430,6 +858,14 @@ export declare class Listener {
    * listener's ID.
    */
   close(): Promise<void>
+  /**
+   * Stop accepting new connections, then close the listener once the active
+   * forwarded connections have finished, or the timeout has passed.
+   * Without a timeout this waits for as long as any connection stays open,
+   * so long-lived connections such as websockets can keep it from resolving.
+   * Resolves to the number of connections which were cut off by the timeout.
+   */
+  drain(options?: DrainOptions | undefined | null): Promise<number>
 }
 /**
  *r" An ngrok listener backing an HTTP endpoint.
@@ -557,6 +993,27 @@ export declare class HttpListenerBuilder {
   metadata(metadata: string): this
   /** Whether to disable certificate verification for this listener. */
   verifyUpstreamTls(verifyUpstreamTls: boolean): this
+  /**
+   * Create an independent copy of this builder and its settings, such as for
+   * deriving listeners which differ only in a few settings.
+   * Changes to the copy do not affect this builder, and vice versa.
+   */
+  clone(): HttpListenerBuilder
+  /**
+   * The settings made on this builder so far, in the form of a {@link Config}.
+   *
+   * Secrets are returned in plaintext so the config can be passed back to
+   * {@link fromConfig}: the basic auth passwords, the OAuth and OIDC client secrets,
+   * the webhook verification secret, and the TLS termination key. Take care not to
+   * log or persist the result as-is.
+   */
+  toConfig(): Config
+  /**
+   * Apply the listener settings of a {@link Config}, such as one returned by
+   * {@link toConfig}, on top of the settings already made on this builder.
+   * Session settings such as the authtoken are ignored.
+   */
+  fromConfig(config: Config): this
   /** Begin listening for new connections on this listener. */
   listen(bind?: boolean | undefined | null): Promise<Listener>
   /**
@@ -597,7 +1054,15 @@ export declare class HttpListenerBuilder {
    */
   forwardsTo(forwardsTo: string): this
   policy(policy: string): this
-  trafficPolicy(trafficPolicy: string): this
+  /** The traffic policy for this listener, as a JSON or YAML string, or a {@link TrafficPolicy}. */
+  trafficPolicy(trafficPolicy: string | TrafficPolicy): this
+  /**
+   * Read the traffic policy for this listener from a JSON or YAML file.
+   * The policy is checked for unknown phases, and for missing required fields of the
+   * common action types, before listening. Errors include the line and column of the
+   * problem. Other action types are left to the ngrok service to check.
+   */
+  trafficPolicyFile(path: string): this
   /**
    * Sets the ingress configuration for this endpoint.
    * Valid values: "public", "internal", "kubernetes"
@@ -615,6 +1080,27 @@ export declare class TcpListenerBuilder {
   metadata(metadata: string): this
   /** Whether to disable certificate verification for this listener. */
   verifyUpstreamTls(verifyUpstreamTls: boolean): this
+  /**
+   * Create an independent copy of this builder and its settings, such as for
+   * deriving listeners which differ only in a few settings.
+   * Changes to the copy do not affect this builder, and vice versa.
+   */
+  clone(): TcpListenerBuilder
+  /**
+   * The settings made on this builder so far, in the form of a {@link Config}.
+   *
+   * Secrets are returned in plaintext so the config can be passed back to
+   * {@link fromConfig}: the basic auth passwords, the OAuth and OIDC client secrets,
+   * the webhook verification secret, and the TLS termination key. Take care not to
+   * log or persist the result as-is.
+   */
+  toConfig(): Config
+  /**
+   * Apply the listener settings of a {@link Config}, such as one returned by
+   * {@link toConfig}, on top of the settings already made on this builder.
+   * Session settings such as the authtoken are ignored.
+   */
+  fromConfig(config: Config): this
   /** Begin listening for new connections on this listener. */
   listen(bind?: boolean | undefined | null): Promise<Listener>
   /**
@@ -655,7 +1141,15 @@ export declare class TcpListenerBuilder {
    */
   forwardsTo(forwardsTo: string): this
   policy(policy: string): this
-  trafficPolicy(trafficPolicy: string): this
+  /** The traffic policy for this listener, as a JSON or YAML string, or a {@link TrafficPolicy}. */
+  trafficPolicy(trafficPolicy: string | TrafficPolicy): this
+  /**
+   * Read the traffic policy for this listener from a JSON or YAML file.
+   * The policy is checked for unknown phases, and for missing required fields of the
+   * common action types, before listening. Errors include the line and column of the
+   * problem. Other action types are left to the ngrok service to check.
+   */
+  trafficPolicyFile(path: string): this
   /**
    * Sets the ingress configuration for this endpoint.
    * Valid values: "public", "internal", "kubernetes"
@@ -682,6 +1176,27 @@ export declare class TlsListenerBuilder {
   metadata(metadata: string): this
   /** Whether to disable certificate verification for this listener. */
   verifyUpstreamTls(verifyUpstreamTls: boolean): this
+  /**
+   * Create an independent copy of this builder and its settings, such as for
+   * deriving listeners which differ only in a few settings.
+   * Changes to the copy do not affect this builder, and vice versa.
+   */
+  clone(): TlsListenerBuilder
+  /**
+   * The settings made on this builder so far, in the form of a {@link Config}.
+   *
+   * Secrets are returned in plaintext so the config can be passed back to
+   * {@link fromConfig}: the basic auth passwords, the OAuth and OIDC client secrets,
+   * the webhook verification secret, and the TLS termination key. Take care not to
+   * log or persist the result as-is.
+   */
+  toConfig(): Config
+  /**
+   * Apply the listener settings of a {@link Config}, such as one returned by
+   * {@link toConfig}, on top of the settings already made on this builder.
+   * Session settings such as the authtoken are ignored.
+   */
+  fromConfig(config: Config): this
   /** Begin listening for new connections on this listener. */
   listen(bind?: boolean | undefined | null): Promise<Listener>
   /**
@@ -722,7 +1237,15 @@ export declare class TlsListenerBuilder {
    */
   forwardsTo(forwardsTo: string): this
   policy(policy: string): this
-  trafficPolicy(trafficPolicy: string): this
+  /** The traffic policy for this listener, as a JSON or YAML string, or a {@link TrafficPolicy}. */
+  trafficPolicy(trafficPolicy: string | TrafficPolicy): this
+  /**
+   * Read the traffic policy for this listener from a JSON or YAML file.
+   * The policy is checked for unknown phases, and for missing required fields of the
+   * common action types, before listening. Errors include the line and column of the
+   * problem. Other action types are left to the ngrok service to check.
+   */
+  trafficPolicyFile(path: string): this
   /**
    * Sets the ingress configuration for this endpoint.
    * Valid values: "public", "internal", "kubernetes"
@@ -764,6 +1287,27 @@ export declare class LabeledListenerBuilder {
   metadata(metadata: string): this
   /** Whether to disable certificate verification for this listener. */
   verifyUpstreamTls(verifyUpstreamTls: boolean): this
+  /**
+   * Create an independent copy of this builder and its settings, such as for
+   * deriving listeners which differ only in a few settings.
+   * Changes to the copy do not affect this builder, and vice versa.
+   */
+  clone(): LabeledListenerBuilder
+  /**
+   * The settings made on this builder so far, in the form of a {@link Config}.
+   *
+   * Secrets are returned in plaintext so the config can be passed back to
+   * {@link fromConfig}: the basic auth passwords, the OAuth and OIDC client secrets,
+   * the webhook verification secret, and the TLS termination key. Take care not to
+   * log or persist the result as-is.
+   */
+  toConfig(): Config
+  /**
+   * Apply the listener settings of a {@link Config}, such as one returned by
+   * {@link toConfig}, on top of the settings already made on this builder.
+   * Session settings such as the authtoken are ignored.
+   */
+  fromConfig(config: Config): this
   /** Begin listening for new connections on this listener. */
   listen(bind?: boolean | undefined | null): Promise<Listener>
   /**
@@ -811,6 +1355,34 @@ export declare class SessionBuilder {
    * NGROK_AUTHTOKEN environment variable.
    */
   authtokenFromEnv(): this
+  /**
+   * Shortcut for calling [SessionBuilder::authtoken] with the contents of the
+   * file at the given path, such as a mounted Kubernetes secret.
+   */
+  authtokenFromFile(path: string): this
+  /**
+   * Shortcut for calling [SessionBuilder::authtoken] with the authtoken saved in the
+   * ngrok agent config file by `ngrok config add-authtoken`. The file is looked for in
+   * its default locations, unless a path is given.
+   */
+  authtokenFromAgentConfig(path?: string | undefined | null): this
+  /**
+   * Configures the session to authenticate with the authtoken stored for the
+   * named account by {@link authtoken}, rather than the default one. Connecting
+   * fails if no authtoken is stored for the account, or if an authtoken is also
+   * configured on this builder, such as with {@link authtokenFromEnv}.
+   */
+  account(name: string): this
+  /**
+   * Configures an async function which provides the authtoken, such as one fetched
+   * from a secrets vault. It is called when connecting, and again once the session
+   * is disconnected, but not on each retry while reconnecting. When the token has been
+   * rotated, the session is reconnected with the new token and its listeners are
+   * recreated, as with {@link restartListeners}.
+   *
+   * The provided token takes precedence over any other authtoken configured.
+   */
+  authtokenProvider(provider: () => Promise<string>): this
   /**
    * Add client type and version information for a client application.
    *
@@ -863,6 +1435,19 @@ export declare class SessionBuilder {
    * [server_addr parameter in the ngrok docs]: https://ngrok.com/docs/ngrok-agent/config#server_addr
    */
   serverAddr(addr: string): this
+  /**
+   * Configures an ordered list of network addresses to dial to connect to the
+   * ngrok service, for failover between self-hosted or multiple agent ingresses.
+   * On connect, and on every reconnect, each address is tried until one succeeds.
+   *
+   * The strategy is one of "ordered", which tries the addresses in the order given,
+   * or "latency", which measures the TCP connect time to each address and tries
+   * the fastest first. Defaults to "ordered".
+   *
+   * The address in use is available from {@link Session.serverAddr}, and is passed
+   * to the connection and disconnection handlers.
+   */
+  serverAddrs(addrs: Array<string>, strategy?: "ordered" | "latency"): this
   /**
    * Sets the file path to a default certificate in PEM format to validate ngrok Session TLS connections.
    * Setting to "trusted" is the default, using the ngrok CA certificate.
@@ -886,15 +1471,35 @@ export declare class SessionBuilder {
    * [root_cas parameter in the ngrok docs]: https://ngrok.com/docs/ngrok-agent/config#root_cas
    */
   caCert(certBytes: Uint8Array): this
+  /**
+   * Pins the certificate of the ngrok service, for deployments which should not
+   * rely on certificate authorities alone. The session fails to connect unless the
+   * certificate chain presented matches one of the pins, after being validated
+   * against the root CAs as usual.
+   *
+   * Each pin is either the SHA-256 hash of a certificate's public key, in the form
+   * "sha256/<base64>", which may pin any certificate in the chain, or the SHA-256
+   * fingerprint of the leaf certificate in hex, colons between the bytes allowed.
+   * The backup pins, such as those of a key which is not in use yet, are also
+   * accepted, with a warning that the primary pins may need updating.
+   */
+  serverCertPins(pins: Array<string>, backupPins?: Array<string> | undefined | null): this
   /**
    * Configures a function which is called to after a disconnection to the
    * ngrok service. In the event of network disruptions, it will be called each time
-   * the session reconnects. The handler is given the address that will be used to
-   * connect the session to, e.g. "example.com:443", and the message from the error
+   * the session reconnects. The handler is given the address the session was
+   * connected to, e.g. "example.com:443", and the message from the error
    * that occurred. Returning true from the handler will cause the session to
    * reconnect, returning false will cause the Session to throw an uncaught error.
    */
   handleDisconnection(handler: (addr: string, error: string) => boolean): this
+  /**
+   * Configures a function which is called each time a connection to the ngrok
+   * service is attempted. The handler is given the status, either "connected" or
+   * "closed", the message from the error that occurred, if any, and the server
+   * address that was dialed, e.g. "example.com:443".
+   */
+  handleConnection(handler: (status: string, error: string | undefined, addr: string) => void): this
   /**
    * Configures a function which is called when the ngrok service requests that
    * this [Session] stops. Your application may choose to interpret this callback
@@ -922,6 +1527,16 @@ export declare class SessionBuilder {
    * callback, it will also cause the operation to time out.
    */
   handleRestartCommand(handler: () => void): this
+  /**
+   * Handle restart commands from the ngrok service by reconnecting the [Session]
+   * and recreating its listeners with the configuration they were built with,
+   * resuming any forwarding. Existing Listener objects refer to the recreated
+   * listeners afterwards, whose new ids are reported by "listener-restarted"
+   * session events.
+   *
+   * A handler configured with {@link handleRestartCommand} is still called.
+   */
+  restartListeners(): this
   /**
    * Configures a function which is called when the ngrok service requests
    * that this [Session] updates. Your application may choose to interpret
@@ -959,8 +1574,29 @@ export declare class Session {
   tcpEndpoint(): TcpListenerBuilder
   /** Start building a listener backing a TLS endpoint. */
   tlsEndpoint(): TlsListenerBuilder
+  /**
+   * Start building a listener for the endpoint with the given url, inferring the kind of
+   * listener from its scheme, and the domain or TCP address from the rest of it, e.g.
+   * "https://example.ngrok.app", "tls://example.ngrok.app" or "tcp://1.tcp.ngrok.io:12345".
+   */
+  endpoint(url: string): HttpListenerBuilder | TcpListenerBuilder | TlsListenerBuilder
   /** Start building a labeled listener. */
   labeledListener(): LabeledListenerBuilder
+  /** The server address this session is currently connected to, e.g. "connect.ngrok-agent.com:443". */
+  serverAddr(): string | null
+  /**
+   * Subscribe to this session's events, such as reconnects, heartbeats, commands
+   * from the ngrok service, and closed listeners. The returned object is an async
+   * iterator, yielding events in the order they occurred from the time of this call
+   * until the session is closed, stopped by the ngrok service, fails to reconnect,
+   * or is no longer referenced:
+   *
+   * for await (const event of session.events()) { console.log(event.type); }
+   *
+   * Command events are only sent for commands with a registered handler,
+   * e.g. via {@link SessionBuilder.handleStopCommand}.
+   */
+  events(): SessionEvents
   /** Retrieve a list of this session's non-closed listeners, in no particular order. */
   listeners(): Promise<Array<Listener>>
   /** Close a listener with the given ID. */
@@ -975,6 +1611,49 @@ export declare class UpdateRequest {
   /** Whether or not updating to the same major version is sufficient. */
   permitMajorVersion: boolean
 }
+/**
+ * A traffic policy, built from typed rules and presets for common needs.
+ * Pass it to {@link HttpListenerBuilder.trafficPolicy} or the `traffic_policy`
+ * option of {@link forward}, or call `toString()` for the policy document.
+ * See [Traffic Policy] in the ngrok docs for additional details.
+ *
+ * [Traffic Policy]: https://ngrok.com/docs/traffic-policy/
+ *
+ * @group Listener Builders
+ */
+export declare class TrafficPolicy {
+  /** Create an empty traffic policy. */
+  constructor()
+  /** Add a rule for HTTP requests, before they are forwarded to the listener. */
+  onHttpRequest(rule: TrafficPolicyRule): this
+  /** Add a rule for HTTP responses, before they are returned to the client. */
+  onHttpResponse(rule: TrafficPolicyRule): this
+  /** Add a rule for TCP connections, as they are established. */
+  onTcpConnect(rule: TrafficPolicyRule): this
+  /**
+   * Only allow connections from the `allow` CIDR ranges, and reject those from the `deny` ranges.
+   * Applies to HTTP requests, or to the given phase, e.g. "on_tcp_connect" for TCP and TLS endpoints.
+   */
+  restrictIps(allow?: Array<string> | undefined | null, deny?: Array<string> | undefined | null, phase?: string | undefined | null): this
+  /**
+   * Limit each client IP to `capacity` HTTP requests per `rate`, e.g. `rateLimit(100, "60s")`.
+   * Requests can be bucketed by other keys instead, e.g. ["req.headers['x-api-key']"].
+   */
+  rateLimit(capacity: number, rate: string, bucketKey?: Array<string> | undefined | null): this
+  /**
+   * Require HTTP requests to carry a bearer JWT in the Authorization header, issued by
+   * `issuer` for `audience`, and signed by a key from the JSON Web Key Set at `jwksUrl`.
+   */
+  validateJwt(issuer: string, audience: string, jwksUrl: string): this
+  /**
+   * Respond to HTTP requests from the edge instead of forwarding them, for example
+   * to serve a maintenance page. Only requests matching all of the expressions are
+   * answered, or every request if there are none.
+   */
+  customResponse(statusCode: number, content?: string | undefined | null, headers?: Record<string, string> | undefined | null, expressions?: Array<string> | undefined | null): this
+  /** The policy document as a JSON string, as accepted by `trafficPolicy`. */
+  toString(): string
+}
 /**
  * Get a listenable ngrok listener, suitable for passing to net.Server.listen().
  * Uses the NGROK_AUTHTOKEN environment variable to authenticate.
@@ -990,3 +1669,7 @@ export function listen(server: import("net").Server, listener?: Listener): Liste
  * Optionally set the logging level to one of ERROR, WARN, INFO, DEBUG, or TRACE.
  */
 export function consoleLog(level?: String): void;
+/**
+ * Session events are consumed as an async iterator, e.g. `for await (const event of session.events())`.
+ */
+export interface SessionEvents extends AsyncIterableIterator<SessionEvent> {}
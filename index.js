@@ -310,12 +310,14 @@ if (!nativeBinding) {
   throw new Error(`Failed to load native binding`)
 }
 
-const { connect, forward, disconnect, kill, Listener, listeners, getListener, getListenerByUrl, HttpListenerBuilder, TcpListenerBuilder, TlsListenerBuilder, LabeledListenerBuilder, loggingCallback, authtoken, SessionBuilder, Session, UpdateRequest } = nativeBinding
+const { configFromEnv, connect, forward, disconnect, kill, SessionEvents, Listener, listeners, getListener, getListenerByUrl, HttpListenerBuilder, TcpListenerBuilder, TlsListenerBuilder, LabeledListenerBuilder, logToStderr, logToFile, removeLogSink, loggingCallback, structuredLoggingCallback, setLogLevel, evaluateTrafficPolicy, setRedaction, authtoken, authtokenAccounts, removeAuthtoken, SessionBuilder, Session, UpdateRequest, shutdownOnSignals, exportTelemetry, stopTelemetry, TrafficPolicy } = nativeBinding
 
+module.exports.configFromEnv = configFromEnv
 module.exports.connect = connect
 module.exports.forward = forward
 module.exports.disconnect = disconnect
 module.exports.kill = kill
+module.exports.SessionEvents = SessionEvents
 module.exports.Listener = Listener
 module.exports.listeners = listeners
 module.exports.getListener = getListener
@@ -324,11 +326,24 @@ module.exports.HttpListenerBuilder = HttpListenerBuilder
 module.exports.TcpListenerBuilder = TcpListenerBuilder
 module.exports.TlsListenerBuilder = TlsListenerBuilder
 module.exports.LabeledListenerBuilder = LabeledListenerBuilder
+module.exports.logToStderr = logToStderr
+module.exports.logToFile = logToFile
+module.exports.removeLogSink = removeLogSink
 module.exports.loggingCallback = loggingCallback
+module.exports.structuredLoggingCallback = structuredLoggingCallback
+module.exports.setLogLevel = setLogLevel
+module.exports.evaluateTrafficPolicy = evaluateTrafficPolicy
+module.exports.setRedaction = setRedaction
 module.exports.authtoken = authtoken
+module.exports.authtokenAccounts = authtokenAccounts
+module.exports.removeAuthtoken = removeAuthtoken
 module.exports.SessionBuilder = SessionBuilder
 module.exports.Session = Session
 module.exports.UpdateRequest = UpdateRequest
+module.exports.shutdownOnSignals = shutdownOnSignals
+module.exports.exportTelemetry = exportTelemetry
+module.exports.stopTelemetry = stopTelemetry
+module.exports.TrafficPolicy = TrafficPolicy
 //
 // javascript trailer
 //
@@ -358,6 +373,33 @@ TcpListenerBuilder.prototype.listenAndServe = listenAndServe;
 TlsListenerBuilder.prototype.listenAndServe = listenAndServe;
 LabeledListenerBuilder.prototype.listenAndServe = listenAndServe;
 
+// wrap fromConfig to accept the same config shapes as forward()
+HttpListenerBuilder.prototype._fromConfig = HttpListenerBuilder.prototype.fromConfig;
+TcpListenerBuilder.prototype._fromConfig = TcpListenerBuilder.prototype.fromConfig;
+TlsListenerBuilder.prototype._fromConfig = TlsListenerBuilder.prototype.fromConfig;
+LabeledListenerBuilder.prototype._fromConfig = LabeledListenerBuilder.prototype.fromConfig;
+
+HttpListenerBuilder.prototype.fromConfig = ngrokFromConfig;
+TcpListenerBuilder.prototype.fromConfig = ngrokFromConfig;
+TlsListenerBuilder.prototype.fromConfig = ngrokFromConfig;
+LabeledListenerBuilder.prototype.fromConfig = ngrokFromConfig;
+
+// wrap trafficPolicy to accept TrafficPolicy objects as well as strings
+HttpListenerBuilder.prototype._trafficPolicy = HttpListenerBuilder.prototype.trafficPolicy;
+TcpListenerBuilder.prototype._trafficPolicy = TcpListenerBuilder.prototype.trafficPolicy;
+TlsListenerBuilder.prototype._trafficPolicy = TlsListenerBuilder.prototype.trafficPolicy;
+
+HttpListenerBuilder.prototype.trafficPolicy = ngrokTrafficPolicy;
+TcpListenerBuilder.prototype.trafficPolicy = ngrokTrafficPolicy;
+TlsListenerBuilder.prototype.trafficPolicy = ngrokTrafficPolicy;
+
+// make session events an async iterator, for use with 'for await'
+SessionEvents.prototype[Symbol.asyncIterator] = function () {
+  return this;
+};
+SessionEvents.prototype.next = sessionEventsNext;
+SessionEvents.prototype.return = sessionEventsReturn;
+
 // Wrap session connect to fill in exception's errorCode
 async function ngrokSessionConnect() {
   try {
@@ -393,6 +435,18 @@ async function listenAndServe(server) {
   return listener;
 }
 
+// Async iterator protocol for session events, done once the session closes
+async function sessionEventsNext() {
+  const value = await this.recv();
+  return value == null ? { done: true, value: undefined } : { done: false, value: value };
+}
+
+// Async iterator protocol for session events, called on early exit from 'for await'
+async function sessionEventsReturn() {
+  this.close();
+  return { done: true, value: undefined };
+}
+
 function populateErrorCode(err) {
   if (err.message) {
     const regex = /error_code: (ERR_NGROK_\d+)$/;
@@ -560,10 +614,23 @@ async function ngrokLinkPipe(listener, server) {
 
 // protect against multiple calls, for instance from npm
 var sigHandlerRan = false;
+// whether shutdownOnSignals has taken over signal handling
+var shutdownManaged = false;
+
+// webserver sockets to close when shutdownOnSignals shuts down
+var managedSockets = [];
+
+// the signal handlers registered before shutdownOnSignals, to be removed when it is called
+var cleanupHandlers = [];
 
 function registerCleanup(listener, socket) {
+  // the shutdown manager closes every listener, and a js handler would replace it
+  if (shutdownManaged) {
+    if (socket) managedSockets.push(socket);
+    return;
+  }
   for (const signal of ["SIGINT", "SIGTERM"]) {
-    process.on(signal, function () {
+    const handler = function () {
       if (process.listenerCount(signal) > 1) {
         // user has registered a handler, abort this one
         return;
@@ -580,9 +647,12 @@ function registerCleanup(listener, socket) {
       }
       // close webserver's socket
       if (socket) socket.close();
-      // unregister any logging callback
+      // unregister any logging callbacks
       loggingCallback();
-    });
+      structuredLoggingCallback();
+    };
+    process.on(signal, handler);
+    cleanupHandlers.push({ signal, handler, socket });
   }
 }
 
@@ -613,6 +683,43 @@ async function ngrokForward(config) {
   // Convert addr to string to allow for numeric port numbers
   const addr = config["addr"];
   if (Number.isInteger(addr)) config["addr"] = "localhost:" + String(config["addr"]);
+  normalizeConfig(config);
+  // break out the logging callback function to meet what napi-rs expects
+  var on_log_event;
+  if (config["onLogEvent"]) {
+    const onLogEvent = config.onLogEvent;
+    on_log_event = (level, target, message) => {
+      onLogEvent(`${level} ${target} - ${message}`);
+    };
+    config["onLogEvent"] = true;
+  }
+  // break out the status change callback functions to what napi-rs expects
+  var on_connection, on_disconnection;
+  if (config["onStatusChange"]) {
+    const onStatusChange = config.onStatusChange;
+    on_connection = (status, err, addr) => {
+      onStatusChange(status);
+    };
+    on_disconnection = (addr, err) => {
+      onStatusChange("closed");
+    };
+    config["onStatusChange"] = true;
+  }
+  // call into rust
+  try {
+    return await _forward(config, on_log_event, on_connection, on_disconnection);
+  } catch (err) {
+    populateErrorCode(err);
+    throw err;
+  }
+}
+
+// convert the config values to what napi-rs expects
+function normalizeConfig(config) {
+  // serialize a TrafficPolicy object to its policy document
+  if (config["traffic_policy"] instanceof TrafficPolicy) {
+    config["traffic_policy"] = config["traffic_policy"].toString();
+  }
   // convert scalar values to arrays to meet what napi-rs expects
   [
     "allow_user_agent",
@@ -622,6 +729,7 @@ async function ngrokForward(config) {
     "ip_restriction.allow_cidrs",
     "ip_restriction.deny_cidrs",
     "labels",
+    "mutual_tls_cas",
     "oauth.allow_domains",
     "oauth.allow_emails",
     "oauth.scopes",
@@ -659,34 +767,24 @@ async function ngrokForward(config) {
   ].forEach((key) => {
     undot(config, key);
   });
-  // break out the logging callback function to meet what napi-rs expects
-  var on_log_event;
-  if (config["onLogEvent"]) {
-    const onLogEvent = config.onLogEvent;
-    on_log_event = (level, target, message) => {
-      onLogEvent(`${level} ${target} - ${message}`);
-    };
-    config["onLogEvent"] = true;
-  }
-  // break out the status change callback functions to what napi-rs expects
-  var on_connection, on_disconnection;
-  if (config["onStatusChange"]) {
-    const onStatusChange = config.onStatusChange;
-    on_connection = (status, err) => {
-      onStatusChange(status);
-    };
-    on_disconnection = (addr, err) => {
-      onStatusChange("closed");
-    };
-    config["onStatusChange"] = true;
-  }
-  // call into rust
-  try {
-    return await _forward(config, on_log_event, on_connection, on_disconnection);
-  } catch (err) {
-    populateErrorCode(err);
-    throw err;
-  }
+}
+
+// set the traffic policy from a string or TrafficPolicy object
+function ngrokTrafficPolicy(trafficPolicy) {
+  return this._trafficPolicy(String(trafficPolicy));
+}
+
+// evaluate a traffic policy string or TrafficPolicy object
+const _evaluateTrafficPolicy = evaluateTrafficPolicy;
+function ngrokEvaluateTrafficPolicy(policy, sample) {
+  return _evaluateTrafficPolicy(String(policy), sample);
+}
+
+// apply a config object on top of a listener builder's settings
+function ngrokFromConfig(config) {
+  config = Object.assign({}, config);
+  normalizeConfig(config);
+  return this._fromConfig(config);
 }
 
 function undot(config, dotKey) {
@@ -717,8 +815,34 @@ function vectorize(config, key) {
   }
 }
 
+// wrap shutdownOnSignals to run the app's cleanup, then exit unless told not to
+const _shutdownOnSignals = shutdownOnSignals;
+function ngrokShutdownOnSignals(options, cleanup) {
+  _shutdownOnSignals(options, async (signal) => {
+    // the listeners are closed by now, close the webservers' sockets
+    for (const socket of managedSockets.splice(0)) socket.close();
+    try {
+      if (cleanup) await cleanup(signal);
+    } catch (err) {
+      console.error(`Error during cleanup: ${err}`);
+    }
+    // exit as if killed by the signal
+    if (options == null || options.exit !== false) {
+      process.exit(signal == "SIGTERM" ? 143 : 130);
+    }
+  });
+  shutdownManaged = true;
+  // listeners created before now would close themselves and race the drain, so hand them over
+  for (const { signal, handler, socket } of cleanupHandlers.splice(0)) {
+    process.off(signal, handler);
+    if (socket && !managedSockets.includes(socket)) managedSockets.push(socket);
+  }
+}
+
 module.exports.connect = ngrokForward;
 module.exports.forward = ngrokForward;
 module.exports.consoleLog = consoleLog;
 module.exports.listen = ngrokListen;
 module.exports.listenable = listenable;
+module.exports.shutdownOnSignals = ngrokShutdownOnSignals;
+module.exports.evaluateTrafficPolicy = ngrokEvaluateTrafficPolicy;
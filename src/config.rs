@@ -1,11 +1,23 @@
+use std::env;
+
+use napi::bindgen_prelude::*;
 use napi_derive::napi;
 
+use crate::napi_err;
+
+pub(crate) const DEFAULT_ENV_PREFIX: &str = "NGROK_";
+pub(crate) const DEFAULT_ENV_DELIMITER: &str = ",";
+
 /// Configuration object to pass to ngrok.forward()
 ///
 /// @group Functions
 #[napi(object)]
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Config {
+    /// The name of the account whose authtoken, stored with {@link authtoken}, the
+    /// session authenticates with. Each account gets its own session.
+    /// It cannot be combined with `authtoken` or `authtoken_from_env`.
+    pub account: Option<String>,
     /// Port, network address, url, or named pipe. Defaults to 80.
     /// Examples: "80", "localhost:8080", "https://192.168.1.100:8443", "unix:/tmp/my.sock", "pipe://./my-pipe"
     #[napi(ts_type = "number|string")]
@@ -30,6 +42,14 @@ pub struct Config {
     /// NGROK_AUTHTOKEN environment variable.
     #[napi(js_name = "authtoken_from_env")]
     pub authtoken_from_env: Option<bool>,
+    /// Shortcut for calling [SessionBuilder::authtoken] with the contents of the file
+    /// at this path, such as a mounted Kubernetes secret.
+    #[napi(js_name = "authtoken_from_file")]
+    pub authtoken_from_file: Option<String>,
+    /// Shortcut for calling [SessionBuilder::authtoken] with the authtoken saved by
+    /// `ngrok config add-authtoken` in the default ngrok agent config file.
+    #[napi(js_name = "authtoken_from_agent_config")]
+    pub authtoken_from_agent_config: Option<bool>,
     /// Credentials for basic authentication, with username and password colon separated.
     #[napi(js_name = "basic_auth", ts_type = "string|Array<string>")]
     pub basic_auth: Option<Vec<String>>,
@@ -68,6 +88,11 @@ pub struct Config {
     /// Force a new session connection to be made.
     #[napi(js_name = "force_new_session")]
     pub force_new_session: Option<bool>,
+    /// Fill in the options not given here from NGROK_* environment variables, named after
+    /// the option in upper case, e.g. NGROK_DOMAIN or NGROK_TRAFFIC_POLICY.
+    /// Lists are comma separated. See {@link configFromEnv} for a different prefix or delimiter.
+    #[napi(js_name = "from_env")]
+    pub from_env: Option<bool>,
     /// Unused, will warn and be ignored
     #[napi(js_name = "host_header")]
     pub host_header: Option<String>,
@@ -121,11 +146,15 @@ pub struct Config {
     pub metadata: Option<String>,
     /// Certificates to use for client authentication at the ngrok edge.
     /// Only used if "proto" is "tls" or "http".
+    /// Each is a string, or the raw bytes of a Buffer or Uint8Array.
     /// See [Mutual TLS] in the ngrok docs for additional details.
     ///
     /// [Mutual TLS]: https://ngrok.com/docs/cloud-edge/modules/mutual-tls/
-    #[napi(js_name = "mutual_tls_cas", ts_type = "string|Array<string>")]
-    pub mutual_tls_cas: Option<Vec<String>>,
+    #[napi(
+        js_name = "mutual_tls_cas",
+        ts_type = "string|Uint8Array|Array<string|Uint8Array>"
+    )]
+    pub mutual_tls_cas: Option<Vec<Certificate>>,
     /// Unused, will warn and be ignored
     pub name: Option<String>,
 
@@ -211,6 +240,10 @@ pub struct Config {
     pub on_status_change: Option<bool>,
     /// DEPRECATED: use TrafficPolicy instead.
     pub policy: Option<String>,
+    /// Enable endpoint pooling for this listener.
+    /// Only used if proto is "http", "tcp", or "tls".
+    #[napi(js_name = "pooling_enabled")]
+    pub pooling_enabled: Option<bool>,
     /// The port for the listener to forward to.
     /// Only used if addr is not defined.
     pub port: Option<u32>,
@@ -264,7 +297,8 @@ pub struct Config {
     pub root_cas: Option<String>,
     /// The scheme that this edge should use.
     /// "HTTPS" or "HTTP", defaults to "HTTPS".
-    /// If multiple are given only the last one is used.
+    /// If multiple are given, {@link forward} starts a listener for each, grouped into the
+    /// one {@link Listener} it returns. Listener builders only use the last one.
     #[napi(ts_type = "string|Array<string>")]
     pub schemes: Option<Vec<String>>,
     /// Configures the TLS certificate used to connect to the ngrok service while
@@ -296,14 +330,30 @@ pub struct Config {
     /// [server_addr parameter in the ngrok docs]: https://ngrok.com/docs/ngrok-agent/config#server_addr
     #[napi(js_name = "server_addr")]
     pub server_addr: Option<String>,
+    /// Configures an ordered list of network addresses to dial to connect to the ngrok service,
+    /// tried on connect and on every reconnect until one succeeds. Overrides server_addr.
+    /// A single address is accepted as a string.
+    #[napi(js_name = "server_addrs")]
+    pub server_addrs: Option<Either<String, Vec<String>>>,
+    /// How to choose between the server_addrs, "ordered" or "latency", defaults to "ordered".
+    #[napi(js_name = "server_addr_strategy")]
+    pub server_addr_strategy: Option<String>,
     /// Unused, use domain instead, will warn and be ignored
     pub subdomain: Option<String>,
     /// Unused, will warn and be ignored
     #[napi(js_name = "terminate_at")]
     pub terminate_at: Option<String>,
-    /// The Traffic Policy to use for this endpoint.
-    #[napi(js_name = "traffic_policy")]
+    /// The Traffic Policy to use for this endpoint, as a JSON or YAML string, or a {@link TrafficPolicy}.
+    #[napi(js_name = "traffic_policy", ts_type = "string | TrafficPolicy")]
     pub traffic_policy: Option<String>,
+    /// A path to a JSON or YAML Traffic Policy file to use for this endpoint.
+    /// The file is checked locally before listening, reporting the line and column of any error.
+    #[napi(js_name = "traffic_policy_file")]
+    pub traffic_policy_file: Option<String>,
+    /// The URL of the endpoint, such as "https://example.ngrok.app", "tls://example.ngrok.app"
+    /// or "tcp://1.tcp.ngrok.io:12345". The proto, and the domain or remote_addr, are inferred
+    /// from it unless given separately.
+    pub url: Option<String>,
     /// Whether to disable certificate verification for this listener
     #[napi(js_name = "verify_upstream_tls")]
     pub verify_upstream_tls: Option<bool>,
@@ -330,3 +380,236 @@ pub struct Config {
     /// If not specified, the ngrok service will use its default binding configuration.
     pub binding: Option<String>,
 }
+
+/// Read a Config from environment variables named after its options in upper case, after
+/// the prefix, e.g. NGROK_DOMAIN, NGROK_TRAFFIC_POLICY or NGROK_BASIC_AUTH with the default
+/// "NGROK_" prefix. Lists such as NGROK_SCHEMES are split on the delimiter, "," by default.
+#[napi]
+pub fn config_from_env(prefix: Option<String>, delimiter: Option<String>) -> Result<Config> {
+    let mut config = Config::default();
+    config.fill_from_env(
+        prefix.as_deref().unwrap_or(DEFAULT_ENV_PREFIX),
+        delimiter.as_deref().unwrap_or(DEFAULT_ENV_DELIMITER),
+    )?;
+    Ok(config)
+}
+
+/// Set each field which is unset from its environment variable, if that is set.
+macro_rules! fill_from_env {
+    ($config:ident, $reader:ident, $($field:ident => $name:literal),* $(,)?) => {
+        $(
+            if $config.$field.is_none() {
+                $config.$field = $reader.read($name)?;
+            }
+        )*
+    };
+}
+
+impl Config {
+    /// Fill in the options which are not set from environment variables.
+    pub(crate) fn fill_from_env(&mut self, prefix: &str, delimiter: &str) -> Result<()> {
+        if delimiter.is_empty() {
+            return Err(napi_err("The environment list delimiter cannot be empty"));
+        }
+        let reader = EnvReader { prefix, delimiter };
+        let config = self;
+        fill_from_env!(config, reader,
+            account => "ACCOUNT",
+            addr => "ADDR",
+            app_protocol => "APP_PROTOCOL",
+            auth => "AUTH",
+            authtoken => "AUTHTOKEN",
+            authtoken_from_file => "AUTHTOKEN_FROM_FILE",
+            authtoken_from_agent_config => "AUTHTOKEN_FROM_AGENT_CONFIG",
+            basic_auth => "BASIC_AUTH",
+            bin_path => "BIN_PATH",
+            circuit_breaker => "CIRCUIT_BREAKER",
+            compression => "COMPRESSION",
+            config_path => "CONFIG_PATH",
+            crt => "CRT",
+            domain => "DOMAIN",
+            forwards_to => "FORWARDS_TO",
+            force_new_session => "FORCE_NEW_SESSION",
+            host_header => "HOST_HEADER",
+            host => "HOST",
+            hostname => "HOSTNAME",
+            inspect => "INSPECT",
+            allow_cidr => "IP_RESTRICTION_ALLOW_CIDRS",
+            deny_cidr => "IP_RESTRICTION_DENY_CIDRS",
+            allow_user_agent => "ALLOW_USER_AGENT",
+            deny_user_agent => "DENY_USER_AGENT",
+            key => "KEY",
+            labels => "LABELS",
+            metadata => "METADATA",
+            mutual_tls_cas => "MUTUAL_TLS_CAS",
+            name => "NAME",
+            oauth_allow_domains => "OAUTH_ALLOW_DOMAINS",
+            oauth_allow_emails => "OAUTH_ALLOW_EMAILS",
+            oauth_scopes => "OAUTH_SCOPES",
+            oauth_provider => "OAUTH_PROVIDER",
+            oauth_client_id => "OAUTH_CLIENT_ID",
+            oauth_client_secret => "OAUTH_CLIENT_SECRET",
+            oidc_client_id => "OIDC_CLIENT_ID",
+            oidc_client_secret => "OIDC_CLIENT_SECRET",
+            oidc_scopes => "OIDC_SCOPES",
+            oidc_issuer_url => "OIDC_ISSUER_URL",
+            oidc_allow_domains => "OIDC_ALLOW_DOMAINS",
+            oidc_allow_emails => "OIDC_ALLOW_EMAILS",
+            policy => "POLICY",
+            pooling_enabled => "POOLING_ENABLED",
+            port => "PORT",
+            proto => "PROTO",
+            proxy_proto => "PROXY_PROTO",
+            request_header_add => "REQUEST_HEADER_ADD",
+            request_header_remove => "REQUEST_HEADER_REMOVE",
+            response_header_add => "RESPONSE_HEADER_ADD",
+            response_header_remove => "RESPONSE_HEADER_REMOVE",
+            region => "REGION",
+            remote_addr => "REMOTE_ADDR",
+            root_cas => "ROOT_CAS",
+            schemes => "SCHEMES",
+            session_ca_cert => "SESSION_CA_CERT",
+            session_metadata => "SESSION_METADATA",
+            server_addr => "SERVER_ADDR",
+            server_addrs => "SERVER_ADDRS",
+            server_addr_strategy => "SERVER_ADDR_STRATEGY",
+            subdomain => "SUBDOMAIN",
+            terminate_at => "TERMINATE_AT",
+            traffic_policy => "TRAFFIC_POLICY",
+            traffic_policy_file => "TRAFFIC_POLICY_FILE",
+            url => "URL",
+            verify_upstream_tls => "VERIFY_UPSTREAM_TLS",
+            verify_webhook_provider => "VERIFY_WEBHOOK_PROVIDER",
+            verify_webhook_secret => "VERIFY_WEBHOOK_SECRET",
+            web_addr => "WEB_ADDR",
+            websocket_tcp_converter => "WEBSOCKET_TCP_CONVERTER",
+            binding => "BINDING",
+        );
+        Ok(())
+    }
+}
+
+/// Reads the environment variables for Config options.
+struct EnvReader<'a> {
+    prefix: &'a str,
+    delimiter: &'a str,
+}
+
+impl EnvReader<'_> {
+    /// The value of the variable for the option, if it is set and not empty.
+    fn read<T: EnvValue>(&self, name: &str) -> Result<Option<T>> {
+        let var = format!("{}{name}", self.prefix);
+        match env::var(&var) {
+            Ok(value) if !value.trim().is_empty() => T::parse(value.trim(), self.delimiter)
+                .map(Some)
+                .map_err(|expected| {
+                    napi_err(format!(
+                        "Invalid value for {var}: {value:?}, expected {expected}"
+                    ))
+                }),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// A Config option type which can be parsed from an environment variable.
+trait EnvValue: Sized {
+    /// Parse the value, or describe what was expected.
+    fn parse(value: &str, delimiter: &str) -> core::result::Result<Self, &'static str>;
+}
+
+impl EnvValue for String {
+    fn parse(value: &str, _delimiter: &str) -> core::result::Result<Self, &'static str> {
+        Ok(value.to_string())
+    }
+}
+
+impl EnvValue for bool {
+    fn parse(value: &str, _delimiter: &str) -> core::result::Result<Self, &'static str> {
+        match value.to_lowercase().as_str() {
+            "true" | "1" | "yes" => Ok(true),
+            "false" | "0" | "no" => Ok(false),
+            _ => Err("true or false"),
+        }
+    }
+}
+
+impl EnvValue for u32 {
+    fn parse(value: &str, _delimiter: &str) -> core::result::Result<Self, &'static str> {
+        value.parse().map_err(|_| "a whole number")
+    }
+}
+
+impl EnvValue for f64 {
+    fn parse(value: &str, _delimiter: &str) -> core::result::Result<Self, &'static str> {
+        value.parse().map_err(|_| "a number")
+    }
+}
+
+impl EnvValue for Either<String, Vec<String>> {
+    fn parse(value: &str, delimiter: &str) -> core::result::Result<Self, &'static str> {
+        Vec::parse(value, delimiter).map(Either::B)
+    }
+}
+
+impl EnvValue for Vec<String> {
+    fn parse(value: &str, delimiter: &str) -> core::result::Result<Self, &'static str> {
+        Ok(value
+            .split(delimiter)
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(String::from)
+            .collect())
+    }
+}
+
+impl EnvValue for Vec<Certificate> {
+    fn parse(value: &str, delimiter: &str) -> core::result::Result<Self, &'static str> {
+        Vec::<String>::parse(value, delimiter)
+            .map(|certs| certs.into_iter().map(Certificate::from).collect())
+    }
+}
+
+/// A certificate given as a string, or as the raw bytes of a Buffer or Uint8Array.
+/// The bytes are kept as-is, so binary (DER) certificates survive being recorded in a Config.
+#[derive(Clone, Debug, Default)]
+pub struct Certificate(pub(crate) Vec<u8>);
+
+impl From<String> for Certificate {
+    fn from(cert: String) -> Self {
+        Certificate(cert.into_bytes())
+    }
+}
+
+impl TypeName for Certificate {
+    fn type_name() -> &'static str {
+        "string | Uint8Array"
+    }
+
+    fn value_type() -> ValueType {
+        ValueType::Unknown
+    }
+}
+
+impl ValidateNapiValue for Certificate {}
+
+impl FromNapiValue for Certificate {
+    unsafe fn from_napi_value(env: sys::napi_env, napi_val: sys::napi_value) -> Result<Self> {
+        if type_of!(env, napi_val)? == ValueType::String {
+            return String::from_napi_value(env, napi_val).map(Certificate::from);
+        }
+        let bytes = Uint8Array::from_napi_value(env, napi_val)
+            .map_err(|_| napi_err("Expected a certificate string, Buffer or Uint8Array"))?;
+        Ok(Certificate(bytes.to_vec()))
+    }
+}
+
+impl ToNapiValue for Certificate {
+    unsafe fn to_napi_value(env: sys::napi_env, val: Self) -> Result<sys::napi_value> {
+        // text, such as PEM, is handed back the way it is usually given
+        match String::from_utf8(val.0) {
+            Ok(cert) => String::to_napi_value(env, cert),
+            Err(e) => Buffer::to_napi_value(env, e.into_bytes().into()),
+        }
+    }
+}
@@ -1,3 +1,8 @@
+use std::{
+    collections::HashMap,
+    time::Duration,
+};
+
 use lazy_static::lazy_static;
 use napi::{
     bindgen_prelude::*,
@@ -6,14 +11,26 @@ use napi::{
 use napi_derive::napi;
 use tokio::sync::Mutex;
 use tracing::warn;
+use url::Url;
 
 use crate::{
-    config::Config,
+    config::{
+        Config,
+        DEFAULT_ENV_DELIMITER,
+        DEFAULT_ENV_PREFIX,
+    },
     listener::{
         self,
+        CloseResult,
         Listener,
         TCP_PREFIX,
     },
+    listener_builder::{
+        HttpListenerBuilder,
+        LabeledListenerBuilder,
+        TcpListenerBuilder,
+        TlsListenerBuilder,
+    },
     logging::logging_callback,
     napi_err,
     session::{
@@ -23,8 +40,9 @@ use crate::{
 };
 
 lazy_static! {
-    // Save a user-facing NgrokSession to use for connect use cases
-    pub(crate) static ref SESSION: Mutex<Option<Session>> = Mutex::new(None);
+    // Save a user-facing NgrokSession per account to use for connect use cases,
+    // the default account's under None
+    pub(crate) static ref SESSION: Mutex<HashMap<Option<String>, Session>> = Mutex::new(HashMap::new());
 }
 
 /// Single string configuration
@@ -77,7 +95,7 @@ macro_rules! plumb_vec {
     ($builder:tt, $config:tt, $name:tt, $config_name:tt, vecu8) => {
         if let Some(ref $name) = $config.$config_name {
             for val in $name {
-                $builder.$name(Uint8Array::new(val.as_bytes().to_vec()));
+                $builder.$name(Uint8Array::new(val.0.clone()));
             }
         }
     };
@@ -105,6 +123,9 @@ macro_rules! config_common {
         plumb!($builder, $config, traffic_policy);
         // policy is in the process of being deprecated. for now, we just remap it to traffic_policy
         plumb!($builder, $config, traffic_policy, policy);
+        if let Some(ref path) = $config.traffic_policy_file {
+            $builder.traffic_policy_file(path.clone())?;
+        }
         plumb!($builder, $config, binding);
     };
 }
@@ -148,12 +169,21 @@ pub fn forward(
     if on_log_event.is_some() {
         logging_callback(env, on_log_event, None)?;
     }
+    if cfg.from_env.unwrap_or(false) {
+        cfg.fill_from_env(DEFAULT_ENV_PREFIX, DEFAULT_ENV_DELIMITER)?;
+    }
     warn_unused(&cfg);
     set_defaults(&mut cfg);
 
     // session configuration
     let mut s_builder = SessionBuilder::new();
+    // the more specific sources of the authtoken are applied last, to take precedence
+    if cfg.authtoken_from_agent_config.unwrap_or(false) {
+        s_builder.authtoken_from_agent_config(None)?;
+    }
+    plumb_with_result!(s_builder, cfg, authtoken_from_file, authtoken_from_file);
     plumb!(s_builder, cfg, authtoken);
+    plumb!(s_builder, cfg, account);
     plumb_bool!(s_builder, cfg, authtoken_from_env);
     plumb!(s_builder, cfg, metadata, session_metadata);
     if let Some(ref ca_cert) = cfg.session_ca_cert {
@@ -161,6 +191,13 @@ pub fn forward(
     }
     plumb_with_result!(s_builder, cfg, root_cas, root_cas);
     plumb_with_result!(s_builder, cfg, server_addr, server_addr);
+    if let Some(ref server_addrs) = cfg.server_addrs {
+        let server_addrs = match server_addrs {
+            Either::A(addr) => vec![addr.clone()],
+            Either::B(addrs) => addrs.clone(),
+        };
+        s_builder.server_addrs(server_addrs, cfg.server_addr_strategy.clone())?;
+    }
     if let Some(func) = on_connection {
         s_builder.handle_connection(env, func);
     }
@@ -173,137 +210,293 @@ pub fn forward(
 }
 
 /// Connect the session, configure and start the listener
-async fn async_connect(s_builder: SessionBuilder, config: Config) -> Result<Listener> {
+async fn async_connect(mut s_builder: SessionBuilder, config: Config) -> Result<Listener> {
     let force_new_session = config.force_new_session.unwrap_or(false);
 
-    // Using a singleton session for connect use cases
-    let mut opt = SESSION.lock().await;
-    if opt.is_none() || force_new_session {
-        opt.replace(s_builder.connect().await?);
+    // Using a singleton session per account for connect use cases
+    let mut sessions = SESSION.lock().await;
+    let account = config.account.clone();
+    if !sessions.contains_key(&account) || force_new_session {
+        let session = s_builder.connect().await;
+        s_builder.release_authtoken();
+        sessions.insert(account.clone(), session?);
+    } else {
+        s_builder.release_authtoken();
     }
-    let session = opt.as_ref().unwrap();
+    let session = &sessions[&account];
 
-    // listener configuration dispatch
-    let proto = config.proto.as_ref().unwrap();
-    let id = match proto.as_str() {
-        "http" => http_endpoint(session, &config).await?,
-        "tcp" => tcp_endpoint(session, &config).await?,
-        "tls" => tls_endpoint(session, &config).await?,
-        "labeled" => labeled_listener(session, &config).await?,
-        _ => return Err(napi_err(format!("unhandled protocol {proto}"))),
+    // one listener per scheme, all forwarding to the same address
+    let configs = match config.schemes {
+        Some(ref schemes) if schemes.len() > 1 => schemes
+            .iter()
+            .map(|scheme| Config {
+                schemes: Some(vec![scheme.clone()]),
+                ..config.clone()
+            })
+            .collect(),
+        _ => vec![config.clone()],
     };
+    let mut listeners = vec![];
+    for cfg in &configs {
+        match start_listener(session, cfg).await {
+            Ok(listener) => listeners.push(listener),
+            Err(e) => {
+                // don't leave a partial group behind
+                for listener in listeners {
+                    let _ = listener.close().await;
+                }
+                return Err(e);
+            }
+        }
+    }
 
-    let listener = listener::get_listener(id.clone())
-        .await
-        .ok_or(napi_err("failed to start listener".to_string()))?;
-
-    // move forwarding to another task
+    // move forwarding to other tasks
     if let Some(addr) = config.addr {
-        tokio::spawn(async move { listener::forward(&id, addr).await });
+        for listener in &listeners {
+            let (id, addr) = (listener.id(), addr.clone());
+            tokio::spawn(async move { listener::forward(&id, addr).await });
+        }
     }
 
-    Ok(listener)
+    Listener::grouped(listeners).ok_or(napi_err("failed to start listener".to_string()))
 }
 
-/// HTTP Listener configuration
-async fn http_endpoint(session: &Session, cfg: &Config) -> Result<String> {
-    let mut bld = session.http_endpoint();
-    config_common!(bld, cfg);
-    plumb_vec!(bld, cfg, scheme, schemes);
-    plumb!(bld, cfg, domain, hostname); // synonym for domain
-    plumb!(bld, cfg, domain);
-    plumb!(bld, cfg, app_protocol);
-    plumb_vec!(bld, cfg, mutual_tlsca, mutual_tls_cas, vecu8);
-    plumb_bool!(bld, cfg, compression);
-    plumb_bool!(bld, cfg, websocket_tcp_conversion, websocket_tcp_converter);
-    plumb_vec!(bld, cfg, request_header, request_header_add, ":");
-    plumb_vec!(bld, cfg, response_header, response_header_add, ":");
-    plumb_vec!(bld, cfg, remove_request_header, request_header_remove);
-    plumb_vec!(bld, cfg, remove_response_header, response_header_remove);
-    plumb_vec!(bld, cfg, basic_auth, basic_auth, ":");
-    plumb_vec!(bld, cfg, allow_user_agent, allow_user_agent);
-    plumb_vec!(bld, cfg, deny_user_agent, deny_user_agent);
-    // circuit breaker
-    if let Some(circuit_breaker) = cfg.circuit_breaker {
-        bld.circuit_breaker(circuit_breaker);
-    }
-    // oauth
-    if let Some(ref provider) = cfg.oauth_provider {
-        bld.oauth(
-            provider.clone(),
-            cfg.oauth_allow_emails.clone(),
-            cfg.oauth_allow_domains.clone(),
-            cfg.oauth_scopes.clone(),
-            cfg.oauth_client_id.clone(),
-            cfg.oauth_client_secret.clone(),
-        );
-    }
-    // oidc
-    if let Some(ref issuer_url) = cfg.oidc_issuer_url {
-        if cfg.oidc_client_id.is_none() {
-            return Err(napi_err("Missing client id for oidc"));
+async fn start_listener(session: &Session, config: &Config) -> Result<Listener> {
+    let id = listen_with_config(session, config).await?;
+    listener::get_listener(id)
+        .await
+        .ok_or(napi_err("failed to start listener".to_string()))
+}
+
+/// Start a listener on the session from the configuration, returning its id.
+pub(crate) async fn listen_with_config(session: &Session, config: &Config) -> Result<String> {
+    let with_url;
+    let config = match config.url {
+        Some(ref url) => {
+            with_url = merge_url_config(config, url)?;
+            &with_url
+        }
+        None => config,
+    };
+    // listener configuration dispatch
+    let proto = config.proto.as_deref().unwrap_or("http");
+    match proto {
+        "http" => http_endpoint(session, config).await,
+        "tcp" => tcp_endpoint(session, config).await,
+        "tls" => tls_endpoint(session, config).await,
+        "labeled" => labeled_listener(session, config).await,
+        _ => Err(napi_err(format!("unhandled protocol {proto}"))),
+    }
+}
+
+/// The listener options implied by an endpoint url, such as "https://example.ngrok.app",
+/// "tls://example.ngrok.app" or "tcp://1.tcp.ngrok.io:12345".
+pub(crate) fn url_config(url: &str) -> Result<Config> {
+    let parsed =
+        Url::parse(url).map_err(|e| napi_err(format!("Endpoint url parse failure, {e}")))?;
+    let host = parsed
+        .host_str()
+        .filter(|host| !host.is_empty())
+        .map(String::from);
+    let mut config = Config::default();
+    match parsed.scheme() {
+        scheme @ ("http" | "https") => {
+            config.proto = Some("http".to_string());
+            config.schemes = Some(vec![scheme.to_uppercase()]);
+            config.domain = host;
         }
-        if cfg.oidc_client_secret.is_none() {
-            return Err(napi_err("Missing client secret for oidc"));
+        "tls" => {
+            config.proto = Some("tls".to_string());
+            config.domain = host;
         }
-        bld.oidc(
-            issuer_url.clone(),
-            cfg.oidc_client_id.clone().unwrap(),
-            cfg.oidc_client_secret.clone().unwrap(),
-            cfg.oidc_allow_emails.clone(),
-            cfg.oidc_allow_domains.clone(),
-            cfg.oidc_scopes.clone(),
-        );
-    }
-    // webhook verification
-    if let Some(ref provider) = cfg.verify_webhook_provider {
-        if let Some(ref secret) = cfg.verify_webhook_secret {
-            bld.webhook_verification(provider.clone(), secret.clone());
-        } else {
-            return Err(napi_err("Missing secret for webhook verification"));
+        "tcp" => {
+            config.proto = Some("tcp".to_string());
+            config.remote_addr = match (host, parsed.port()) {
+                (Some(host), Some(port)) => Some(format!("{host}:{port}")),
+                (None, None) => None,
+                _ => {
+                    return Err(napi_err(format!(
+                        "TCP endpoint url {url:?} must have both a host and a port"
+                    )))
+                }
+            };
+        }
+        scheme => {
+            return Err(napi_err(format!(
+                "Unsupported endpoint url scheme {scheme:?}, expected \"http\", \"https\", \"tls\" or \"tcp\""
+            )))
         }
     }
+    // http ports are implied by the scheme, and tls endpoints are always on 443
+    if config.proto.as_deref() != Some("tcp") && parsed.port().is_some_and(|port| port != 443) {
+        return Err(napi_err(format!(
+            "Endpoint url {url:?} cannot have a port, only tcp endpoints can"
+        )));
+    }
+    Ok(config)
+}
+
+/// Fill in the options implied by the config's url, which must agree with its proto.
+fn merge_url_config(config: &Config, url: &str) -> Result<Config> {
+    let from_url = url_config(url)?;
+    if config.proto.is_some() && config.proto != from_url.proto {
+        return Err(napi_err(format!(
+            "Config proto {:?} does not match the url {url:?}",
+            config.proto.as_deref().unwrap_or_default()
+        )));
+    }
+    let mut config = config.clone();
+    config.proto = from_url.proto;
+    if config.schemes.is_none() {
+        config.schemes = from_url.schemes;
+    }
+    if config.domain.is_none() && config.hostname.is_none() {
+        config.domain = from_url.domain;
+    }
+    if config.remote_addr.is_none() {
+        config.remote_addr = from_url.remote_addr;
+    }
+    Ok(config)
+}
+
+/// HTTP Listener configuration
+async fn http_endpoint(session: &Session, cfg: &Config) -> Result<String> {
+    let mut bld = session.http_endpoint();
+    bld.apply_config(cfg)?;
     Ok(bld.listen(None).await?.id())
 }
 
 /// TCP Listener configuration
 async fn tcp_endpoint(session: &Session, cfg: &Config) -> Result<String> {
     let mut bld = session.tcp_endpoint();
-    config_common!(bld, cfg);
-    plumb!(bld, cfg, remote_addr);
+    bld.apply_config(cfg)?;
     Ok(bld.listen(None).await?.id())
 }
 
 /// TLS Listener configuration
 async fn tls_endpoint(session: &Session, cfg: &Config) -> Result<String> {
     let mut bld = session.tls_endpoint();
-    config_common!(bld, cfg);
-    plumb!(bld, cfg, domain, hostname); // synonym for domain
-    plumb!(bld, cfg, domain);
-    plumb_vec!(bld, cfg, mutual_tlsca, mutual_tls_cas, vecu8);
-    if let Some(ref crt) = cfg.crt {
-        if let Some(ref key) = cfg.key {
-            bld.termination(
-                Uint8Array::new(crt.as_bytes().to_vec()),
-                Uint8Array::new(key.as_bytes().to_vec()),
-            );
-        } else {
-            return Err(napi_err("Missing key for tls termination"));
-        }
-    }
+    bld.apply_config(cfg)?;
     Ok(bld.listen(None).await?.id())
 }
 
 /// Labeled Listener configuration
 async fn labeled_listener(session: &Session, cfg: &Config) -> Result<String> {
     let mut bld = session.labeled_listener();
-    plumb!(bld, cfg, metadata);
-    plumb!(bld, cfg, app_protocol);
-    plumb!(bld, cfg, verify_upstream_tls);
-    plumb_vec!(bld, cfg, label, labels, ":");
+    bld.apply_config(cfg)?;
     Ok(bld.listen(None).await?.id())
 }
 
+impl HttpListenerBuilder {
+    /// Apply the HTTP listener options of the configuration.
+    pub(crate) fn apply_config(&mut self, cfg: &Config) -> Result<()> {
+        let bld = self;
+        config_common!(bld, cfg);
+        plumb_vec!(bld, cfg, scheme, schemes);
+        plumb!(bld, cfg, domain, hostname); // synonym for domain
+        plumb!(bld, cfg, domain);
+        plumb!(bld, cfg, app_protocol);
+        plumb_vec!(bld, cfg, mutual_tlsca, mutual_tls_cas, vecu8);
+        plumb_bool!(bld, cfg, compression);
+        plumb_bool!(bld, cfg, websocket_tcp_conversion, websocket_tcp_converter);
+        plumb!(bld, cfg, pooling_enabled);
+        plumb_vec!(bld, cfg, request_header, request_header_add, ":");
+        plumb_vec!(bld, cfg, response_header, response_header_add, ":");
+        plumb_vec!(bld, cfg, remove_request_header, request_header_remove);
+        plumb_vec!(bld, cfg, remove_response_header, response_header_remove);
+        plumb_vec!(bld, cfg, basic_auth, basic_auth, ":");
+        plumb_vec!(bld, cfg, allow_user_agent, allow_user_agent);
+        plumb_vec!(bld, cfg, deny_user_agent, deny_user_agent);
+        // circuit breaker
+        if let Some(circuit_breaker) = cfg.circuit_breaker {
+            bld.circuit_breaker(circuit_breaker);
+        }
+        // oauth
+        if let Some(ref provider) = cfg.oauth_provider {
+            bld.oauth(
+                provider.clone(),
+                cfg.oauth_allow_emails.clone(),
+                cfg.oauth_allow_domains.clone(),
+                cfg.oauth_scopes.clone(),
+                cfg.oauth_client_id.clone(),
+                cfg.oauth_client_secret.clone(),
+            );
+        }
+        // oidc
+        if let Some(ref issuer_url) = cfg.oidc_issuer_url {
+            if cfg.oidc_client_id.is_none() {
+                return Err(napi_err("Missing client id for oidc"));
+            }
+            if cfg.oidc_client_secret.is_none() {
+                return Err(napi_err("Missing client secret for oidc"));
+            }
+            bld.oidc(
+                issuer_url.clone(),
+                cfg.oidc_client_id.clone().unwrap(),
+                cfg.oidc_client_secret.clone().unwrap(),
+                cfg.oidc_allow_emails.clone(),
+                cfg.oidc_allow_domains.clone(),
+                cfg.oidc_scopes.clone(),
+            );
+        }
+        // webhook verification
+        if let Some(ref provider) = cfg.verify_webhook_provider {
+            if let Some(ref secret) = cfg.verify_webhook_secret {
+                bld.webhook_verification(provider.clone(), secret.clone());
+            } else {
+                return Err(napi_err("Missing secret for webhook verification"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TcpListenerBuilder {
+    /// Apply the TCP listener options of the configuration.
+    pub(crate) fn apply_config(&mut self, cfg: &Config) -> Result<()> {
+        let bld = self;
+        config_common!(bld, cfg);
+        plumb!(bld, cfg, remote_addr);
+        plumb!(bld, cfg, pooling_enabled);
+        Ok(())
+    }
+}
+
+impl TlsListenerBuilder {
+    /// Apply the TLS listener options of the configuration.
+    pub(crate) fn apply_config(&mut self, cfg: &Config) -> Result<()> {
+        let bld = self;
+        config_common!(bld, cfg);
+        plumb!(bld, cfg, domain, hostname); // synonym for domain
+        plumb!(bld, cfg, domain);
+        plumb_vec!(bld, cfg, mutual_tlsca, mutual_tls_cas, vecu8);
+        plumb!(bld, cfg, pooling_enabled);
+        if let Some(ref crt) = cfg.crt {
+            if let Some(ref key) = cfg.key {
+                bld.termination(
+                    Uint8Array::new(crt.as_bytes().to_vec()),
+                    Uint8Array::new(key.as_bytes().to_vec()),
+                );
+            } else {
+                return Err(napi_err("Missing key for tls termination"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl LabeledListenerBuilder {
+    /// Apply the labeled listener options of the configuration.
+    pub(crate) fn apply_config(&mut self, cfg: &Config) -> Result<()> {
+        let bld = self;
+        plumb!(bld, cfg, metadata);
+        plumb!(bld, cfg, app_protocol);
+        plumb!(bld, cfg, verify_upstream_tls);
+        plumb_vec!(bld, cfg, label, labels, ":");
+        Ok(())
+    }
+}
+
 /// Set the expected defaults for configuration values
 fn set_defaults(config: &mut Config) {
     if config.proto.is_none() {
@@ -350,11 +543,6 @@ fn warn_unused(config: &Config) {
     if config.region.is_some() {
         warn!("region is unused");
     }
-    if let Some(ref schemes) = config.schemes {
-        if schemes.len() > 1 {
-            warn!("Multiple schemes set, only last one will be used");
-        }
-    }
     if config.subdomain.is_some() {
         warn!("subdomain is unused");
     }
@@ -366,25 +554,46 @@ fn warn_unused(config: &Config) {
     }
 }
 
-/// Close a listener with the given url, or all listeners if no url is defined.
+/// Options for {@link disconnect}.
+///
+/// @group Functions
+#[napi(object)]
+pub struct DisconnectOptions {
+    /// How long to wait in milliseconds for each listener to close before giving up on it.
+    pub timeout: Option<u32>,
+}
+
+/// Close the listeners with the given url, or all listeners if no url is defined.
+/// The listeners are closed concurrently, and a result is returned for each of them
+/// rather than failing on the first error.
 #[napi]
 #[allow(dead_code)]
-pub async fn disconnect(url: Option<String>) -> Result<()> {
-    listener::close_url(url.clone()).await?;
+pub async fn disconnect(
+    url: Option<String>,
+    options: Option<DisconnectOptions>,
+) -> Result<Vec<CloseResult>> {
+    let wait = options
+        .and_then(|o| o.timeout)
+        .map(|t| Duration::from_millis(t.into()));
+    let results = listener::close_url(url.clone(), wait).await;
 
-    // if closing every listener, close and remove the stored session
+    // if closing every listener, close and remove the stored sessions
     if url.as_ref().is_none() {
-        if let Some(session) = SESSION.lock().await.take() {
-            session.close().await?;
+        let sessions: Vec<Session> = SESSION.lock().await.drain().map(|(_, s)| s).collect();
+        // close every session, even if one fails, as they are no longer stored
+        for session in sessions {
+            if let Err(e) = session.close().await {
+                warn!("Failed to close session: {}", e.reason);
+            }
         }
     }
 
-    Ok(())
+    Ok(results)
 }
 
 /// Close all listeners.
 #[napi]
 #[allow(dead_code)]
-pub async fn kill() -> Result<()> {
-    disconnect(None).await
+pub async fn kill() -> Result<Vec<CloseResult>> {
+    disconnect(None, None).await
 }
@@ -0,0 +1,217 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+};
+
+use lazy_static::lazy_static;
+use napi_derive::napi;
+use parking_lot::Mutex as SyncMutex;
+use tokio::sync::{
+    broadcast::{
+        self,
+        error::RecvError,
+    },
+    Mutex,
+    Notify,
+};
+use tracing::warn;
+
+use crate::session::UpdateRequest;
+
+// how many events a slow consumer may fall behind before losing events
+const EVENT_CAPACITY: usize = 256;
+
+lazy_static! {
+    // event senders for connected sessions, by session id, so listeners can report their events.
+    static ref SESSION_EVENTS: SyncMutex<HashMap<String, SessionEventSender>> =
+        SyncMutex::new(HashMap::new());
+}
+
+/// An event in the life of a session, as yielded by {@link Session.events}.
+///
+/// @group Listener and Sessions
+#[napi(object, object_from_js = false)]
+#[derive(Clone, Default)]
+pub struct SessionEvent {
+    /// The kind of event.
+    #[napi(
+        js_name = "type",
+        ts_type = "\"connected\" | \"disconnected\" | \"heartbeat\" | \"command\" | \"listener-closed\" | \"listener-restarted\""
+    )]
+    pub kind: String,
+    /// The server address for "connected" and "disconnected" events.
+    pub addr: Option<String>,
+    /// The error message for "disconnected" events.
+    pub error: Option<String>,
+    /// The heartbeat latency in milliseconds for "heartbeat" events, absent if the heartbeat timed out.
+    pub latency: Option<u32>,
+    /// The command for "command" events, one of "stop", "restart", or "update".
+    pub command: Option<String>,
+    /// The update request for "update" commands.
+    pub update: Option<UpdateRequest>,
+    /// The listener id for "listener-closed" and "listener-restarted" events.
+    pub listener_id: Option<String>,
+    /// The id the listener had before the session restarted, for "listener-restarted" events.
+    pub previous_listener_id: Option<String>,
+}
+
+impl SessionEvent {
+    pub(crate) fn connected(addr: String) -> Self {
+        SessionEvent {
+            kind: "connected".to_string(),
+            addr: Some(addr),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn disconnected(addr: String, error: String) -> Self {
+        SessionEvent {
+            kind: "disconnected".to_string(),
+            addr: Some(addr),
+            error: Some(error),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn heartbeat(latency: Option<u32>) -> Self {
+        SessionEvent {
+            kind: "heartbeat".to_string(),
+            latency,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn command(command: &str, update: Option<UpdateRequest>) -> Self {
+        SessionEvent {
+            kind: "command".to_string(),
+            command: Some(command.to_string()),
+            update,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn listener_closed(id: String) -> Self {
+        SessionEvent {
+            kind: "listener-closed".to_string(),
+            listener_id: Some(id),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn listener_restarted(id: String, previous_id: String) -> Self {
+        SessionEvent {
+            kind: "listener-restarted".to_string(),
+            listener_id: Some(id),
+            previous_listener_id: Some(previous_id),
+            ..Default::default()
+        }
+    }
+}
+
+/// The sending side of a session's event stream. A `None` marks the end of the stream.
+#[derive(Clone)]
+pub(crate) struct SessionEventSender(broadcast::Sender<Option<SessionEvent>>);
+
+impl Default for SessionEventSender {
+    fn default() -> Self {
+        SessionEventSender(broadcast::channel(EVENT_CAPACITY).0)
+    }
+}
+
+impl SessionEventSender {
+    /// Send the event to all current subscribers, dropping it if there are none.
+    pub(crate) fn send(&self, event: SessionEvent) {
+        let _ = self.0.send(Some(event));
+    }
+
+    /// End the stream for all current subscribers.
+    pub(crate) fn close(&self) {
+        let _ = self.0.send(None);
+    }
+
+    /// End the stream and forget the sender, for a session which ended without
+    /// being closed: stopped by the ngrok service, failed to reconnect, or dropped.
+    pub(crate) fn end(&self) {
+        SESSION_EVENTS
+            .lock()
+            .retain(|_, sender| !sender.0.same_channel(&self.0));
+        self.close();
+    }
+
+    pub(crate) fn subscribe(&self) -> SessionEvents {
+        SessionEvents {
+            receiver: Arc::new(Mutex::new(Some(self.0.subscribe()))),
+            closed: Arc::new(Notify::new()),
+        }
+    }
+}
+
+/// Register the event sender for a connected session.
+pub(crate) fn register_session_events(session_id: String, sender: SessionEventSender) {
+    SESSION_EVENTS.lock().insert(session_id, sender);
+}
+
+/// Remove the event sender for a closed session, ending its event streams.
+pub(crate) fn unregister_session_events(session_id: &String) {
+    if let Some(sender) = SESSION_EVENTS.lock().remove(session_id) {
+        sender.close();
+    }
+}
+
+/// Move the event sender of a restarted session to its new session id, keeping its streams open.
+pub(crate) fn rename_session_events(old_session_id: &String, new_session_id: String) {
+    let mut events = SESSION_EVENTS.lock();
+    if let Some(sender) = events.remove(old_session_id) {
+        events.insert(new_session_id, sender);
+    }
+}
+
+/// Send an event to the streams of the session with the given id, if any.
+pub(crate) fn emit_session_event(session_id: &String, event: SessionEvent) {
+    if let Some(sender) = SESSION_EVENTS.lock().get(session_id) {
+        sender.send(event);
+    }
+}
+
+/// An async iterator of {@link SessionEvent}s, in the order they occurred.
+///
+/// @group Listener and Sessions
+#[napi]
+pub struct SessionEvents {
+    receiver: Arc<Mutex<Option<broadcast::Receiver<Option<SessionEvent>>>>>,
+    closed: Arc<Notify>,
+}
+
+#[napi]
+impl SessionEvents {
+    /// Wait for the next event. Returns null once the session has been closed,
+    /// stopped by the ngrok service, has failed to reconnect, or was dropped.
+    #[napi]
+    pub async fn recv(&self) -> Option<SessionEvent> {
+        let mut guard = self.receiver.lock().await;
+        let receiver = guard.as_mut()?;
+        loop {
+            let res = tokio::select! {
+                res = receiver.recv() => res,
+                _ = self.closed.notified() => Ok(None),
+            };
+            match res {
+                Ok(Some(event)) => return Some(event),
+                Err(RecvError::Lagged(count)) => {
+                    warn!("Session event stream fell behind, {count} events dropped");
+                }
+                // end of stream
+                Ok(None) | Err(RecvError::Closed) => {
+                    guard.take();
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Stop receiving events, ending the stream.
+    #[napi]
+    pub fn close(&self) {
+        self.closed.notify_one();
+    }
+}
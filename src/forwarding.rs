@@ -0,0 +1,231 @@
+use std::{
+    collections::HashMap,
+    future::{
+        self,
+        Future,
+    },
+    io,
+    pin::{
+        pin,
+        Pin,
+    },
+    sync::atomic::{
+        AtomicU64,
+        Ordering,
+    },
+    task::{
+        Context,
+        Poll,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::{
+    Stream,
+    TryStreamExt,
+};
+use lazy_static::lazy_static;
+use ngrok::{
+    prelude::*,
+    session::RpcError,
+    tunnel::AcceptError,
+};
+use parking_lot::Mutex as SyncMutex;
+use tokio::{
+    runtime::{
+        Builder,
+        Handle,
+        Runtime,
+    },
+    time::{
+        sleep,
+        timeout,
+    },
+};
+use tracing::{
+    debug,
+    info_span,
+};
+use url::Url;
+
+#[cfg(feature = "telemetry")]
+use crate::telemetry::record_connection_started;
+
+// identifies each forwarded connection in its tracing span
+static NEXT_CONN_ID: AtomicU64 = AtomicU64::new(1);
+// identifies each tracker's runtime in RUNTIMES
+static NEXT_TRACKER_ID: AtomicU64 = AtomicU64::new(1);
+
+// how often a drain checks whether the connections have finished
+const DRAIN_INTERVAL: Duration = Duration::from_millis(50);
+
+lazy_static! {
+    // the runtimes of the trackers which are forwarding, to count all active connections
+    static ref RUNTIMES: SyncMutex<HashMap<u64, Handle>> = SyncMutex::new(HashMap::new());
+}
+
+/// The connections a listener is currently forwarding, so they can be waited on or cut off.
+///
+/// The upstream forwarder spawns a task for each connection without handing it back, so it
+/// is run with a runtime of the listener's own entered. That runtime runs only those tasks,
+/// which it counts until they finish, and cuts off when it is shut down.
+pub(crate) struct ConnTracker {
+    id: u64,
+    runtime: SyncMutex<Option<Runtime>>,
+}
+
+impl Default for ConnTracker {
+    fn default() -> Self {
+        ConnTracker {
+            id: NEXT_TRACKER_ID.fetch_add(1, Ordering::Relaxed),
+            runtime: SyncMutex::new(None),
+        }
+    }
+}
+
+impl ConnTracker {
+    // the runtime is only started once the listener forwards
+    fn handle(&self) -> io::Result<Handle> {
+        let mut runtime = self.runtime.lock();
+        if let Some(runtime) = runtime.as_ref() {
+            return Ok(runtime.handle().clone());
+        }
+        let started = Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("ngrok-forward")
+            .enable_all()
+            .build()?;
+        let handle = started.handle().clone();
+        RUNTIMES.lock().insert(self.id, handle.clone());
+        runtime.replace(started);
+        Ok(handle)
+    }
+
+    /// Run the upstream forwarder, with the connection tasks it spawns tracked here.
+    pub(crate) async fn track(
+        &self,
+        forward: impl Future<Output = io::Result<()>>,
+    ) -> io::Result<()> {
+        let handle = self.handle()?;
+        let mut forward = pin!(forward);
+        future::poll_fn(|cx| {
+            let _entered = handle.enter();
+            forward.as_mut().poll(cx)
+        })
+        .await
+    }
+
+    /// Wait for the active connections to finish, up to the timeout if one is given.
+    /// Without one, this waits for as long as any connection stays open.
+    /// Any connections still active after that are cut off, and their count returned.
+    pub(crate) async fn drain(&self, wait: Option<Duration>) -> u32 {
+        let Some(handle) = self.runtime.lock().as_ref().map(|r| r.handle().clone()) else {
+            return 0;
+        };
+        let finished = async {
+            while handle.metrics().num_alive_tasks() > 0 {
+                sleep(DRAIN_INTERVAL).await;
+            }
+        };
+        if let Some(wait) = wait {
+            let _ = timeout(wait, finished).await;
+        } else {
+            finished.await;
+        }
+        let remaining = handle.metrics().num_alive_tasks() as u32;
+        self.shutdown();
+        remaining
+    }
+
+    fn shutdown(&self) {
+        if let Some(runtime) = self.runtime.lock().take() {
+            RUNTIMES.lock().remove(&self.id);
+            // aborts the connections, without blocking on them as dropping would
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl Drop for ConnTracker {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// The number of connections being forwarded by all listeners.
+#[cfg(feature = "telemetry")]
+pub(crate) fn active_connections() -> usize {
+    RUNTIMES
+        .lock()
+        .values()
+        .map(|handle| handle.metrics().num_alive_tasks())
+        .sum()
+}
+
+/// A tunnel of one accepted connection, so the upstream forwarder can forward it on its own.
+pub(crate) struct SingleConn<C> {
+    id: String,
+    conn: Option<C>,
+}
+
+impl<C: Conn> Stream for SingleConn<C> {
+    type Item = Result<C, AcceptError>;
+
+    fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(self.get_mut().conn.take().map(Ok))
+    }
+}
+
+impl<C> TunnelInfo for SingleConn<C> {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn forwards_to(&self) -> &str {
+        ""
+    }
+
+    fn metadata(&self) -> &str {
+        ""
+    }
+}
+
+#[async_trait]
+impl<C: Send> TunnelCloser for SingleConn<C> {
+    async fn close(&mut self) -> Result<(), RpcError> {
+        Ok(())
+    }
+}
+
+impl<C: Conn> Tunnel for SingleConn<C> {
+    type Conn = C;
+}
+
+/// Accept the next connection on the listener, to be forwarded to the url.
+/// Returns `None` once the listener is closed.
+pub(crate) async fn next_conn<T: Tunnel>(
+    listener: &mut T,
+    url: &Url,
+) -> io::Result<Option<SingleConn<T::Conn>>> {
+    let Some(conn) = listener
+        .try_next()
+        .await
+        .map_err(|err| io::Error::new(io::ErrorKind::NotConnected, err))?
+    else {
+        return Ok(None);
+    };
+    // nested in the listener's span, which the accept loop runs in
+    info_span!(
+        "connection",
+        conn_id = NEXT_CONN_ID.fetch_add(1, Ordering::Relaxed),
+        remote_addr = %conn.remote_addr(),
+    )
+    .in_scope(|| debug!("Connection accepted, forwarding to {url}"));
+    #[cfg(feature = "telemetry")]
+    record_connection_started();
+    Ok(Some(SingleConn {
+        id: listener.id().to_string(),
+        conn: Some(conn),
+    }))
+}
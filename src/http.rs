@@ -9,7 +9,11 @@ use ngrok::config::{
     Scheme,
 };
 
-use crate::listener_builder::HttpListenerBuilder;
+use crate::{
+    config::Certificate,
+    listener_builder::HttpListenerBuilder,
+    redact::register_secret,
+};
 
 #[napi]
 #[allow(dead_code)]
@@ -23,13 +27,15 @@ impl HttpListenerBuilder {
             Scheme::from_str(scheme.as_str())
                 .unwrap_or_else(|_| panic!("Unknown scheme: {scheme:?}")),
         );
+        self.config.lock().schemes = Some(vec![scheme]);
         self
     }
     /// The L7 application protocol to use for this edge, e.g. "http2" or "http1".
     #[napi]
     pub fn app_protocol(&mut self, app_protocol: String) -> &Self {
         let mut builder = self.listener_builder.lock();
-        builder.app_protocol(app_protocol);
+        builder.app_protocol(app_protocol.clone());
+        self.config.lock().app_protocol = Some(app_protocol);
         self
     }
     /// The domain to request for this edge, any valid domain or hostname that you have
@@ -40,7 +46,8 @@ impl HttpListenerBuilder {
     #[napi]
     pub fn domain(&mut self, domain: String) -> &Self {
         let mut builder = self.listener_builder.lock();
-        builder.domain(domain);
+        builder.domain(domain.clone());
+        self.config.lock().domain = Some(domain);
         self
     }
     /// Certificates to use for client authentication at the ngrok edge.
@@ -51,6 +58,11 @@ impl HttpListenerBuilder {
     pub fn mutual_tlsca(&mut self, mutual_tlsca: Uint8Array) -> &Self {
         let mut builder = self.listener_builder.lock();
         builder.mutual_tlsca(Bytes::from(mutual_tlsca.to_vec()));
+        self.config
+            .lock()
+            .mutual_tls_cas
+            .get_or_insert_with(Vec::new)
+            .push(Certificate(mutual_tlsca.to_vec()));
         self
     }
     /// Enable gzip compression for HTTP responses.
@@ -61,6 +73,7 @@ impl HttpListenerBuilder {
     pub fn compression(&mut self) -> &Self {
         let mut builder = self.listener_builder.lock();
         builder.compression();
+        self.config.lock().compression = Some(true);
         self
     }
     /// Convert incoming websocket connections to TCP-like streams.
@@ -68,6 +81,7 @@ impl HttpListenerBuilder {
     pub fn websocket_tcp_conversion(&mut self) -> &Self {
         let mut builder = self.listener_builder.lock();
         builder.websocket_tcp_conversion();
+        self.config.lock().websocket_tcp_converter = Some(true);
         self
     }
     /// Reject requests when 5XX responses exceed this ratio.
@@ -79,6 +93,7 @@ impl HttpListenerBuilder {
     pub fn circuit_breaker(&mut self, circuit_breaker: f64) -> &Self {
         let mut builder = self.listener_builder.lock();
         builder.circuit_breaker(circuit_breaker);
+        self.config.lock().circuit_breaker = Some(circuit_breaker);
         self
     }
 
@@ -89,7 +104,12 @@ impl HttpListenerBuilder {
     #[napi]
     pub fn request_header(&mut self, name: String, value: String) -> &Self {
         let mut builder = self.listener_builder.lock();
-        builder.request_header(name, value);
+        builder.request_header(name.clone(), value.clone());
+        self.config
+            .lock()
+            .request_header_add
+            .get_or_insert_with(Vec::new)
+            .push(format!("{name}:{value}"));
         self
     }
     /// Adds a header to all responses coming from this edge.
@@ -99,7 +119,12 @@ impl HttpListenerBuilder {
     #[napi]
     pub fn response_header(&mut self, name: String, value: String) -> &Self {
         let mut builder = self.listener_builder.lock();
-        builder.response_header(name, value);
+        builder.response_header(name.clone(), value.clone());
+        self.config
+            .lock()
+            .response_header_add
+            .get_or_insert_with(Vec::new)
+            .push(format!("{name}:{value}"));
         self
     }
     /// Removes a header from requests to this edge.
@@ -109,7 +134,12 @@ impl HttpListenerBuilder {
     #[napi]
     pub fn remove_request_header(&mut self, name: String) -> &Self {
         let mut builder = self.listener_builder.lock();
-        builder.remove_request_header(name);
+        builder.remove_request_header(name.clone());
+        self.config
+            .lock()
+            .request_header_remove
+            .get_or_insert_with(Vec::new)
+            .push(name);
         self
     }
     /// Removes a header from responses from this edge.
@@ -119,7 +149,12 @@ impl HttpListenerBuilder {
     #[napi]
     pub fn remove_response_header(&mut self, name: String) -> &Self {
         let mut builder = self.listener_builder.lock();
-        builder.remove_response_header(name);
+        builder.remove_response_header(name.clone());
+        self.config
+            .lock()
+            .response_header_remove
+            .get_or_insert_with(Vec::new)
+            .push(name);
         self
     }
 
@@ -127,8 +162,14 @@ impl HttpListenerBuilder {
     /// If not called, basic authentication is disabled.
     #[napi]
     pub fn basic_auth(&mut self, username: String, password: String) -> &Self {
+        register_secret(&password);
         let mut builder = self.listener_builder.lock();
-        builder.basic_auth(username, password);
+        builder.basic_auth(username.clone(), password.clone());
+        self.config
+            .lock()
+            .basic_auth
+            .get_or_insert_with(Vec::new)
+            .push(format!("{username}:{password}"));
         self
     }
 
@@ -141,7 +182,12 @@ impl HttpListenerBuilder {
     #[napi]
     pub fn allow_user_agent(&mut self, regex: String) -> &Self {
         let mut builder = self.listener_builder.lock();
-        builder.allow_user_agent(regex);
+        builder.allow_user_agent(regex.clone());
+        self.config
+            .lock()
+            .allow_user_agent
+            .get_or_insert_with(Vec::new)
+            .push(regex);
         self
     }
     /// A set of regular expressions used to match User-Agents that will be denied.
@@ -153,7 +199,12 @@ impl HttpListenerBuilder {
     #[napi]
     pub fn deny_user_agent(&mut self, regex: String) -> &Self {
         let mut builder = self.listener_builder.lock();
-        builder.deny_user_agent(regex);
+        builder.deny_user_agent(regex.clone());
+        self.config
+            .lock()
+            .deny_user_agent
+            .get_or_insert_with(Vec::new)
+            .push(regex);
         self
     }
 
@@ -172,6 +223,15 @@ impl HttpListenerBuilder {
         client_id: Option<String>,
         client_secret: Option<String>,
     ) -> &Self {
+        {
+            let mut config = self.config.lock();
+            config.oauth_provider = Some(provider.clone());
+            config.oauth_allow_emails.clone_from(&allow_emails);
+            config.oauth_allow_domains.clone_from(&allow_domains);
+            config.oauth_scopes.clone_from(&scopes);
+            config.oauth_client_id.clone_from(&client_id);
+            config.oauth_client_secret.clone_from(&client_secret);
+        }
         let mut oauth = OauthOptions::new(provider);
         if let Some(allow_emails) = allow_emails {
             allow_emails.iter().for_each(|v| {
@@ -192,6 +252,7 @@ impl HttpListenerBuilder {
             oauth.client_id(client_id);
         }
         if let Some(client_secret) = client_secret {
+            register_secret(&client_secret);
             oauth.client_secret(client_secret);
         }
 
@@ -215,6 +276,16 @@ impl HttpListenerBuilder {
         allow_domains: Option<Vec<String>>,
         scopes: Option<Vec<String>>,
     ) -> &Self {
+        {
+            let mut config = self.config.lock();
+            config.oidc_issuer_url = Some(issuer_url.clone());
+            config.oidc_client_id = Some(client_id.clone());
+            config.oidc_client_secret = Some(client_secret.clone());
+            config.oidc_allow_emails.clone_from(&allow_emails);
+            config.oidc_allow_domains.clone_from(&allow_domains);
+            config.oidc_scopes.clone_from(&scopes);
+        }
+        register_secret(&client_secret);
         let mut oidc = OidcOptions::new(issuer_url, client_id, client_secret);
         if let Some(allow_emails) = allow_emails {
             allow_emails.iter().for_each(|v| {
@@ -242,6 +313,7 @@ impl HttpListenerBuilder {
     pub fn pooling_enabled(&mut self, pooling_enabled: bool) -> &Self {
         let mut builder = self.listener_builder.lock();
         builder.pooling_enabled(pooling_enabled);
+        self.config.lock().pooling_enabled = Some(pooling_enabled);
         self
     }
 
@@ -252,8 +324,12 @@ impl HttpListenerBuilder {
     /// [Webhook Verification]: https://ngrok.com/docs/cloud-edge/modules/webhook-verification/
     #[napi]
     pub fn webhook_verification(&mut self, provider: String, secret: String) -> &Self {
+        register_secret(&secret);
         let mut builder = self.listener_builder.lock();
-        builder.webhook_verification(provider, secret);
+        builder.webhook_verification(provider.clone(), secret.clone());
+        let mut config = self.config.lock();
+        config.verify_webhook_provider = Some(provider);
+        config.verify_webhook_secret = Some(secret);
         self
     }
 }
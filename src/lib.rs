@@ -3,36 +3,48 @@ use napi::{
     Status,
 };
 use ngrok::prelude::Error as NgrokError;
+use redact::redact;
 
 pub mod config;
 pub mod connect;
+pub mod events;
+pub(crate) mod forwarding;
 pub mod http;
 pub mod listener;
 pub mod listener_builder;
+pub mod log_sinks;
 pub mod logging;
+pub(crate) mod pinning;
+pub mod policy_eval;
+pub mod redact;
 pub mod session;
+pub mod shutdown;
 pub mod tcp;
+#[cfg(feature = "telemetry")]
+pub mod telemetry;
 pub mod tls;
+pub mod traffic_policy;
 
 pub(crate) fn napi_err(message: impl Into<String>) -> Error {
-    Error::new(Status::GenericFailure, message.into())
+    Error::new(Status::GenericFailure, redact(&message.into()).into_owned())
 }
 
 pub(crate) fn napi_ngrok_err(message: impl Into<String>, err: &impl NgrokError) -> Error {
     let py_err = if let Some(error_code) = err.error_code() {
         Error::new(
             Status::GenericFailure,
-            format!(
+            redact(&format!(
                 "{}: {} error_code: {}",
                 message.into(),
                 err.msg(),
                 error_code
-            ),
+            ))
+            .into_owned(),
         )
     } else {
         Error::new(
             Status::GenericFailure,
-            format!("{}: {}", message.into(), err.msg()),
+            redact(&format!("{}: {}", message.into(), err.msg())).into_owned(),
         )
     };
     py_err
@@ -3,7 +3,9 @@ use std::{
     collections::HashMap,
     error::Error as StdError,
     io,
+    iter,
     sync::Arc,
+    time::Duration,
 };
 
 use async_trait::async_trait;
@@ -12,7 +14,6 @@ use lazy_static::lazy_static;
 use napi::bindgen_prelude::*;
 use napi_derive::napi;
 use ngrok::{
-    forwarder::Forwarder,
     prelude::*,
     session::ConnectError,
     tunnel::{
@@ -23,20 +24,39 @@ use ngrok::{
     },
     Session,
 };
+use parking_lot::Mutex as SyncMutex;
 use regex::Regex;
 use tokio::{
     sync::Mutex,
     task::JoinHandle,
+    time::timeout,
 };
 use tracing::{
     debug,
     info,
+    info_span,
+    warn,
+    Instrument,
+    Span,
 };
 use url::Url;
 
+#[cfg(feature = "telemetry")]
+use crate::telemetry::record_listeners;
 use crate::{
+    config::Config,
+    connect::listen_with_config,
+    events::{
+        emit_session_event,
+        SessionEvent,
+    },
+    forwarding::{
+        next_conn,
+        ConnTracker,
+    },
     napi_err,
     napi_ngrok_err,
+    session::session_span,
 };
 
 // no forward host section to allow for relative unix paths
@@ -52,8 +72,20 @@ lazy_static! {
 
 /// Stores the listener and session references to be kept until explicit close.
 struct Storage {
-    listener: Option<Arc<Mutex<dyn ExtendedListener>>>,
-    forwarder: Option<Arc<Mutex<dyn ExtendedForwarder>>>,
+    listener: Arc<Mutex<dyn ExtendedListener>>,
+    forwarder: Mutex<Option<JoinHandle<Result<()>>>>,
+    conns: ConnTracker,
+    session: Session,
+    tun_meta: Arc<ListenerMetadata>,
+    // how the listener was built, and where it forwards to, so it can be recreated on restart
+    config: Config,
+    forwarding: SyncMutex<Option<Url>>,
+    // shared with the Listener objects, which are moved over when the listener is recreated
+    current: SyncMutex<Arc<SyncMutex<ListenerRef>>>,
+}
+
+/// The listener a Listener object refers to.
+struct ListenerRef {
     session: Session,
     tun_meta: Arc<ListenerMetadata>,
 }
@@ -65,17 +97,38 @@ struct ListenerMetadata {
     url: Option<String>,
     proto: Option<String>,
     labels: HashMap<String, String>,
+    // nested in the session's span, and the parent of each forwarded connection's span
+    span: Span,
 }
 
 /// The upstream cannot be turned into an object since it contains generics, so implementing
 /// a proxy trait without generics which can be the dyn type stored in the global map.
 #[async_trait]
-pub trait ExtendedListener: Send {
-    async fn fwd(&mut self, url: Url) -> CoreResult<(), io::Error>;
+pub(crate) trait ExtendedListener: Send {
+    async fn fwd(&mut self, url: Url, conns: &ConnTracker) -> CoreResult<(), io::Error>;
 }
 
-pub trait ExtendedForwarder: Send {
-    fn get_join(&mut self) -> &mut JoinHandle<CoreResult<(), Box<dyn StdError + Send + Sync>>>;
+/// Options for {@link Listener.drain}.
+///
+/// @group Listener and Sessions
+#[napi(object)]
+pub struct DrainOptions {
+    /// How long to wait in milliseconds for active connections to finish before cutting them off.
+    /// Waits indefinitely if not set, which never resolves while a connection stays open.
+    pub timeout: Option<u32>,
+}
+
+/// The outcome of closing one of the listeners in {@link disconnect}.
+///
+/// @group Listener and Sessions
+#[napi(object)]
+pub struct CloseResult {
+    /// The id of the listener.
+    pub id: String,
+    /// The url of the listener, if it has one.
+    pub url: Option<String>,
+    /// Why the listener could not be closed, absent if it was closed.
+    pub error: Option<String>,
 }
 
 /// An ngrok listener.
@@ -84,8 +137,10 @@ pub trait ExtendedForwarder: Send {
 #[napi(custom_finalize)]
 #[allow(dead_code)]
 pub struct Listener {
-    session: Session,
-    tun_meta: Arc<ListenerMetadata>,
+    current: Arc<SyncMutex<ListenerRef>>,
+    // the listeners started along with this one for the other schemes of a forward() config,
+    // which are forwarded, joined, closed and drained together with it
+    group: Vec<Arc<SyncMutex<ListenerRef>>>,
 }
 
 macro_rules! make_listener_type {
@@ -99,8 +154,21 @@ macro_rules! make_listener_type {
         #[napi]
         #[allow(dead_code)]
         impl $wrapper {
-            pub(crate) async fn new_listener(session: Session, raw_listener: $listener) -> Listener {
+            pub(crate) async fn new_listener(
+                session: Session,
+                raw_listener: $listener,
+                config: Config,
+            ) -> Listener {
                 let id = raw_listener.id().to_string();
+                let span = info_span!(
+                    parent: &session_span(&session.id()),
+                    "listener",
+                    listener_id = %id,
+                    url = %raw_listener.url(),
+                );
+                span.in_scope(|| {
+                    info!(listener_id = %id, url = %raw_listener.url(), "Created listener {id:?} with url {:?}", raw_listener.url())
+                });
                 let tun_meta = Arc::new(ListenerMetadata {
                     id: id.clone(),
                     forwards_to: raw_listener.forwards_to().to_string(),
@@ -108,41 +176,9 @@ macro_rules! make_listener_type {
                     url: Some(raw_listener.url().to_string()),
                     proto: Some(raw_listener.proto().to_string()),
                     labels: HashMap::new(),
+                    span,
                 });
-                info!("Created listener {id:?} with url {:?}", raw_listener.url());
-                // keep a listener reference until an explicit call to close to prevent nodejs gc dropping it
-                let storage = Arc::new(Storage {
-                    listener: Some(Arc::new(Mutex::new(raw_listener))),
-                    forwarder: None,
-                    session,
-                    tun_meta,
-                });
-                GLOBAL_LISTENERS.lock().await.insert(id, storage.clone());
-                // create the user-facing object
-                Listener::from_storage(&storage)
-            }
-
-            pub(crate) async fn new_forwarder(session: Session, forwarder: Forwarder<$listener>) -> Listener {
-                let id = forwarder.id().to_string();
-                let tun_meta = Arc::new(ListenerMetadata {
-                    id: id.clone(),
-                    forwards_to: forwarder.forwards_to().to_string(),
-                    metadata: forwarder.metadata().to_string(),
-                    url: Some(forwarder.url().to_string()),
-                    proto: Some(forwarder.proto().to_string()),
-                    labels: HashMap::new(),
-                });
-                info!("Created listener {id:?} with url {:?}", forwarder.url());
-                // keep a listener reference until an explicit call to close to prevent python gc dropping it
-                let storage = Arc::new(Storage {
-                    listener: None,
-                    forwarder: Some(Arc::new(Mutex::new(forwarder))),
-                    session,
-                    tun_meta,
-                });
-                GLOBAL_LISTENERS.lock().await.insert(id, storage.clone());
-                // create the user-facing object
-                Listener::from_storage(&storage)
+                store_listener(Arc::new(Mutex::new(raw_listener)), session, tun_meta, config).await
             }
         }
 
@@ -158,8 +194,20 @@ macro_rules! make_listener_type {
         #[napi]
         #[allow(dead_code)]
         impl $wrapper {
-            pub(crate) async fn new_listener(session: Session, raw_listener: $listener) -> Listener {
+            pub(crate) async fn new_listener(
+                session: Session,
+                raw_listener: $listener,
+                config: Config,
+            ) -> Listener {
                 let id = raw_listener.id().to_string();
+                let span = info_span!(
+                    parent: &session_span(&session.id()),
+                    "listener",
+                    listener_id = %id,
+                );
+                span.in_scope(|| {
+                    info!(listener_id = %id, "Created listener {id:?} with labels {:?}", raw_listener.labels())
+                });
                 let tun_meta = Arc::new(ListenerMetadata {
                     id: id.clone(),
                     forwards_to: raw_listener.forwards_to().to_string(),
@@ -167,41 +215,9 @@ macro_rules! make_listener_type {
                     url: None,
                     proto: None,
                     labels: raw_listener.labels().clone(),
+                    span,
                 });
-                info!("Created listener {id:?} with labels {:?}", tun_meta.labels);
-                // keep a listener reference until an explicit call to close to prevent nodejs gc dropping it
-                let storage = Arc::new(Storage {
-                    listener: Some(Arc::new(Mutex::new(raw_listener))),
-                    forwarder: None,
-                    session,
-                    tun_meta,
-                });
-                GLOBAL_LISTENERS.lock().await.insert(id, storage.clone());
-                // create the user-facing object
-                Listener::from_storage(&storage)
-            }
-
-            pub(crate) async fn new_forwarder(session: Session, forwarder: Forwarder<$listener>) -> Listener {
-                let id = forwarder.id().to_string();
-                let tun_meta = Arc::new(ListenerMetadata {
-                    id: id.clone(),
-                    forwards_to: forwarder.forwards_to().to_string(),
-                    metadata: forwarder.metadata().to_string(),
-                    url: None,
-                    proto: None,
-                    labels: forwarder.labels().clone(),
-                });
-                info!("Created listener {id:?} with labels {:?}", tun_meta.labels);
-                // keep a listener reference until an explicit call to close to prevent python gc dropping it
-                let storage = Arc::new(Storage {
-                    listener: None,
-                    forwarder: Some(Arc::new(Mutex::new(forwarder))),
-                    session,
-                    tun_meta,
-                });
-                GLOBAL_LISTENERS.lock().await.insert(id, storage.clone());
-                // create the user-facing object
-                Listener::from_storage(&storage)
+                store_listener(Arc::new(Mutex::new(raw_listener)), session, tun_meta, config).await
             }
         }
 
@@ -213,19 +229,48 @@ macro_rules! make_listener_type {
         #[async_trait]
         impl ExtendedListener for $listener {
             #[allow(deprecated)]
-            async fn fwd(&mut self, url: Url) -> CoreResult<(), io::Error> {
-                ngrok::prelude::TunnelExt::forward(self, url).await
-            }
-        }
-
-        impl ExtendedForwarder for Forwarder<$listener> {
-            fn get_join(&mut self) -> &mut JoinHandle<CoreResult<(), Box<dyn StdError + Send + Sync>>> {
-                self.join()
+            async fn fwd(&mut self, url: Url, conns: &ConnTracker) -> CoreResult<(), io::Error> {
+                // each connection is handed to the upstream forwarder on its own, to be tracked
+                while let Some(mut conn) = next_conn(self, &url).await? {
+                    conns.track(TunnelExt::forward(&mut conn, url.clone())).await?;
+                }
+                Ok(())
             }
         }
     };
 }
 
+/// Keep a listener reference until an explicit call to close to prevent nodejs gc dropping it,
+/// returning the user-facing object.
+async fn store_listener(
+    listener: Arc<Mutex<dyn ExtendedListener>>,
+    session: Session,
+    tun_meta: Arc<ListenerMetadata>,
+    config: Config,
+) -> Listener {
+    let current = Arc::new(SyncMutex::new(ListenerRef {
+        session: session.clone(),
+        tun_meta: tun_meta.clone(),
+    }));
+    let storage = Arc::new(Storage {
+        listener,
+        forwarder: Mutex::new(None),
+        conns: ConnTracker::default(),
+        session,
+        tun_meta: tun_meta.clone(),
+        config,
+        forwarding: SyncMutex::new(None),
+        current: SyncMutex::new(current),
+    });
+    GLOBAL_LISTENERS
+        .lock()
+        .await
+        .insert(tun_meta.id.clone(), storage.clone());
+    #[cfg(feature = "telemetry")]
+    record_listeners(1);
+    Listener::from_storage(&storage)
+}
+
 #[napi]
 #[allow(dead_code)]
 impl Listener {
@@ -233,33 +278,76 @@ impl Listener {
     fn from_storage(storage: &Arc<Storage>) -> Listener {
         // create the user-facing object
         Listener {
-            session: storage.session.clone(),
-            tun_meta: storage.tun_meta.clone(),
+            current: storage.current.lock().clone(),
+            group: vec![],
         }
     }
 
+    /// Combine listeners into the first of them, with the rest following its forward, join,
+    /// close and drain calls.
+    pub(crate) fn grouped(listeners: Vec<Listener>) -> Option<Listener> {
+        let mut listeners = listeners.into_iter();
+        let first = listeners.next()?;
+        Some(Listener {
+            current: first.current,
+            group: listeners.map(|l| l.current).collect(),
+        })
+    }
+
+    /// This listener and those grouped with it, each on its own.
+    fn members(&self) -> Vec<Listener> {
+        iter::once(&self.current)
+            .chain(&self.group)
+            .map(|current| Listener {
+                current: current.clone(),
+                group: vec![],
+            })
+            .collect()
+    }
+
+    /// The metadata of the listener this object currently refers to.
+    fn tun_meta(&self) -> Arc<ListenerMetadata> {
+        self.current.lock().tun_meta.clone()
+    }
+
     /// The URL that this listener backs.
     #[napi]
     pub fn url(&self) -> Option<String> {
-        self.tun_meta.url.clone()
+        self.tun_meta().url.clone()
+    }
+
+    /// The URLs of this listener and of those started along with it, such as when
+    /// {@link forward} is given several `schemes`.
+    #[napi]
+    pub fn urls(&self) -> Vec<String> {
+        self.members().iter().filter_map(|l| l.url()).collect()
+    }
+
+    /// This listener and those started along with it, such as when {@link forward} is given
+    /// several `schemes`, as separate listeners. The {@link forward}, {@link join}, {@link close}
+    /// and {@link drain} methods of this listener apply to all of them.
+    #[napi]
+    pub fn listeners(&self) -> Vec<Listener> {
+        self.members()
     }
 
     /// The protocol of the endpoint that this listener backs.
     #[napi]
     pub fn proto(&self) -> Option<String> {
-        self.tun_meta.proto.clone()
+        self.tun_meta().proto.clone()
     }
 
     /// The labels this listener was started with.
     #[napi]
     pub fn labels(&self) -> HashMap<String, String> {
-        self.tun_meta.labels.clone()
+        self.tun_meta().labels.clone()
     }
 
     /// Returns a listener's unique ID.
+    /// The ID changes if the listener is recreated by {@link SessionBuilder.restartListeners}.
     #[napi]
     pub fn id(&self) -> String {
-        self.tun_meta.id.clone()
+        self.tun_meta().id.clone()
     }
 
     /// Returns a human-readable string presented in the ngrok dashboard
@@ -273,13 +361,13 @@ impl Listener {
     /// on the Listener Builder. These methods will also set this `forwardsTo` value.
     #[napi]
     pub fn forwards_to(&self) -> String {
-        self.tun_meta.forwards_to.clone()
+        self.tun_meta().forwards_to.clone()
     }
 
     /// Returns the arbitrary metadata string for this listener.
     #[napi]
     pub fn metadata(&self) -> String {
-        self.tun_meta.metadata.clone()
+        self.tun_meta().metadata.clone()
     }
 
     /// Forward incoming listener connections. This can be either a TCP address or a file socket path.
@@ -287,20 +375,25 @@ impl Listener {
     ///     On Windows, addr can be a named pipe, e.e. "\\\\.\\pipe\\an_ngrok_pipe
     #[napi]
     pub async fn forward(&self, addr: String) -> Result<()> {
-        forward(&self.tun_meta.id, addr).await
+        // each member forwards until it is closed, so they have to run side by side
+        let ids: Vec<String> = self.members().iter().map(Listener::id).collect();
+        future::try_join_all(ids.iter().map(|id| forward(id, addr.clone()))).await?;
+        Ok(())
     }
 
     /// Wait for the forwarding task to exit.
     #[napi]
     pub async fn join(&self) -> Result<()> {
-        let id = self.tun_meta.id.clone();
-        let forwarder_option = &get_storage_by_id(&id).await?.forwarder;
-        if let Some(forwarder_mutex) = forwarder_option {
-            forwarder_mutex
-                .lock()
-                .await
-                .get_join()
-                .fuse()
+        future::try_join_all(self.members().iter().map(|l| l.join_one())).await?;
+        Ok(())
+    }
+
+    async fn join_one(&self) -> Result<()> {
+        let id = self.tun_meta().id.clone();
+        let storage = get_storage_by_id(&id).await?;
+        let mut forwarder = storage.forwarder.lock().await;
+        if let Some(join) = forwarder.as_mut() {
+            join.fuse()
                 .await
                 .map_err(|e| napi_err(format!("error on join: {e:?}")))?
                 .map_err(|e| napi_err(format!("error on join: {e:?}")))
@@ -316,27 +409,105 @@ impl Listener {
     /// listener's ID.
     #[napi]
     pub async fn close(&self) -> Result<()> {
-        debug!("Listener closing, id: {}", self.tun_meta.id);
+        // close every member, even if one fails
+        let results = future::join_all(self.members().iter().map(|l| l.close_one())).await;
+        results.into_iter().collect()
+    }
+
+    async fn close_one(&self) -> Result<()> {
+        let (session, id, span) = {
+            let current = self.current.lock();
+            (
+                current.session.clone(),
+                current.tun_meta.id.clone(),
+                current.tun_meta.span.clone(),
+            )
+        };
+        span.in_scope(|| debug!("Listener closing, id: {}", id));
 
         // we may not be able to lock our reference to the listener due to the forward_* calls which
         // continuously accept-loop while the listener is active, so calling close on the Session.
-        let res = self
-            .session
-            .close_tunnel(self.tun_meta.id.clone())
+        let res = session
+            .close_tunnel(id.clone())
+            .instrument(span)
             .await
             .map_err(|e| napi_ngrok_err("error closing listener", &e));
 
         // drop our internal reference to the listener after awaiting close
-        GLOBAL_LISTENERS.lock().await.remove(&self.tun_meta.id);
+        remove_global_listener(&id).await;
 
+        if res.is_ok() {
+            emit_session_event(&session.id(), SessionEvent::listener_closed(id));
+        }
         res
     }
+
+    /// Stop accepting new connections, then close the listener once the active
+    /// forwarded connections have finished, or the timeout has passed.
+    /// Without a timeout this waits for as long as any connection stays open,
+    /// so long-lived connections such as websockets can keep it from resolving.
+    /// Resolves to the number of connections which were cut off by the timeout.
+    #[napi]
+    pub async fn drain(&self, options: Option<DrainOptions>) -> Result<u32> {
+        let wait = options
+            .and_then(|o| o.timeout)
+            .map(|t| Duration::from_millis(t.into()));
+        let mut storages = vec![];
+        for member in self.members() {
+            storages.push(get_storage_by_id(&member.id()).await?);
+        }
+        // finish draining every member, even if one fails, then report the first error
+        let results = future::join_all(storages.into_iter().map(|s| drain_storage(s, wait))).await;
+        let cut_off: Vec<u32> = results.into_iter().collect::<Result<_>>()?;
+        Ok(cut_off.into_iter().sum())
+    }
+}
+
+async fn drain_storage(storage: Arc<Storage>, wait: Option<Duration>) -> Result<u32> {
+    let span = storage.tun_meta.span.clone();
+    async move {
+        let id = storage.tun_meta.id.clone();
+        debug!("Listener draining, id: {id}");
+
+        // unlisten so no new connections arrive, while active ones stay open on the session
+        storage
+            .session
+            .close_tunnel(&id)
+            .await
+            .map_err(|e| napi_ngrok_err("error closing listener", &e))?;
+        // the forwarding loop holds the listener until it sees the close
+        drop(storage.listener.lock().await);
+
+        let cut_off = storage.conns.drain(wait).await;
+        if cut_off > 0 {
+            info!("Listener {id:?} drained, {cut_off} connections cut off");
+        }
+
+        remove_global_listener(&id).await;
+        emit_session_event(&storage.session.id(), SessionEvent::listener_closed(id));
+        Ok(cut_off)
+    }
+    .instrument(span)
+    .await
+}
+
+/// Drain all listeners concurrently, returning how many connections were cut off in total.
+pub(crate) async fn drain_listeners(wait: Option<Duration>) -> u32 {
+    let draining: Vec<Arc<Storage>> = GLOBAL_LISTENERS.lock().await.values().cloned().collect();
+    let mut cut_off = 0;
+    for res in future::join_all(draining.into_iter().map(|s| drain_storage(s, wait))).await {
+        match res {
+            Ok(count) => cut_off += count,
+            Err(e) => warn!("{}", e.reason),
+        }
+    }
+    cut_off
 }
 
 #[allow(unused_mut)]
 impl ObjectFinalize for Listener {
     fn finalize(mut self, _env: Env) -> Result<()> {
-        debug!("Listener finalize, id: {}", self.tun_meta.id);
+        debug!("Listener finalize, id: {}", self.tun_meta().id);
         Ok(())
     }
 }
@@ -367,8 +538,8 @@ make_listener_type! {
 }
 
 pub async fn forward(id: &String, mut addr: String) -> Result<()> {
-    let tun_option = &get_storage_by_id(id).await?.listener;
-    if let Some(tun) = tun_option {
+    let storage = get_storage_by_id(id).await?;
+    if storage.forwarder.lock().await.is_none() {
         // if addr is not a full url, choose a default protocol
         lazy_static! {
             static ref RE: Regex = Regex::new(r"^[a-z0-9\-\.]+:\d+$").unwrap();
@@ -384,14 +555,39 @@ pub async fn forward(id: &String, mut addr: String) -> Result<()> {
         let url = Url::parse(addr.as_str())
             .map_err(|e| napi_err(format!("Cannot parse address: {addr}, error: {e}")))?;
 
-        info!("Listener {id:?} forwarding to {:?}", url.to_string());
-        let res = tun.lock().await.fwd(url).await;
+        forward_url(&storage, url).await
+    } else {
+        Err(napi_err("listener is not forwardable"))
+    }
+}
+
+/// Forward the listener's connections to the url in the background, which can be joined on.
+pub(crate) async fn start_forwarding(id: &String, url: Url) -> Result<()> {
+    let storage = get_storage_by_id(id).await?;
+    let mut forwarder = storage.forwarder.lock().await;
+    let fwd_storage = storage.clone();
+    forwarder.replace(tokio::spawn(
+        async move { forward_url(&fwd_storage, url).await },
+    ));
+    Ok(())
+}
+
+async fn forward_url(storage: &Storage, url: Url) -> Result<()> {
+    let span = storage.tun_meta.span.clone();
+    async move {
+        info!(
+            "Listener {:?} forwarding to {:?}",
+            storage.tun_meta.id,
+            url.to_string()
+        );
+        storage.forwarding.lock().replace(url.clone());
+        let res = storage.listener.lock().await.fwd(url, &storage.conns).await;
 
         debug!("forward returning");
         canceled_is_ok(res)
-    } else {
-        Err(napi_err("listener is not forwardable"))
     }
+    .instrument(span)
+    .await
 }
 
 fn canceled_is_ok(input: CoreResult<(), io::Error>) -> Result<()> {
@@ -426,32 +622,128 @@ async fn get_storage_by_id(id: &String) -> Result<Arc<Storage>> {
 
 /// Delete any reference to the listener id
 pub(crate) async fn remove_global_listener(id: &String) {
-    GLOBAL_LISTENERS.lock().await.remove(id);
-}
-
-/// Close a listener with the given url, or all listeners if no url is defined.
-pub(crate) async fn close_url(url: Option<String>) -> Result<()> {
-    let mut close_ids: Vec<String> = vec![];
-    let listeners = GLOBAL_LISTENERS.lock().await;
-    for (id, storage) in listeners.iter() {
-        debug!("listener: {}", id);
-        if url.as_ref().is_none() || url == storage.tun_meta.url {
-            debug!("closing listener: {}", id);
-            storage
-                .session
-                .close_tunnel(id)
+    if GLOBAL_LISTENERS.lock().await.remove(id).is_some() {
+        #[cfg(feature = "telemetry")]
+        record_listeners(-1);
+    }
+}
+
+/// Close listeners with the given url, or all listeners if no url is defined.
+/// The listeners are closed concurrently, each giving up after the timeout if one is given.
+pub(crate) async fn close_url(url: Option<String>, wait: Option<Duration>) -> Vec<CloseResult> {
+    // collect the listeners to close so the global lock isn't held across the close rpcs
+    let closing: Vec<Arc<Storage>> = GLOBAL_LISTENERS
+        .lock()
+        .await
+        .values()
+        .filter(|storage| url.is_none() || url == storage.tun_meta.url)
+        .cloned()
+        .collect();
+
+    future::join_all(
+        closing
+            .into_iter()
+            .map(|storage| close_storage(storage, wait)),
+    )
+    .await
+}
+
+async fn close_storage(storage: Arc<Storage>, wait: Option<Duration>) -> CloseResult {
+    let span = storage.tun_meta.span.clone();
+    async move {
+        let id = storage.tun_meta.id.clone();
+        debug!("closing listener: {}", id);
+        let close = storage.session.close_tunnel(&id);
+        let res = match wait {
+            Some(wait) => match timeout(wait, close).await {
+                Ok(res) => res.map_err(|e| napi_ngrok_err("error closing listener", &e)),
+                Err(_) => Err(napi_err(format!(
+                    "timed out closing listener after {}ms",
+                    wait.as_millis()
+                ))),
+            },
+            None => close
                 .await
-                .map_err(|e| napi_ngrok_err("error closing listener", &e))?;
-            close_ids.push(id.clone());
+                .map_err(|e| napi_ngrok_err("error closing listener", &e)),
+        };
+
+        // remove references entirely, only once the listener is known to be closed
+        if res.is_ok() {
+            remove_global_listener(&id).await;
+            emit_session_event(
+                &storage.session.id(),
+                SessionEvent::listener_closed(id.clone()),
+            );
+        }
+        CloseResult {
+            id,
+            url: storage.tun_meta.url.clone(),
+            error: res.err().map(|e| e.reason),
         }
     }
-    drop(listeners); // unlock GLOBAL_LISTENERS
+    .instrument(span)
+    .await
+}
 
-    // remove references entirely
-    for id in close_ids {
-        remove_global_listener(&id).await;
+/// Recreate the listeners of a restarted session on its new connection, from the configuration
+/// they were built with. Their Listener objects are moved over, and any forwarding resumed.
+pub(crate) async fn recreate_listeners(old_session_id: &String, session: &crate::session::Session) {
+    let restarting: Vec<Arc<Storage>> = GLOBAL_LISTENERS
+        .lock()
+        .await
+        .values()
+        .filter(|storage| storage.session.id() == *old_session_id)
+        .cloned()
+        .collect();
+
+    for storage in restarting {
+        let old_id = storage.tun_meta.id.clone();
+        // listen on the new session first, so the endpoint stays up while it moves over
+        let mut listened = listen_with_config(session, &storage.config).await;
+        // release the endpoint on the old session, and listen again if it couldn't be bound twice
+        if let Err(e) = storage.session.close_tunnel(&old_id).await {
+            warn!("Failed to close listener {old_id:?} for restart: {e}");
+        }
+        remove_global_listener(&old_id).await;
+        if let Err(ref e) = listened {
+            debug!("Listening again after closing {old_id:?}: {}", e.reason);
+            listened = listen_with_config(session, &storage.config).await;
+        }
+
+        let recreated = match listened {
+            Ok(id) => get_storage_by_id(&id).await,
+            Err(e) => Err(e),
+        };
+        let recreated = match recreated {
+            Ok(recreated) => recreated,
+            Err(e) => {
+                warn!("Failed to recreate listener {old_id:?}: {}", e.reason);
+                emit_session_event(&session.id(), SessionEvent::listener_closed(old_id));
+                continue;
+            }
+        };
+        let new_id = recreated.tun_meta.id.clone();
+        info!("Listener {old_id:?} recreated as {new_id:?}");
+
+        // point the existing Listener objects at the new listener
+        let current = storage.current.lock().clone();
+        *current.lock() = ListenerRef {
+            session: recreated.session.clone(),
+            tun_meta: recreated.tun_meta.clone(),
+        };
+        *recreated.current.lock() = current;
+
+        let forwarding = storage.forwarding.lock().clone();
+        if let Some(url) = forwarding {
+            if let Err(e) = start_forwarding(&new_id, url).await {
+                warn!("Failed to resume forwarding for {new_id:?}: {}", e.reason);
+            }
+        }
+        emit_session_event(
+            &recreated.session.id(),
+            SessionEvent::listener_restarted(new_id, old_id),
+        );
     }
-    Ok(())
 }
 
 /// Make a list of all listeners by iterating over the global listener map and creating an Listener from each.
@@ -477,6 +769,12 @@ pub(crate) async fn search_listeners(
     listeners
 }
 
+/// The number of open listeners.
+#[cfg(feature = "telemetry")]
+pub(crate) async fn listener_count() -> usize {
+    GLOBAL_LISTENERS.lock().await.len()
+}
+
 /// Retrieve a list of non-closed listeners, in no particular order.
 #[napi]
 pub async fn listeners() -> Vec<Listener> {
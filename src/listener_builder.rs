@@ -14,7 +14,13 @@ use tracing::debug;
 use url::Url;
 
 use crate::{
+    config::{
+        Config,
+        DEFAULT_ENV_DELIMITER,
+        DEFAULT_ENV_PREFIX,
+    },
     listener::{
+        start_forwarding,
         HttpListener,
         LabeledListener,
         Listener,
@@ -23,16 +29,19 @@ use crate::{
     },
     napi_err,
     napi_ngrok_err,
+    traffic_policy::load_traffic_policy_file,
 };
 
 macro_rules! make_listener_builder {
-    ($(#[$outer:meta])* $wrapper:ident, $builder:tt, $listener:tt, $mode:tt) => {
+    ($(#[$outer:meta])* $wrapper:ident, $builder:tt, $listener:tt, $mode:tt, $proto:literal) => {
         $(#[$outer])*
         #[napi(custom_finalize)]
         #[allow(dead_code)]
         pub(crate) struct $wrapper {
             session: Arc<Mutex<Session>>,
             pub(crate) listener_builder: Arc<Mutex<$builder>>,
+            // everything set on the builder, so the listener can be recreated on another session
+            pub(crate) config: Arc<Mutex<Config>>,
         }
 
         #[napi]
@@ -42,6 +51,10 @@ macro_rules! make_listener_builder {
                 $wrapper {
                     session: Arc::new(Mutex::new(session)),
                     listener_builder: Arc::new(Mutex::new(raw_listener_builder)),
+                    config: Arc::new(Mutex::new(Config {
+                        proto: Some($proto.to_string()),
+                        ..Default::default()
+                    })),
                 }
             }
 
@@ -49,7 +62,8 @@ macro_rules! make_listener_builder {
             #[napi]
             pub fn metadata(&mut self, metadata: String) -> &Self {
                 let mut builder = self.listener_builder.lock();
-                builder.metadata(metadata);
+                builder.metadata(metadata.clone());
+                self.config.lock().metadata = Some(metadata);
                 self
             }
 
@@ -58,14 +72,60 @@ macro_rules! make_listener_builder {
             pub fn verify_upstream_tls(&mut self, verify_upstream_tls: bool) -> &Self {
                 let mut builder = self.listener_builder.lock();
                 builder.verify_upstream_tls(verify_upstream_tls);
+                self.config.lock().verify_upstream_tls = Some(verify_upstream_tls);
                 self
             }
 
+            /// Create an independent copy of this builder and its settings, such as for
+            /// deriving listeners which differ only in a few settings.
+            /// Changes to the copy do not affect this builder, and vice versa.
+            #[napi(js_name = "clone")]
+            pub fn clone_builder(&self) -> Self {
+                $wrapper {
+                    session: Arc::new(Mutex::new(self.session.lock().clone())),
+                    listener_builder: Arc::new(Mutex::new(self.listener_builder.lock().clone())),
+                    config: Arc::new(Mutex::new(self.config.lock().clone())),
+                }
+            }
+
+            /// The settings made on this builder so far, in the form of a {@link Config}.
+            ///
+            /// Secrets are returned in plaintext so the config can be passed back to
+            /// {@link fromConfig}: the basic auth passwords, the OAuth and OIDC client secrets,
+            /// the webhook verification secret, and the TLS termination key. Take care not to
+            /// log or persist the result as-is.
+            #[napi]
+            pub fn to_config(&self) -> Config {
+                self.config.lock().clone()
+            }
+
+            /// Apply the listener settings of a {@link Config}, such as one returned by
+            /// {@link toConfig}, on top of the settings already made on this builder.
+            /// Session settings such as the authtoken are ignored.
+            #[napi]
+            #[allow(clippy::wrong_self_convention)]
+            pub fn from_config(&mut self, mut config: Config) -> Result<&Self> {
+                if config.from_env.unwrap_or(false) {
+                    config.fill_from_env(DEFAULT_ENV_PREFIX, DEFAULT_ENV_DELIMITER)?;
+                }
+                if let Some(ref proto) = config.proto {
+                    if proto != $proto {
+                        return Err(napi_err(format!(
+                            "Config proto {proto:?} does not match the {:?} builder",
+                            $proto
+                        )));
+                    }
+                }
+                self.apply_config(&config)?;
+                Ok(self)
+            }
+
             /// Begin listening for new connections on this listener.
             #[napi]
             pub async fn listen(&self, _bind: Option<bool>) -> Result<Listener> {
                 let session = self.session.lock().clone();
                 let tun = self.listener_builder.lock().clone();
+                let config = self.config.lock().clone();
                 let result = tun
                     .listen()
                     .await
@@ -73,7 +133,7 @@ macro_rules! make_listener_builder {
 
                 // create the wrapping listener object via its async new()
                 match result {
-                    Ok(raw_tun) => Ok($listener::new_listener(session, raw_tun).await),
+                    Ok(raw_tun) => Ok($listener::new_listener(session, raw_tun, config).await),
                     Err(val) => Err(val),
                 }
             }
@@ -84,18 +144,20 @@ macro_rules! make_listener_builder {
             pub async fn listen_and_forward(&self, to_url: String) -> Result<Listener> {
                 let url = Url::parse(&to_url).map_err(|e| napi_err(format!("Url forward argument parse failure, {e}")))?;
                 let session = self.session.lock().clone();
-                let builder = self.listener_builder.lock().clone();
+                let mut builder = self.listener_builder.lock().clone();
+                builder.forwards_to(url.as_str());
+                let mut config = self.config.lock().clone();
+                config.forwards_to = Some(url.to_string());
 
-                let result = builder
-                .listen_and_forward(url)
+                let raw_tun = builder
+                .listen()
                 .await
-                .map_err(|e| napi_ngrok_err("failed to start listener", &e));
+                .map_err(|e| napi_ngrok_err("failed to start listener", &e))?;
 
                 // create the wrapping listener object via its async new()
-                match result {
-                    Ok(raw_fwd) => Ok($listener::new_forwarder(session, raw_fwd).await),
-                    Err(val) => Err(val),
-                }
+                let listener = $listener::new_listener(session, raw_tun, config).await;
+                start_forwarding(&listener.id(), url).await?;
+                Ok(listener)
             }
 
             /// Begin listening for new connections on this listener and forwarding them to the given server.
@@ -128,7 +190,8 @@ macro_rules! make_listener_builder {
             #[napi]
             pub fn allow_cidr(&mut self, cidr: String) -> &Self {
                 let mut builder = self.listener_builder.lock();
-                builder.allow_cidr(cidr);
+                builder.allow_cidr(cidr.clone());
+                self.config.lock().allow_cidr.get_or_insert_with(Vec::new).push(cidr);
                 self
             }
             /// Restriction placed on the origin of incoming connections to the edge to deny these CIDR ranges.
@@ -139,17 +202,18 @@ macro_rules! make_listener_builder {
             #[napi]
             pub fn deny_cidr(&mut self, cidr: String) -> &Self {
                 let mut builder = self.listener_builder.lock();
-                builder.deny_cidr(cidr);
+                builder.deny_cidr(cidr.clone());
+                self.config.lock().deny_cidr.get_or_insert_with(Vec::new).push(cidr);
                 self
             }
             /// The version of PROXY protocol to use with this listener "1", "2", or "" if not using.
             #[napi]
             pub fn proxy_proto(&mut self, proxy_proto: String) -> &Self {
                 let mut builder = self.listener_builder.lock();
-                builder.proxy_proto(
-                    ProxyProto::from_str(proxy_proto.as_str())
-                        .unwrap_or_else(|_| panic!("Unknown proxy protocol: {:?}", proxy_proto)),
-                );
+                let parsed = ProxyProto::from_str(proxy_proto.as_str())
+                    .unwrap_or_else(|_| panic!("Unknown proxy protocol: {:?}", proxy_proto));
+                builder.proxy_proto(parsed);
+                self.config.lock().proxy_proto = Some(proxy_proto);
                 self
             }
             /// Listener backend metadata. Viewable via the dashboard and API, but has no
@@ -161,28 +225,45 @@ macro_rules! make_listener_builder {
             #[napi]
             pub fn forwards_to(&mut self, forwards_to: String) -> &Self {
                 let mut builder = self.listener_builder.lock();
-                builder.forwards_to(forwards_to);
+                builder.forwards_to(forwards_to.clone());
+                self.config.lock().forwards_to = Some(forwards_to);
                 self
             }
             #[napi]
             pub fn policy(&mut self, policy: String) -> &Self {
                 let mut builder = self.listener_builder.lock();
-                builder.traffic_policy(policy);
+                builder.traffic_policy(policy.clone());
+                self.config.lock().traffic_policy = Some(policy);
                 self
             }
-            #[napi]
+            /// The traffic policy for this listener, as a JSON or YAML string, or a {@link TrafficPolicy}.
+            #[napi(ts_args_type = "trafficPolicy: string | TrafficPolicy")]
             pub fn traffic_policy(&mut self, traffic_policy: String) -> &Self {
                 let mut builder = self.listener_builder.lock();
-                builder.traffic_policy(traffic_policy);
+                builder.traffic_policy(traffic_policy.clone());
+                self.config.lock().traffic_policy = Some(traffic_policy);
                 self
             }
+            /// Read the traffic policy for this listener from a JSON or YAML file.
+            /// The policy is checked for unknown phases, and for missing required fields of the
+            /// common action types, before listening. Errors include the line and column of the
+            /// problem. Other action types are left to the ngrok service to check.
+            #[napi]
+            pub fn traffic_policy_file(&mut self, path: String) -> Result<&Self> {
+                let policy = load_traffic_policy_file(&path)?;
+                let mut builder = self.listener_builder.lock();
+                builder.traffic_policy(policy.clone());
+                self.config.lock().traffic_policy = Some(policy);
+                Ok(self)
+            }
             /// Sets the ingress configuration for this endpoint.
             /// Valid values: "public", "internal", "kubernetes"
             /// If not specified, the ngrok service will use its default binding configuration.
             #[napi]
             pub fn binding(&mut self, binding: String) -> &Self {
                 let mut builder = self.listener_builder.lock();
-                builder.binding(binding);
+                builder.binding(binding.clone());
+                self.config.lock().binding = Some(binding);
                 self
             }
         }
@@ -199,7 +280,8 @@ macro_rules! make_listener_builder {
             #[napi]
             pub fn label(&mut self, label: String, value: String) -> &Self {
                 let mut builder = self.listener_builder.lock();
-                builder.label(label, value);
+                builder.label(label.clone(), value.clone());
+                self.config.lock().labels.get_or_insert_with(Vec::new).push(format!("{label}:{value}"));
                 self
             }
 
@@ -207,7 +289,8 @@ macro_rules! make_listener_builder {
             #[napi]
             pub fn app_protocol(&mut self, app_protocol: String) -> &Self {
                 let mut builder = self.listener_builder.lock();
-                builder.app_protocol(app_protocol);
+                builder.app_protocol(app_protocol.clone());
+                self.config.lock().app_protocol = Some(app_protocol);
                 self
             }
         }
@@ -218,23 +301,23 @@ make_listener_builder! {
     /// An ngrok listener backing an HTTP endpoint.
     ///
     /// @group Listener Builders
-    HttpListenerBuilder, HttpTunnelBuilder, HttpListener, common
+    HttpListenerBuilder, HttpTunnelBuilder, HttpListener, common, "http"
 }
 make_listener_builder! {
     /// An ngrok listener backing a TCP endpoint.
     ///
     /// @group Listener Builders
-    TcpListenerBuilder, TcpTunnelBuilder, TcpListener, common
+    TcpListenerBuilder, TcpTunnelBuilder, TcpListener, common, "tcp"
 }
 make_listener_builder! {
     /// An ngrok listener backing a TLS endpoint.
     ///
     /// @group Listener Builders
-    TlsListenerBuilder, TlsTunnelBuilder, TlsListener, common
+    TlsListenerBuilder, TlsTunnelBuilder, TlsListener, common, "tls"
 }
 make_listener_builder! {
     /// A labeled ngrok listener.
     ///
     /// @group Listener Builders
-    LabeledListenerBuilder, LabeledTunnelBuilder, LabeledListener, label
+    LabeledListenerBuilder, LabeledTunnelBuilder, LabeledListener, label, "labeled"
 }
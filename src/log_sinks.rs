@@ -0,0 +1,242 @@
+use std::{
+    fs::{
+        self,
+        File,
+        OpenOptions,
+    },
+    io::{
+        self,
+        IsTerminal,
+        Write,
+    },
+    path::PathBuf,
+    sync::Mutex,
+    time::{
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use tracing_subscriber::{
+    fmt::{
+        self,
+        MakeWriter,
+    },
+    Layer,
+};
+
+use crate::{
+    logging::{
+        init_subscriber,
+        set_sink,
+        Sink,
+    },
+    napi_err,
+    redact::redact,
+};
+
+const DEFAULT_MAX_FILES: u32 = 5;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Options for {@link logToStderr} and {@link logToFile}.
+///
+/// @group Functions
+#[napi(object)]
+#[derive(Default)]
+pub struct LogSinkOptions {
+    /// The format of each line, one of "full", "compact", "pretty", or "json". Defaults to "full".
+    pub format: Option<String>,
+    /// Whether to start each line with a timestamp, defaults to true.
+    pub timestamps: Option<bool>,
+    /// Whether to use ANSI colors. Defaults to true for stderr if it is a terminal, false otherwise.
+    pub ansi: Option<bool>,
+    /// The log level of this sink, or `RUST_LOG` style directives, which leaves the level of
+    /// the other sinks and the callbacks as is. Follows {@link setLogLevel} if not set.
+    pub level: Option<String>,
+    /// For files, start a new file once the current one would exceed this many bytes.
+    pub max_size: Option<u32>,
+    /// For files, start a new file each day, in UTC.
+    pub rotate_daily: Option<bool>,
+    /// For files, how many previous files to keep, named with suffixes ".1" (newest) to ".N". Defaults to 5.
+    pub max_files: Option<u32>,
+}
+
+/// Write log events to stderr natively, without passing them through javascript,
+/// so they are written even while the nodejs event loop is blocked.
+/// Calling this again replaces the previous options.
+#[napi]
+pub fn log_to_stderr(options: Option<LogSinkOptions>) -> Result<()> {
+    let options = options.unwrap_or_default();
+    init_subscriber(None)?;
+    let ansi = options.ansi.unwrap_or_else(|| io::stderr().is_terminal());
+    let sink = sink(io::stderr, &options, ansi)?;
+    set_sink("stderr", Some(sink), options.level.as_deref())
+}
+
+/// Write log events to the file at the given path natively, without passing them through
+/// javascript, rotating it by size or by day if configured.
+/// Calling this again with the same path replaces the previous options.
+#[napi]
+pub fn log_to_file(path: String, options: Option<LogSinkOptions>) -> Result<()> {
+    let options = options.unwrap_or_default();
+    init_subscriber(None)?;
+    let file = RotatingFile::open(
+        PathBuf::from(&path),
+        options.max_size.map(u64::from),
+        options.rotate_daily.unwrap_or(false),
+        options.max_files.unwrap_or(DEFAULT_MAX_FILES),
+    )
+    .map_err(|e| napi_err(format!("failed to open log file {path}: {e}")))?;
+    let ansi = options.ansi.unwrap_or(false);
+    let sink = sink(Mutex::new(file), &options, ansi)?;
+    set_sink(&path, Some(sink), options.level.as_deref())
+}
+
+/// Stop writing log events to stderr, if "stderr" is given, or to the file at the given path.
+#[napi]
+pub fn remove_log_sink(sink: String) -> Result<()> {
+    set_sink(&sink, None, None)
+}
+
+fn sink<W>(writer: W, options: &LogSinkOptions, ansi: bool) -> Result<Sink>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer().with_writer(Redacting(writer)).with_ansi(ansi);
+    let timestamps = options.timestamps.unwrap_or(true);
+    // each combination is its own type, so they are boxed to be stored together
+    macro_rules! with_timestamps {
+        ($layer:expr) => {
+            if timestamps {
+                $layer.boxed()
+            } else {
+                $layer.without_time().boxed()
+            }
+        };
+    }
+    Ok(match options.format.as_deref().unwrap_or("full") {
+        "full" => with_timestamps!(layer),
+        "compact" => with_timestamps!(layer.compact()),
+        "pretty" => with_timestamps!(layer.pretty()),
+        "json" => with_timestamps!(layer.json().with_span_list(true)),
+        format => {
+            return Err(napi_err(format!(
+            "Unknown log format {format:?}, expected \"full\", \"compact\", \"pretty\" or \"json\""
+        )))
+        }
+    })
+}
+
+/// Masks secrets in each formatted event before it is written.
+struct Redacting<M>(M);
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacting<M> {
+    type Writer = Redacting<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        Redacting(self.0.make_writer())
+    }
+}
+
+impl<W: Write> Write for Redacting<W> {
+    // the formatter writes each event in a single call, so secrets are never split
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.0.write_all(redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// A log file which moves itself aside when it gets too large, or a new day starts.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    day: u64,
+    max_size: Option<u64>,
+    daily: bool,
+    max_files: u32,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_size: Option<u64>, daily: bool, max_files: u32) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let metadata = file.metadata()?;
+        // an existing file is rotated on the first write if it is from an earlier day
+        let day = metadata.modified().map(day_of).unwrap_or_else(|_| today());
+        Ok(RotatingFile {
+            path,
+            file,
+            size: metadata.len(),
+            day,
+            max_size,
+            daily,
+            max_files,
+        })
+    }
+
+    fn rotated_path(&self, index: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        path.into()
+    }
+
+    /// Shift the previous files along, dropping the oldest, then start a new file.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated_path(self.max_files));
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    fs::rename(from, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        self.day = today();
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let too_large = self
+            .max_size
+            .is_some_and(|max| self.size > 0 && self.size + buf.len() as u64 > max);
+        let new_day = self.daily && today() != self.day;
+        if too_large || new_day {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn day_of(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / SECONDS_PER_DAY)
+        .unwrap_or_default()
+}
+
+fn today() -> u64 {
+    day_of(SystemTime::now())
+}
@@ -1,3 +1,11 @@
+use std::{
+    collections::HashMap,
+    time::{
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+
 use lazy_static::lazy_static;
 use napi::{
     bindgen_prelude::*,
@@ -10,58 +18,324 @@ use napi::{
 };
 use napi_derive::napi;
 use parking_lot::Mutex;
-use tracing::metadata::LevelFilter;
+use serde_json::{
+    json,
+    Value,
+};
+use tracing::{
+    field::Field,
+    metadata::LevelFilter,
+    span,
+};
 use tracing_subscriber::{
+    layer::{
+        Context,
+        Layered,
+    },
     prelude::*,
+    registry::LookupSpan,
+    reload,
+    EnvFilter,
     Layer,
+    Registry,
 };
 
-use crate::napi_err;
+use crate::{
+    napi_err,
+    redact::redact,
+};
 
 // keep a reference to the javascript logging callback function.
 // uses synchronous parking_lot mutex to avoid needing napi Env, and encourage maintaining log sequence.
 lazy_static! {
     static ref GLOBAL_DATA: Mutex<Option<ThreadsafeFunction<Vec<String>, ErrorStrategy::Fatal>>> =
         Mutex::new(None);
+    static ref STRUCTURED_CALLBACK: Mutex<Option<ThreadsafeFunction<LogRecord, ErrorStrategy::Fatal>>> =
+        Mutex::new(None);
+    // changes the filter and sinks of the installed subscriber at runtime
+    static ref HANDLES: Mutex<Option<Handles>> = Mutex::new(None);
+}
+
+/// A native log sink, such as stderr or a file.
+pub(crate) type Sink = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// The subscriber the javascript callbacks are layered on.
+type CallbackSubscriber = Layered<reload::Layer<Option<Vec<Sink>>, Registry>, Registry>;
+
+/// The reload handles of the installed subscriber.
+struct Handles {
+    // the level of the javascript callbacks, and of the sinks without a level of their own
+    filter: reload::Handle<EnvFilter, CallbackSubscriber>,
+    // the native sinks by name, so that configuring one again replaces it.
+    // none rather than empty, as an empty list of layers disables every event.
+    sinks: reload::Handle<Option<Vec<Sink>>, Registry>,
+    sink_names: Vec<String>,
+}
+
+/// The level of a native sink.
+enum SinkLevel {
+    Own(Box<EnvFilter>),
+    // follows the level of the javascript callbacks
+    Shared(reload::Handle<EnvFilter, CallbackSubscriber>),
+}
+
+/// Passes a native sink only the events enabled by its level. Sinks are added at runtime,
+/// which the per-layer filters of `tracing_subscriber` do not support, so this filters itself.
+struct LeveledSink {
+    sink: Sink,
+    level: SinkLevel,
+}
+
+impl LeveledSink {
+    fn own_filter(&self) -> Option<&EnvFilter> {
+        match &self.level {
+            SinkLevel::Own(filter) => Some(filter.as_ref()),
+            SinkLevel::Shared(_) => None,
+        }
+    }
+}
+
+impl Layer<Registry> for LeveledSink {
+    fn register_callsite(
+        &self,
+        metadata: &'static tracing::Metadata<'static>,
+    ) -> tracing::subscriber::Interest {
+        // lets the filter note the callsites its directives match, without affecting other layers
+        if let Some(filter) = self.own_filter() {
+            Layer::<Registry>::register_callsite(filter, metadata);
+        }
+        self.sink.register_callsite(metadata)
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        match &self.level {
+            SinkLevel::Own(filter) => EnvFilter::max_level_hint(filter),
+            SinkLevel::Shared(handle) => handle.with_current(|f| f.max_level_hint()).ok()?,
+        }
+    }
+
+    fn on_event(&self, event: &tracing::Event<'_>, ctx: Context<'_, Registry>) {
+        let enabled = match &self.level {
+            SinkLevel::Own(filter) => EnvFilter::enabled(filter, event.metadata(), ctx.clone()),
+            SinkLevel::Shared(handle) => handle
+                .with_current(|f| f.enabled(event.metadata(), ctx.clone()))
+                .unwrap_or(false),
+        };
+        if enabled {
+            self.sink.on_event(event, ctx);
+        }
+    }
+
+    // spans all pass through, as the events of the sink may be within any of them
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, Registry>) {
+        if let Some(filter) = self.own_filter() {
+            filter.on_new_span(attrs, id, ctx.clone());
+        }
+        self.sink.on_new_span(attrs, id, ctx);
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, Registry>) {
+        if let Some(filter) = self.own_filter() {
+            filter.on_record(id, values, ctx.clone());
+        }
+        self.sink.on_record(id, values, ctx);
+    }
+
+    fn on_follows_from(&self, id: &span::Id, follows: &span::Id, ctx: Context<'_, Registry>) {
+        self.sink.on_follows_from(id, follows, ctx);
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, Registry>) {
+        if let Some(filter) = self.own_filter() {
+            filter.on_enter(id, ctx.clone());
+        }
+        self.sink.on_enter(id, ctx);
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, Registry>) {
+        if let Some(filter) = self.own_filter() {
+            filter.on_exit(id, ctx.clone());
+        }
+        self.sink.on_exit(id, ctx);
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, Registry>) {
+        if let Some(filter) = self.own_filter() {
+            filter.on_close(id.clone(), ctx.clone());
+        }
+        self.sink.on_close(id, ctx);
+    }
+
+    fn on_id_change(&self, old: &span::Id, new: &span::Id, ctx: Context<'_, Registry>) {
+        self.sink.on_id_change(old, new, ctx);
+    }
+}
+
+/// A log event, as passed to the {@link structuredLoggingCallback} callback.
+///
+/// @group Functions
+#[napi(object, object_from_js = false)]
+pub struct LogRecord {
+    /// When the event happened, in milliseconds since the Unix epoch, as with `Date.now()`.
+    pub timestamp: f64,
+    /// The level of the event, one of ERROR, WARN, INFO, DEBUG, or TRACE.
+    pub level: String,
+    /// The module the event came from, e.g. "ngrok::session".
+    pub target: String,
+    /// The message of the event.
+    pub message: String,
+    /// The other fields recorded with the event.
+    #[napi(ts_type = "Record<string, any>")]
+    pub fields: HashMap<String, Value>,
+    /// The spans the event happened within, outermost first.
+    pub spans: Vec<LogSpan>,
+}
+
+/// A span in the {@link LogRecord.spans} stack.
+///
+/// @group Functions
+#[napi(object, object_from_js = false)]
+pub struct LogSpan {
+    /// The name of the span, e.g. "listener".
+    pub name: String,
+    /// The fields recorded with the span, e.g. the listener id.
+    #[napi(ts_type = "Record<string, any>")]
+    pub fields: HashMap<String, Value>,
 }
 
+/// The fields recorded with a span, kept in its extensions for structured log records.
+struct SpanFields(HashMap<String, Value>);
+
 /// tracing subscriber layer to plumb events to javascript
 struct CustomLayer;
 
 impl<S> Layer<S> for CustomLayer
 where
-    S: tracing::Subscriber,
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
 {
-    fn on_event(
+    fn on_new_span(
+        &self,
+        attrs: &span::Attributes<'_>,
+        id: &span::Id,
+        ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let mut visitor = EventVisitor::default();
+        attrs.record(&mut visitor);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(visitor.fields));
+        }
+    }
+
+    fn on_record(
         &self,
-        event: &tracing::Event<'_>,
-        _ctx: tracing_subscriber::layer::Context<'_, S>,
+        id: &span::Id,
+        values: &span::Record<'_>,
+        ctx: tracing_subscriber::layer::Context<'_, S>,
     ) {
+        let mut visitor = EventVisitor::default();
+        values.record(&mut visitor);
+        if let Some(span) = ctx.span(id) {
+            if let Some(fields) = span.extensions_mut().get_mut::<SpanFields>() {
+                fields.0.extend(visitor.fields);
+            }
+        }
+    }
+
+    fn on_event(&self, event: &tracing::Event<'_>, ctx: tracing_subscriber::layer::Context<'_, S>) {
         let mut visitor = EventVisitor {
             ..Default::default()
         };
         event.record(&mut visitor);
-        if let Err(err) = log_to_callback(
-            event.metadata().level().to_string(),
-            event.metadata().target().to_string(),
-            visitor.message,
-        ) {
+        let level = event.metadata().level().to_string();
+        let target = event.metadata().target().to_string();
+
+        if STRUCTURED_CALLBACK.lock().is_some() {
+            let spans = ctx
+                .event_scope(event)
+                .into_iter()
+                .flat_map(|scope| scope.from_root())
+                .map(|span| LogSpan {
+                    name: span.name().to_string(),
+                    fields: span
+                        .extensions()
+                        .get::<SpanFields>()
+                        .map(|fields| fields.0.clone())
+                        .unwrap_or_default(),
+                })
+                .collect();
+            let record = LogRecord {
+                timestamp: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs_f64() * 1000.0)
+                    .unwrap_or_default(),
+                level: level.clone(),
+                target: target.clone(),
+                message: visitor.message.clone(),
+                fields: visitor.fields,
+                spans,
+            };
+            if let Err(err) = log_record_to_callback(record) {
+                println!("Error logging to javascript function: {err:?}");
+            }
+        }
+
+        if let Err(err) = log_to_callback(level, target, visitor.message) {
             println!("Error logging to javascript function: {err:?}");
         }
     }
 }
 
-/// Visitor to record the message from the event record
+/// Visitor to record the message and other fields from the event record
 #[derive(Default)]
 struct EventVisitor {
     message: String,
+    fields: HashMap<String, Value>,
+}
+
+impl EventVisitor {
+    fn record_value(&mut self, field: &Field, value: Value) {
+        self.fields.insert(field.name().to_string(), value);
+    }
 }
 
 impl tracing::field::Visit for EventVisitor {
-    // everything currently used comes through as a fmt debug
-    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.record_value(field, json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.record_value(field, json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.record_value(field, json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.record_value(field, json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        let value = redact(value).into_owned();
+        if field.name() == "message" {
+            self.message = value;
+        } else {
+            self.record_value(field, json!(value));
+        }
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.record_value(field, json!(redact(&value.to_string())));
+    }
+
+    // everything else comes through as a fmt debug
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        let value = redact(&format!("{value:?}")).into_owned();
         if field.name() == "message" {
-            self.message = format!("{value:?}");
+            self.message = value;
+        } else {
+            self.record_value(field, json!(value));
         }
     }
 }
@@ -80,9 +354,22 @@ pub fn log_to_callback(level: String, target: String, message: String) -> Result
     Ok(())
 }
 
+/// Send the record to the structured javascript callback, if one is registered
+fn log_record_to_callback(record: LogRecord) -> Result<()> {
+    if let Some(tsfn) = STRUCTURED_CALLBACK.lock().as_ref() {
+        let status = tsfn.call(record, ThreadsafeFunctionCallMode::NonBlocking);
+        if status != Status::Ok {
+            return Err(napi_err(status.to_string()));
+        }
+    }
+    Ok(())
+}
+
 /// Register a callback function that will receive logging event information.
 /// An absent callback will unregister an existing callback function.
-/// The log level defaults to INFO, it can be set to one of ERROR, WARN, INFO, DEBUG, or TRACE.
+/// The log level defaults to the `RUST_LOG` environment variable, or INFO if that is not set.
+/// It can be set to one of ERROR, WARN, INFO, DEBUG, or TRACE, or to per-target directives
+/// as with `RUST_LOG`, and changed later with {@link setLogLevel}.
 #[napi(
     ts_args_type = "callback?: (level: string, target: string, message: string) => void, level?: string"
 )]
@@ -113,28 +400,156 @@ pub fn logging_callback(
     // store the global callback
     let _ = GLOBAL_DATA.lock().insert(tsfn);
 
-    let tracing_level = if let Some(level) = level {
-        match level.to_uppercase().as_str() {
-            "ERROR" => LevelFilter::ERROR,
-            "WARN" => LevelFilter::WARN,
-            "INFO" => LevelFilter::INFO,
-            "DEBUG" => LevelFilter::DEBUG,
-            "TRACE" => LevelFilter::TRACE,
-            _ => return Err(napi_err("Unknown log level: {level:?}")),
-        }
-    } else {
-        LevelFilter::INFO
+    init_subscriber(level)
+}
+
+/// Register a callback function that will receive each logging event as a {@link LogRecord},
+/// with all of its fields and the spans it happened within, such as for JSON loggers like
+/// pino or winston. It can be used alongside {@link loggingCallback}.
+/// An absent callback will unregister an existing callback function.
+/// The log level defaults to the `RUST_LOG` environment variable, or INFO if that is not set.
+/// It can be set to one of ERROR, WARN, INFO, DEBUG, or TRACE, or to per-target directives
+/// as with `RUST_LOG`, and changed later with {@link setLogLevel}.
+#[napi(ts_args_type = "callback?: (record: LogRecord) => void, level?: string")]
+pub fn structured_logging_callback(
+    env: Env,
+    callback: Option<JsFunction>,
+    level: Option<String>,
+) -> Result<()> {
+    let Some(callback) = callback else {
+        // clear out any registered callback
+        STRUCTURED_CALLBACK.lock().take();
+        return Ok(());
     };
 
+    let mut tsfn: ThreadsafeFunction<LogRecord, ErrorStrategy::Fatal> = callback
+        .create_threadsafe_function(0, |ctx: ThreadSafeCallContext<LogRecord>| {
+            Ok(vec![ctx.value])
+        })?;
+    // tell the runtime it can exit while this callback exists
+    tsfn.unref(&env)?;
+
+    let _ = STRUCTURED_CALLBACK.lock().insert(tsfn);
+
+    init_subscriber(level)
+}
+
+/// Change the level of the log events passed to the logging callbacks, taking effect immediately.
+/// The level can be one of ERROR, WARN, INFO, DEBUG, TRACE, or OFF, or comma separated
+/// directives setting the level per target, as with `RUST_LOG`, e.g. "info,ngrok=debug,muxado=warn".
+/// A bare target such as "ngrok" enables every level for that target only.
+#[napi]
+pub fn set_log_level(level: String) -> Result<()> {
+    init_subscriber(Some(level))
+}
+
+/// Install the subscriber which sends events to the javascript callbacks, or change the
+/// level of the installed one. The level defaults to `RUST_LOG`, or INFO, and is left as is
+/// if already installed. Fails if a global subscriber from elsewhere is already installed.
+pub(crate) fn init_subscriber(level: Option<String>) -> Result<()> {
+    let level = level.as_deref().map(parse_filter).transpose()?;
+
+    let mut handles = HANDLES.lock();
+    if let Some(handles) = handles.as_ref() {
+        if let Some(level) = level {
+            handles
+                .filter
+                .reload(level)
+                .map_err(|e| napi_err(format!("Failed to set log level, {e}")))?;
+        }
+        return Ok(());
+    }
+
+    let level = level.unwrap_or_else(|| {
+        EnvFilter::builder()
+            .with_default_directive(LevelFilter::INFO.into())
+            .from_env_lossy()
+    });
+    let (filter, filter_handle) = reload::Layer::new(level);
+    let (sinks, sinks_handle) = reload::Layer::new(None::<Vec<Sink>>);
     if let Err(err) = tracing_subscriber::registry()
-        .with(CustomLayer)
-        .with(tracing_level)
+        .with(sinks)
+        .with(CustomLayer.with_filter(filter))
         .try_init()
     {
-        if !err.to_string().contains("already been set") {
-            return Err(napi_err(format!("Failed to subscribe logger, {err:?}")));
+        // the level and sinks could never be changed, so fail now rather than on first use
+        if err.to_string().contains("already been set") {
+            return Err(napi_err(
+                "Failed to subscribe logger, another global logger has already been set",
+            ));
         }
+        return Err(napi_err(format!("Failed to subscribe logger, {err:?}")));
     }
+    handles.replace(Handles {
+        filter: filter_handle,
+        sinks: sinks_handle,
+        sink_names: vec![],
+    });
 
     Ok(())
 }
+
+/// Add a native sink, replacing any with the same name, or remove it if `sink` is None.
+/// The sink gets the events of its own level if given, or else of the javascript callbacks.
+pub(crate) fn set_sink(name: &str, sink: Option<Sink>, level: Option<&str>) -> Result<()> {
+    let level = level.map(parse_filter).transpose()?;
+    let mut handles = HANDLES.lock();
+    let Some(handles) = handles.as_mut() else {
+        return Err(napi_err(
+            "Failed to add log sink, another logger has already been set",
+        ));
+    };
+    let sink = sink.map(|sink| -> Sink {
+        let level = match level {
+            Some(filter) => SinkLevel::Own(Box::new(filter)),
+            None => SinkLevel::Shared(handles.filter.clone()),
+        };
+        Box::new(LeveledSink { sink, level })
+    });
+    let position = handles.sink_names.iter().position(|n| n == name);
+    let adding = sink.is_some();
+    handles
+        .sinks
+        .modify(|sinks| {
+            let mut list = sinks.take().unwrap_or_default();
+            if let Some(position) = position {
+                list.remove(position);
+            }
+            if let Some(sink) = sink {
+                list.push(sink);
+            }
+            *sinks = (!list.is_empty()).then_some(list);
+        })
+        .map_err(|e| napi_err(format!("Failed to set log sink, {e}")))?;
+    if let Some(position) = position {
+        handles.sink_names.remove(position);
+    }
+    if adding {
+        handles.sink_names.push(name.to_string());
+    }
+    Ok(())
+}
+
+/// Parse a level, or `RUST_LOG` style directives such as "info,ngrok=debug" or "ngrok".
+fn parse_filter(level: &str) -> Result<EnvFilter> {
+    if level.contains(['=', ',', '[']) {
+        return EnvFilter::builder()
+            .parse(level)
+            .map_err(|e| napi_err(format!("Invalid log directives {level:?}: {e}")));
+    }
+    let level = match level.to_uppercase().as_str() {
+        "ERROR" => LevelFilter::ERROR,
+        "WARN" => LevelFilter::WARN,
+        "INFO" => LevelFilter::INFO,
+        "DEBUG" => LevelFilter::DEBUG,
+        "TRACE" => LevelFilter::TRACE,
+        "OFF" => LevelFilter::OFF,
+        // a bare target such as "ngrok" is a directive too, enabling all of its levels
+        _ => {
+            return EnvFilter::builder()
+                .parse(level)
+                .map_err(|e| napi_err(format!("Unknown log level: {level:?}, {e}")))
+        }
+    };
+    Ok(EnvFilter::default().add_directive(level.into()))
+}
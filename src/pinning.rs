@@ -0,0 +1,364 @@
+use std::{
+    io,
+    str::FromStr,
+    sync::Arc,
+};
+
+use aws_lc_rs::digest::{
+    digest,
+    SHA256,
+    SHA256_OUTPUT_LEN,
+};
+use base64::{
+    engine::general_purpose::STANDARD as BASE64,
+    Engine,
+};
+use lazy_static::lazy_static;
+use napi::bindgen_prelude::*;
+use ngrok::session::ConnectError;
+use rustls::{
+    client::{
+        danger::{
+            HandshakeSignatureValid,
+            ServerCertVerified,
+            ServerCertVerifier,
+        },
+        WebPkiServerVerifier,
+    },
+    pki_types::{
+        CertificateDer,
+        ServerName,
+        UnixTime,
+    },
+    ClientConfig,
+    DigitallySignedStruct,
+    OtherError,
+    RootCertStore,
+    SignatureScheme,
+};
+use rustls_pemfile::Item;
+use tracing::warn;
+
+use crate::napi_err;
+
+const SPKI_PREFIX: &str = "sha256/";
+// the CA of the ngrok service, which the upstream sdk validates against by default.
+// a copy of `assets/ngrok.ca.crt` in the ngrok crate, its `CERT_BYTES`, to be kept in sync
+// with it whenever the ngrok dependency is updated
+const NGROK_CA_CERT: &[u8] = include_bytes!("../assets/ngrok.ca.crt");
+// the DER tags of a SEQUENCE, and of the explicit version of a TBSCertificate
+const DER_SEQUENCE: u8 = 0x30;
+const DER_VERSION: u8 = 0xa0;
+
+lazy_static! {
+    // the host's root certificates, loaded once for all pinned sessions which use them
+    static ref ROOT_STORE: RootCertStore = {
+        let mut root_store = RootCertStore::empty();
+        match rustls_native_certs::load_native_certs() {
+            Ok(certs) => {
+                root_store.add_parsable_certificates(certs);
+            }
+            Err(e) => warn!("Failed to load host root certificates: {e}"),
+        }
+        root_store
+    };
+}
+
+type Sha256 = [u8; SHA256_OUTPUT_LEN];
+
+/// A pin on the certificate of the ngrok service.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Pin {
+    /// The SHA-256 hash of the SubjectPublicKeyInfo of any certificate in the chain.
+    Spki(Sha256),
+    /// The SHA-256 fingerprint of the leaf certificate.
+    Fingerprint(Sha256),
+}
+
+impl FromStr for Pin {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || {
+            napi_err(format!(
+                "Invalid certificate pin {s:?}, expected \"sha256/<base64 SPKI hash>\" or a hex SHA-256 certificate fingerprint"
+            ))
+        };
+        if let Some(hash) = s.strip_prefix(SPKI_PREFIX) {
+            let hash = BASE64.decode(hash).map_err(|_| invalid())?;
+            return hash.try_into().map(Pin::Spki).map_err(|_| invalid());
+        }
+        // fingerprints are often written with colons between the bytes
+        let hex: String = s.chars().filter(|c| *c != ':').collect();
+        if hex.len() != SHA256_OUTPUT_LEN * 2 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut fingerprint = [0u8; SHA256_OUTPUT_LEN];
+        for (i, byte) in fingerprint.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(Pin::Fingerprint(fingerprint))
+    }
+}
+
+/// The pins the certificate of the ngrok service must match one of, if any.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ServerCertPins {
+    pins: Vec<Pin>,
+    backup_pins: Vec<Pin>,
+}
+
+impl ServerCertPins {
+    pub(crate) fn new(pins: Vec<String>, backup_pins: Vec<String>) -> Result<Self> {
+        let parse = |pins: Vec<String>| {
+            pins.iter()
+                .map(|pin| pin.trim().parse())
+                .collect::<Result<Vec<Pin>>>()
+        };
+        Ok(ServerCertPins {
+            pins: parse(pins)?,
+            backup_pins: parse(backup_pins)?,
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pins.is_empty() && self.backup_pins.is_empty()
+    }
+
+    /// Check the certificate chain presented by the server against the pins.
+    fn check(
+        &self,
+        host: &str,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+    ) -> io::Result<()> {
+        let presented = presented_pins(end_entity, intermediates);
+        if self.pins.iter().any(|pin| presented.contains(pin)) {
+            return Ok(());
+        }
+        if self.backup_pins.iter().any(|pin| presented.contains(pin)) {
+            warn!("The certificate of {host} only matched a backup pin, the primary pins may need updating");
+            return Ok(());
+        }
+        let spki = presented.iter().find_map(|pin| match pin {
+            Pin::Spki(hash) => Some(format!("{SPKI_PREFIX}{}", BASE64.encode(hash))),
+            Pin::Fingerprint(_) => None,
+        });
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "certificate of {host} matched none of the pinned certificates, its public key is {}",
+                spki.unwrap_or_else(|| "unknown".into())
+            ),
+        ))
+    }
+}
+
+/// The pins of the leaf certificate, then the SPKI pins of the rest of the chain.
+fn presented_pins(
+    end_entity: &CertificateDer<'_>,
+    intermediates: &[CertificateDer<'_>],
+) -> Vec<Pin> {
+    let sha256 = |bytes: &[u8]| -> Sha256 {
+        digest(&SHA256, bytes)
+            .as_ref()
+            .try_into()
+            .expect("sha256 digest length")
+    };
+    let mut presented = vec![Pin::Fingerprint(sha256(end_entity.as_ref()))];
+    for cert in [end_entity].into_iter().chain(intermediates) {
+        if let Some(spki) = subject_public_key_info(cert.as_ref()) {
+            presented.push(Pin::Spki(sha256(spki)));
+        }
+    }
+    presented
+}
+
+/// The DER SubjectPublicKeyInfo of a certificate, which follows the version, serial number,
+/// signature algorithm, issuer, validity and subject of the TBSCertificate.
+fn subject_public_key_info(cert: &[u8]) -> Option<&[u8]> {
+    let cert = DerElement::parse(cert)?.contents;
+    let mut tbs = DerElement::parse(cert)?.contents;
+    // the version is left out for v1 certificates
+    if tbs.first() == Some(&DER_VERSION) {
+        tbs = DerElement::parse(tbs)?.rest;
+    }
+    for _ in 0..5 {
+        tbs = DerElement::parse(tbs)?.rest;
+    }
+    let spki = DerElement::parse(tbs)?;
+    (spki.tag == DER_SEQUENCE).then_some(spki.whole)
+}
+
+/// The DER element at the start of some DER, split from whatever follows it.
+struct DerElement<'a> {
+    tag: u8,
+    // the tag, length and contents
+    whole: &'a [u8],
+    contents: &'a [u8],
+    rest: &'a [u8],
+}
+
+impl<'a> DerElement<'a> {
+    fn parse(der: &'a [u8]) -> Option<Self> {
+        let (&tag, rest) = der.split_first()?;
+        let (&len, mut rest) = rest.split_first()?;
+        let mut len = usize::from(len);
+        // long form lengths give the number of bytes the length takes
+        if len >= 0x80 {
+            let bytes = len & 0x7f;
+            if bytes == 0 || bytes > 4 || rest.len() < bytes {
+                return None;
+            }
+            len = rest[..bytes]
+                .iter()
+                .fold(0, |len, byte| len << 8 | usize::from(*byte));
+            rest = &rest[bytes..];
+        }
+        if rest.len() < len {
+            return None;
+        }
+        let header = der.len() - rest.len();
+        Some(DerElement {
+            tag,
+            whole: &der[..header + len],
+            contents: &rest[..len],
+            rest: &rest[len..],
+        })
+    }
+}
+
+/// The certificates the chain of the ngrok service is validated against,
+/// chosen the same way as by the upstream session builder.
+///
+/// rustls offers no way to get the verifier back out of the `ClientConfig` the connector
+/// is given, so this follows `SessionBuilder::get_or_create_tls_config` in the ngrok crate,
+/// and has to be kept in sync with it whenever the ngrok dependency is updated.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ServerRoots {
+    // the host's certificates, which take precedence over a CA certificate
+    host: bool,
+    ca_cert: Option<Vec<u8>>,
+}
+
+impl ServerRoots {
+    /// Follow the `root_cas` of the upstream builder, "trusted", "host" or a file path.
+    pub(crate) fn root_cas(&mut self, root_cas: &str) -> io::Result<()> {
+        match root_cas {
+            "trusted" => self.ca_cert = None,
+            "host" => self.host = true,
+            path => self.ca_cert = Some(std::fs::read(path)?),
+        }
+        Ok(())
+    }
+
+    /// Follow the `ca_cert` of the upstream builder, the bytes of a PEM certificate.
+    pub(crate) fn ca_cert(&mut self, ca_cert: Vec<u8>) {
+        self.ca_cert = Some(ca_cert);
+    }
+
+    fn load(&self) -> RootCertStore {
+        if self.host {
+            return ROOT_STORE.clone();
+        }
+        let pem = self.ca_cert.as_deref().unwrap_or(NGROK_CA_CERT);
+        let mut pem = io::Cursor::new(pem);
+        let certs = rustls_pemfile::read_all(&mut pem).filter_map(|item| match item {
+            Ok(Item::X509Certificate(cert)) => Some(cert),
+            _ => None,
+        });
+        let mut root_store = RootCertStore::empty();
+        root_store.add_parsable_certificates(certs);
+        root_store
+    }
+}
+
+/// Checks the certificate of the ngrok service against the pins during the TLS handshake.
+#[derive(Clone)]
+pub(crate) struct ServerCertPinning {
+    pins: Arc<ServerCertPins>,
+    roots: Arc<RootCertStore>,
+}
+
+impl ServerCertPinning {
+    /// The pinning for the session, or none if no pins are configured.
+    pub(crate) fn new(pins: &ServerCertPins, roots: &ServerRoots) -> Option<Self> {
+        (!pins.is_empty()).then(|| ServerCertPinning {
+            pins: Arc::new(pins.clone()),
+            roots: Arc::new(roots.load()),
+        })
+    }
+
+    /// A copy of the TLS config given to the connector, which also checks the pins.
+    pub(crate) fn tls_config(
+        &self,
+        tls_config: &ClientConfig,
+    ) -> core::result::Result<Arc<ClientConfig>, ConnectError> {
+        let inner = WebPkiServerVerifier::builder_with_provider(
+            self.roots.clone(),
+            tls_config.crypto_provider().clone(),
+        )
+        .build()
+        .map_err(|e| ConnectError::Tls(io::Error::new(io::ErrorKind::InvalidInput, e)))?;
+        let mut tls_config = tls_config.clone();
+        tls_config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(PinnedVerifier {
+                inner,
+                pins: self.pins.clone(),
+            }));
+        Ok(Arc::new(tls_config))
+    }
+}
+
+/// Validates the certificate chain as usual, then fails the handshake unless it matches the pins.
+#[derive(Debug)]
+struct PinnedVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    pins: Arc<ServerCertPins>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> core::result::Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+        self.pins
+            .check(&server_name.to_str(), end_entity, intermediates)
+            .map_err(|e| rustls::Error::Other(OtherError(Arc::new(e))))?;
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> core::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> core::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
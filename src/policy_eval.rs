@@ -0,0 +1,1074 @@
+use core::result::Result as CoreResult;
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    net::IpAddr,
+};
+
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use regex::Regex;
+
+use crate::{
+    napi_err,
+    traffic_policy::{
+        lint_traffic_policy,
+        PolicyDocument,
+        TrafficPolicyAction,
+        TrafficPolicyRule,
+    },
+};
+
+/// A sample HTTP request or TCP connection for {@link evaluateTrafficPolicy}.
+/// Leave `method` and `path` unset for a TCP connection.
+///
+/// @group Functions
+#[napi(object)]
+#[derive(Clone, Default)]
+pub struct TrafficPolicySample {
+    /// The HTTP request method, defaults to "GET" if `path` is set.
+    pub method: Option<String>,
+    /// The HTTP request path, with an optional query string, e.g. "/api/items?page=2".
+    /// Defaults to "/" if `method` is set.
+    pub path: Option<String>,
+    /// The host of the HTTP request, defaults to "example.ngrok.app".
+    pub host: Option<String>,
+    /// The scheme of the HTTP request, "http" or "https", defaults to "https".
+    pub scheme: Option<String>,
+    /// The HTTP request headers. Names are lowercased, as in `req.headers['user-agent']`.
+    pub headers: Option<HashMap<String, String>>,
+    /// The IP address of the client, defaults to "127.0.0.1".
+    pub client_ip: Option<String>,
+    /// The port of the client.
+    pub client_port: Option<u32>,
+    /// The port of the endpoint, defaults to 80 for the "http" scheme and 443 otherwise.
+    pub server_port: Option<u32>,
+    /// The status code of the upstream response, for "on_http_response" rules, defaults to 200.
+    pub status_code: Option<u32>,
+    /// The headers of the upstream response, for "on_http_response" rules.
+    pub response_headers: Option<HashMap<String, String>>,
+}
+
+/// How a rule fared in {@link evaluateTrafficPolicy}.
+///
+/// @group Functions
+#[napi(object, object_from_js = false)]
+#[derive(Clone, Default)]
+pub struct TrafficPolicyRuleResult {
+    /// The phase of the rule, e.g. "on_http_request".
+    pub phase: String,
+    /// The position of the rule within its phase.
+    pub index: u32,
+    /// The name of the rule, if it has one.
+    pub name: Option<String>,
+    /// Whether all of the rule's expressions matched.
+    pub matched: bool,
+    /// The types of the actions which would run, in order. Empty if the rule did not match.
+    pub actions: Vec<String>,
+    /// Why the rule's expressions could not be evaluated, in which case it did not match.
+    pub error: Option<String>,
+}
+
+/// The outcome of {@link evaluateTrafficPolicy}.
+///
+/// @group Functions
+#[napi(object, object_from_js = false)]
+#[derive(Clone, Default)]
+pub struct TrafficPolicyEvaluation {
+    /// The rules which were evaluated, in order. Rules after a terminating action are not evaluated.
+    pub rules: Vec<TrafficPolicyRuleResult>,
+    /// The types of all of the actions which would run, in order.
+    pub actions: Vec<String>,
+    /// The action which would end processing, such as "deny", "custom-response", "redirect",
+    /// or an enforced "restrict-ips" rejecting the client.
+    pub terminated_by: Option<String>,
+}
+
+/// Evaluate a traffic policy locally against a sample HTTP request or TCP connection, reporting
+/// which rules match and which actions would run, such as to unit test a policy without a listener.
+///
+/// The "on_tcp_connect" rules are evaluated for every sample, followed by the "on_http_request"
+/// and "on_http_response" rules for HTTP requests. Expressions may use the common subset of CEL:
+/// the `req`, `res` and `conn` variables, literals, lists and maps, the usual operators and `in`,
+/// `has()`, `size()`, `int()`, `double()`, `string()`, `inCidrRange()`, `inCidrRanges()`, the string
+/// methods `startsWith`, `endsWith`, `contains`, `matches`, `lowerAscii` and `upperAscii`, and the
+/// `exists`, `all`, `exists_one`, `filter` and `map` macros. Actions depending on state or other
+/// services, such as "rate-limit" or "oauth", are reported as running without being evaluated.
+#[napi(ts_args_type = "policy: string | TrafficPolicy, sample: TrafficPolicySample")]
+pub fn evaluate_traffic_policy(
+    policy: String,
+    sample: TrafficPolicySample,
+) -> Result<TrafficPolicyEvaluation> {
+    lint_traffic_policy(&policy).map_err(|e| napi_err(format!("invalid traffic policy: {e}")))?;
+    let document = serde_yaml::from_str::<PolicyDocument>(&policy)
+        .map_err(|e| napi_err(format!("invalid traffic policy: {e}")))?;
+    let is_http = sample.method.is_some() || sample.path.is_some();
+    let vars = variables(&sample, is_http).map_err(napi_err)?;
+
+    let mut phases = vec![("on_tcp_connect", &document.on_tcp_connect)];
+    if is_http {
+        phases.push(("on_http_request", &document.on_http_request));
+        phases.push(("on_http_response", &document.on_http_response));
+    }
+
+    let mut evaluation = TrafficPolicyEvaluation::default();
+    'phases: for (phase, rules) in phases {
+        for (index, rule) in rules.iter().enumerate() {
+            let mut result = TrafficPolicyRuleResult {
+                phase: phase.to_string(),
+                index: index as u32,
+                name: rule.name.clone(),
+                ..Default::default()
+            };
+            match rule_matches(rule, &vars) {
+                Ok(matched) => result.matched = matched,
+                Err(e) => result.error = Some(e),
+            }
+            if result.matched {
+                for action in &rule.actions {
+                    result.actions.push(action.kind.clone());
+                    evaluation.actions.push(action.kind.clone());
+                    if terminates(action, &sample).map_err(napi_err)? {
+                        evaluation.terminated_by = Some(action.kind.clone());
+                        evaluation.rules.push(result);
+                        break 'phases;
+                    }
+                }
+            }
+            evaluation.rules.push(result);
+        }
+    }
+    Ok(evaluation)
+}
+
+fn rule_matches(rule: &TrafficPolicyRule, vars: &BTreeMap<String, Val>) -> EvalResult<bool> {
+    for expression in rule.expressions.iter().flatten() {
+        let parsed = Parser::parse(expression).map_err(|e| format!("{expression:?}: {e}"))?;
+        match eval(&parsed, &Scope::Root(vars)) {
+            Ok(Val::Bool(true)) => {}
+            Ok(Val::Bool(false)) => return Ok(false),
+            Ok(other) => {
+                return Err(format!(
+                    "{expression:?}: expected a bool, got {}",
+                    other.type_name()
+                ))
+            }
+            Err(e) => return Err(format!("{expression:?}: {e}")),
+        }
+    }
+    Ok(true)
+}
+
+/// Whether the action would end processing of the sample.
+fn terminates(action: &TrafficPolicyAction, sample: &TrafficPolicySample) -> EvalResult<bool> {
+    match action.kind.as_str() {
+        "deny" | "custom-response" | "redirect" => Ok(true),
+        "restrict-ips" => {
+            let config = action.config.as_ref();
+            let enforce = config
+                .and_then(|c| c.get("enforce"))
+                .and_then(|e| e.as_bool())
+                .unwrap_or(false);
+            if !enforce {
+                return Ok(false);
+            }
+            let cidrs = |key: &str| -> Vec<String> {
+                config
+                    .and_then(|c| c.get(key))
+                    .and_then(|v| v.as_array())
+                    .map(|a| {
+                        a.iter()
+                            .filter_map(|v| v.as_str().map(String::from))
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            };
+            let ip = client_ip(sample);
+            for cidr in cidrs("deny") {
+                if in_cidr(&ip, &cidr)? {
+                    return Ok(true);
+                }
+            }
+            let allow = cidrs("allow");
+            for cidr in &allow {
+                if in_cidr(&ip, cidr)? {
+                    return Ok(false);
+                }
+            }
+            Ok(!allow.is_empty())
+        }
+        _ => Ok(false),
+    }
+}
+
+fn client_ip(sample: &TrafficPolicySample) -> String {
+    sample
+        .client_ip
+        .clone()
+        .unwrap_or_else(|| "127.0.0.1".to_string())
+}
+
+/// The variables available to expressions, in the shape ngrok provides them.
+fn variables(sample: &TrafficPolicySample, is_http: bool) -> EvalResult<BTreeMap<String, Val>> {
+    let ip = client_ip(sample);
+    ip.parse::<IpAddr>()
+        .map_err(|_| format!("invalid client IP address {ip:?}"))?;
+    let scheme = sample.scheme.clone().unwrap_or_else(|| "https".to_string());
+    let server_port = sample
+        .server_port
+        .unwrap_or(if scheme == "http" { 80 } else { 443 });
+
+    let mut conn = vec![
+        ("client_ip", Val::Str(ip)),
+        ("server_port", Val::Int(server_port.into())),
+    ];
+    if let Some(port) = sample.client_port {
+        conn.push(("client_port", Val::Int(port.into())));
+    }
+    let mut vars = BTreeMap::from([("conn".to_string(), Val::map(conn))]);
+    if !is_http {
+        return Ok(vars);
+    }
+
+    let host = sample
+        .host
+        .clone()
+        .unwrap_or_else(|| "example.ngrok.app".to_string());
+    let target = sample.path.clone().unwrap_or_else(|| "/".to_string());
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let mut query_params = BTreeMap::<String, Vec<Val>>::new();
+    for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+        query_params
+            .entry(key.into_owned())
+            .or_default()
+            .push(Val::Str(value.into_owned()));
+    }
+    let url = Val::map([
+        ("scheme", Val::Str(scheme.clone())),
+        ("host", Val::Str(host.clone())),
+        ("path", Val::Str(path.to_string())),
+        ("query", Val::Str(query.to_string())),
+        (
+            "query_params",
+            Val::Map(
+                query_params
+                    .into_iter()
+                    .map(|(k, v)| (k, Val::List(v)))
+                    .collect(),
+            ),
+        ),
+        ("raw", Val::Str(format!("{scheme}://{host}{target}"))),
+    ]);
+    let method = sample
+        .method
+        .clone()
+        .unwrap_or_else(|| "GET".to_string())
+        .to_uppercase();
+    vars.insert(
+        "req".to_string(),
+        Val::map([
+            ("method", Val::Str(method)),
+            ("host", Val::Str(host)),
+            ("url", url),
+            ("headers", headers(&sample.headers)),
+        ]),
+    );
+    vars.insert(
+        "res".to_string(),
+        Val::map([
+            (
+                "status_code",
+                Val::Int(sample.status_code.unwrap_or(200).into()),
+            ),
+            ("headers", headers(&sample.response_headers)),
+        ]),
+    );
+    Ok(vars)
+}
+
+fn headers(headers: &Option<HashMap<String, String>>) -> Val {
+    Val::Map(
+        headers
+            .iter()
+            .flatten()
+            .map(|(k, v)| (k.to_lowercase(), Val::List(vec![Val::Str(v.clone())])))
+            .collect(),
+    )
+}
+
+fn in_cidr(ip: &str, cidr: &str) -> EvalResult<bool> {
+    let ip = ip
+        .parse::<IpAddr>()
+        .map_err(|_| format!("invalid IP address {ip:?}"))?;
+    let (network, prefix) = cidr.split_once('/').unwrap_or((cidr, ""));
+    let network = network
+        .parse::<IpAddr>()
+        .map_err(|_| format!("invalid CIDR range {cidr:?}"))?;
+    let width = if network.is_ipv4() { 32 } else { 128 };
+    let prefix = if prefix.is_empty() {
+        width
+    } else {
+        prefix
+            .parse::<u32>()
+            .ok()
+            .filter(|p| *p <= width)
+            .ok_or_else(|| format!("invalid CIDR range {cidr:?}"))?
+    };
+    // compare the leading prefix bits, which are the same if shifting out the rest leaves nothing
+    Ok(match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            prefix == 0 || (u32::from(ip) ^ u32::from(network)) >> (32 - prefix) == 0
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            prefix == 0 || (u128::from(ip) ^ u128::from(network)) >> (128 - prefix) == 0
+        }
+        _ => false,
+    })
+}
+
+type EvalResult<T> = CoreResult<T, String>;
+
+/// A value of an expression.
+#[derive(Clone, Debug)]
+enum Val {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Double(f64),
+    Str(String),
+    List(Vec<Val>),
+    Map(BTreeMap<String, Val>),
+}
+
+impl Val {
+    fn map<'a>(entries: impl IntoIterator<Item = (&'a str, Val)>) -> Val {
+        Val::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Val::Null => "null",
+            Val::Bool(_) => "bool",
+            Val::Int(_) => "int",
+            Val::Double(_) => "double",
+            Val::Str(_) => "string",
+            Val::List(_) => "list",
+            Val::Map(_) => "map",
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Val::Int(i) => Some(*i as f64),
+            Val::Double(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> EvalResult<&str> {
+        match self {
+            Val::Str(s) => Ok(s),
+            other => Err(format!("expected a string, got {}", other.type_name())),
+        }
+    }
+
+    fn as_bool(&self) -> EvalResult<bool> {
+        match self {
+            Val::Bool(b) => Ok(*b),
+            other => Err(format!("expected a bool, got {}", other.type_name())),
+        }
+    }
+
+    fn equals(&self, other: &Val) -> bool {
+        match (self, other) {
+            (Val::Null, Val::Null) => true,
+            (Val::Bool(a), Val::Bool(b)) => a == b,
+            (Val::Str(a), Val::Str(b)) => a == b,
+            (Val::Int(a), Val::Int(b)) => a == b,
+            (Val::List(a), Val::List(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.equals(b))
+            }
+            (Val::Map(a), Val::Map(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .all(|(k, v)| b.get(k).map(|w| v.equals(w)).unwrap_or(false))
+            }
+            _ => match (self.as_f64(), other.as_f64()) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            },
+        }
+    }
+}
+
+/// A parsed expression.
+#[derive(Debug)]
+enum Expr {
+    Literal(Val),
+    Ident(String),
+    Member(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Call(Option<Box<Expr>>, String, Vec<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+    List(Vec<Expr>),
+    Map(Vec<(Expr, Expr)>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Int(i64),
+    Double(f64),
+    Str(String),
+    Op(&'static str),
+}
+
+// longest first, so that "==" is not read as "=" "="
+const OPERATORS: [&str; 25] = [
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "+", "-", "*", "/", "%", "(", ")", "[", "]",
+    "{", "}", ".", ",", "?", ":", "=",
+];
+
+fn tokenize(src: &str) -> EvalResult<Vec<Token>> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '\''
+            || c == '"'
+            || ((c == 'r' || c == 'R') && matches!(chars.get(i + 1), Some('\'' | '"')))
+        {
+            let raw = c == 'r' || c == 'R';
+            if raw {
+                i += 1;
+            }
+            let quote = chars[i];
+            let mut value = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err("unterminated string".to_string()),
+                    Some(&q) if q == quote => break,
+                    Some('\\') if !raw => {
+                        i += 1;
+                        match chars.get(i) {
+                            Some('n') => value.push('\n'),
+                            Some('t') => value.push('\t'),
+                            Some('r') => value.push('\r'),
+                            Some(&other) => value.push(other),
+                            None => return Err("unterminated string".to_string()),
+                        }
+                    }
+                    Some(&other) => value.push(other),
+                }
+                i += 1;
+            }
+            i += 1;
+            tokens.push(Token::Str(value));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let mut is_double = false;
+            if chars.get(i) == Some(&'.') && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) {
+                is_double = true;
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            // unsigned ints are treated as ints
+            if !is_double && matches!(chars.get(i), Some('u' | 'U')) {
+                i += 1;
+            }
+            tokens.push(if is_double {
+                Token::Double(text.parse().map_err(|_| format!("invalid number {text}"))?)
+            } else {
+                Token::Int(text.parse().map_err(|_| format!("invalid number {text}"))?)
+            });
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .ok_or_else(|| format!("unexpected character {c:?}"))?;
+            // a lone "=" is a common slip for "=="
+            if *op == "=" {
+                return Err("unexpected \"=\", use \"==\" for comparisons".to_string());
+            }
+            i += op.len();
+            tokens.push(Token::Op(op));
+        }
+    }
+    Ok(tokens)
+}
+
+// how deeply an expression may nest, so that parsing, evaluating and dropping it can't
+// overflow the stack, however many parentheses or operators it chains
+const MAX_DEPTH: usize = 100;
+
+/// A recursive descent parser, one method per level of operator precedence.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    // how deeply the expression being parsed is nested
+    depth: usize,
+}
+
+impl Parser {
+    fn parse(src: &str) -> EvalResult<Expr> {
+        let mut parser = Parser {
+            tokens: tokenize(src)?,
+            pos: 0,
+            depth: 0,
+        };
+        let expr = parser.conditional()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected {token:?}")),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Op(o)) if *o == op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> EvalResult<()> {
+        if self.eat(op) {
+            Ok(())
+        } else {
+            Err(match self.peek() {
+                Some(token) => format!("expected {op:?}, found {token:?}"),
+                None => format!("expected {op:?} at end of expression"),
+            })
+        }
+    }
+
+    // count a level of nesting, as each operator or parenthesis adds one
+    fn deeper(&mut self) -> EvalResult<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(format!(
+                "expression nested more than {MAX_DEPTH} levels deep"
+            ));
+        }
+        Ok(())
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> EvalResult<Expr>) -> EvalResult<Expr> {
+        self.deeper()?;
+        let expr = parse(self)?;
+        self.depth -= 1;
+        Ok(expr)
+    }
+
+    fn conditional(&mut self) -> EvalResult<Expr> {
+        self.nested(Self::ternary)
+    }
+
+    fn ternary(&mut self) -> EvalResult<Expr> {
+        let cond = self.or()?;
+        if !self.eat("?") {
+            return Ok(cond);
+        }
+        let then = self.conditional()?;
+        self.expect(":")?;
+        let otherwise = self.conditional()?;
+        Ok(Expr::Cond(
+            Box::new(cond),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    fn or(&mut self) -> EvalResult<Expr> {
+        let depth = self.depth;
+        let mut left = self.and()?;
+        while self.eat("||") {
+            self.deeper()?;
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        self.depth = depth;
+        Ok(left)
+    }
+
+    fn and(&mut self) -> EvalResult<Expr> {
+        let depth = self.depth;
+        let mut left = self.relation()?;
+        while self.eat("&&") {
+            self.deeper()?;
+            left = Expr::And(Box::new(left), Box::new(self.relation()?));
+        }
+        self.depth = depth;
+        Ok(left)
+    }
+
+    fn relation(&mut self) -> EvalResult<Expr> {
+        let depth = self.depth;
+        let mut left = self.addition()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(op @ ("==" | "!=" | "<" | "<=" | ">" | ">="))) => *op,
+                Some(Token::Ident(ident)) if ident == "in" => "in",
+                _ => {
+                    self.depth = depth;
+                    return Ok(left);
+                }
+            };
+            self.pos += 1;
+            self.deeper()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.addition()?));
+        }
+    }
+
+    fn addition(&mut self) -> EvalResult<Expr> {
+        let depth = self.depth;
+        let mut left = self.multiplication()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(op @ ("+" | "-"))) => *op,
+                _ => {
+                    self.depth = depth;
+                    return Ok(left);
+                }
+            };
+            self.pos += 1;
+            self.deeper()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.multiplication()?));
+        }
+    }
+
+    fn multiplication(&mut self) -> EvalResult<Expr> {
+        let depth = self.depth;
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(op @ ("*" | "/" | "%"))) => *op,
+                _ => {
+                    self.depth = depth;
+                    return Ok(left);
+                }
+            };
+            self.pos += 1;
+            self.deeper()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> EvalResult<Expr> {
+        if self.eat("!") {
+            Ok(Expr::Not(Box::new(self.nested(Self::unary)?)))
+        } else if self.eat("-") {
+            Ok(Expr::Neg(Box::new(self.nested(Self::unary)?)))
+        } else {
+            self.postfix()
+        }
+    }
+
+    fn postfix(&mut self) -> EvalResult<Expr> {
+        let depth = self.depth;
+        let mut expr = self.primary()?;
+        loop {
+            if self.eat(".") {
+                self.deeper()?;
+                let name = match self.next() {
+                    Some(Token::Ident(name)) => name,
+                    other => {
+                        return Err(format!(
+                            "expected a field name after \".\", found {other:?}"
+                        ))
+                    }
+                };
+                expr = if self.eat("(") {
+                    Expr::Call(Some(Box::new(expr)), name, self.arguments(")")?)
+                } else {
+                    Expr::Member(Box::new(expr), name)
+                };
+            } else if self.eat("[") {
+                self.deeper()?;
+                let index = self.conditional()?;
+                self.expect("]")?;
+                expr = Expr::Index(Box::new(expr), Box::new(index));
+            } else {
+                self.depth = depth;
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn arguments(&mut self, close: &str) -> EvalResult<Vec<Expr>> {
+        let mut args = vec![];
+        if self.eat(close) {
+            return Ok(args);
+        }
+        loop {
+            args.push(self.conditional()?);
+            if self.eat(close) {
+                return Ok(args);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn primary(&mut self) -> EvalResult<Expr> {
+        match self.next() {
+            Some(Token::Int(i)) => Ok(Expr::Literal(Val::Int(i))),
+            Some(Token::Double(d)) => Ok(Expr::Literal(Val::Double(d))),
+            Some(Token::Str(s)) => Ok(Expr::Literal(Val::Str(s))),
+            Some(Token::Ident(ident)) => match ident.as_str() {
+                "true" => Ok(Expr::Literal(Val::Bool(true))),
+                "false" => Ok(Expr::Literal(Val::Bool(false))),
+                "null" => Ok(Expr::Literal(Val::Null)),
+                _ if self.eat("(") => Ok(Expr::Call(None, ident, self.arguments(")")?)),
+                _ => Ok(Expr::Ident(ident)),
+            },
+            Some(Token::Op("(")) => {
+                let expr = self.conditional()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Op("[")) => Ok(Expr::List(self.arguments("]")?)),
+            Some(Token::Op("{")) => {
+                let mut entries = vec![];
+                if !self.eat("}") {
+                    loop {
+                        let key = self.conditional()?;
+                        self.expect(":")?;
+                        entries.push((key, self.conditional()?));
+                        if self.eat("}") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                Ok(Expr::Map(entries))
+            }
+            Some(token) => Err(format!("unexpected {token:?}")),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+/// The variables in scope, including those bound by macros such as `exists`.
+enum Scope<'a> {
+    Root(&'a BTreeMap<String, Val>),
+    Local(&'a str, &'a Val, &'a Scope<'a>),
+}
+
+impl Scope<'_> {
+    fn lookup(&self, name: &str) -> Option<&Val> {
+        match self {
+            Scope::Root(vars) => vars.get(name),
+            Scope::Local(local, value, _) if *local == name => Some(value),
+            Scope::Local(_, _, parent) => parent.lookup(name),
+        }
+    }
+}
+
+fn eval(expr: &Expr, scope: &Scope) -> EvalResult<Val> {
+    match expr {
+        Expr::Literal(val) => Ok(val.clone()),
+        Expr::Ident(name) => scope
+            .lookup(name)
+            .cloned()
+            .ok_or_else(|| format!("undeclared reference to {name:?}")),
+        Expr::Member(target, field) => match eval(target, scope)? {
+            Val::Map(mut map) => map
+                .remove(field)
+                .ok_or_else(|| format!("no such key: {field:?}")),
+            other => Err(format!("no field {field:?} on {}", other.type_name())),
+        },
+        Expr::Index(target, index) => match (eval(target, scope)?, eval(index, scope)?) {
+            (Val::Map(mut map), Val::Str(key)) => map
+                .remove(&key)
+                .ok_or_else(|| format!("no such key: {key:?}")),
+            (Val::List(list), Val::Int(i)) => usize::try_from(i)
+                .ok()
+                .and_then(|i| list.get(i).cloned())
+                .ok_or_else(|| format!("index {i} out of range for a list of size {}", list.len())),
+            (target, index) => Err(format!(
+                "cannot index {} with {}",
+                target.type_name(),
+                index.type_name()
+            )),
+        },
+        Expr::Not(inner) => Ok(Val::Bool(!eval(inner, scope)?.as_bool()?)),
+        Expr::Neg(inner) => match eval(inner, scope)? {
+            Val::Int(i) => i
+                .checked_neg()
+                .map(Val::Int)
+                .ok_or_else(|| format!("overflow in -({i})")),
+            Val::Double(d) => Ok(Val::Double(-d)),
+            other => Err(format!("cannot negate {}", other.type_name())),
+        },
+        // errors on one side are absorbed if the other side decides the result, as in CEL
+        Expr::And(left, right) => {
+            let left = eval(left, scope).and_then(|v| v.as_bool());
+            if let Ok(false) = left {
+                return Ok(Val::Bool(false));
+            }
+            let right = eval(right, scope).and_then(|v| v.as_bool());
+            if let Ok(false) = right {
+                return Ok(Val::Bool(false));
+            }
+            Ok(Val::Bool(left? && right?))
+        }
+        Expr::Or(left, right) => {
+            let left = eval(left, scope).and_then(|v| v.as_bool());
+            if let Ok(true) = left {
+                return Ok(Val::Bool(true));
+            }
+            let right = eval(right, scope).and_then(|v| v.as_bool());
+            if let Ok(true) = right {
+                return Ok(Val::Bool(true));
+            }
+            Ok(Val::Bool(left? || right?))
+        }
+        Expr::Cond(cond, then, otherwise) => {
+            if eval(cond, scope)?.as_bool()? {
+                eval(then, scope)
+            } else {
+                eval(otherwise, scope)
+            }
+        }
+        Expr::Binary(op, left, right) => binary(op, eval(left, scope)?, eval(right, scope)?),
+        Expr::List(items) => Ok(Val::List(
+            items
+                .iter()
+                .map(|item| eval(item, scope))
+                .collect::<EvalResult<_>>()?,
+        )),
+        Expr::Map(entries) => {
+            let mut map = BTreeMap::new();
+            for (key, value) in entries {
+                let key = eval(key, scope)?.as_str()?.to_string();
+                map.insert(key, eval(value, scope)?);
+            }
+            Ok(Val::Map(map))
+        }
+        Expr::Call(None, name, args) if name == "has" => match args.as_slice() {
+            [Expr::Member(target, field)] => match eval(target, scope)? {
+                Val::Map(map) => Ok(Val::Bool(map.contains_key(field))),
+                other => Err(format!("no field {field:?} on {}", other.type_name())),
+            },
+            _ => Err("has() expects a field selection, such as has(req.url.path)".to_string()),
+        },
+        Expr::Call(Some(target), name, args)
+            if matches!(
+                name.as_str(),
+                "exists" | "all" | "exists_one" | "filter" | "map"
+            ) =>
+        {
+            comprehension(name, eval(target, scope)?, args, scope)
+        }
+        Expr::Call(target, name, args) => {
+            let target = target.as_ref().map(|t| eval(t, scope)).transpose()?;
+            let args = args
+                .iter()
+                .map(|arg| eval(arg, scope))
+                .collect::<EvalResult<Vec<_>>>()?;
+            call(name, target, args)
+        }
+    }
+}
+
+fn binary(op: &str, left: Val, right: Val) -> EvalResult<Val> {
+    let mismatch = |left: &Val, right: &Val| {
+        Err(format!(
+            "no such overload: {} {op} {}",
+            left.type_name(),
+            right.type_name()
+        ))
+    };
+    match op {
+        "==" => Ok(Val::Bool(left.equals(&right))),
+        "!=" => Ok(Val::Bool(!left.equals(&right))),
+        "in" => match &right {
+            Val::List(list) => Ok(Val::Bool(list.iter().any(|item| item.equals(&left)))),
+            Val::Map(map) => Ok(Val::Bool(map.contains_key(left.as_str()?))),
+            _ => mismatch(&left, &right),
+        },
+        "<" | "<=" | ">" | ">=" => {
+            let ordering = match (&left, &right) {
+                (Val::Str(a), Val::Str(b)) => a.partial_cmp(b),
+                (Val::Int(a), Val::Int(b)) => a.partial_cmp(b),
+                _ => match (left.as_f64(), right.as_f64()) {
+                    (Some(a), Some(b)) => a.partial_cmp(&b),
+                    _ => return mismatch(&left, &right),
+                },
+            };
+            let Some(ordering) = ordering else {
+                return Ok(Val::Bool(false));
+            };
+            Ok(Val::Bool(match op {
+                "<" => ordering.is_lt(),
+                "<=" => ordering.is_le(),
+                ">" => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            }))
+        }
+        _ => match (left, right) {
+            (Val::Str(a), Val::Str(b)) if op == "+" => Ok(Val::Str(a + &b)),
+            (Val::List(mut a), Val::List(b)) if op == "+" => {
+                a.extend(b);
+                Ok(Val::List(a))
+            }
+            (Val::Int(a), Val::Int(b)) => match op {
+                "+" => a.checked_add(b),
+                "-" => a.checked_sub(b),
+                "*" => a.checked_mul(b),
+                "/" => a.checked_div(b),
+                _ => a.checked_rem(b),
+            }
+            .map(Val::Int)
+            .ok_or_else(|| format!("overflow or division by zero in {a} {op} {b}")),
+            (left, right) => match (left.as_f64(), right.as_f64()) {
+                (Some(a), Some(b)) => Ok(Val::Double(match op {
+                    "+" => a + b,
+                    "-" => a - b,
+                    "*" => a * b,
+                    "/" => a / b,
+                    _ => a % b,
+                })),
+                _ => mismatch(&left, &right),
+            },
+        },
+    }
+}
+
+/// The macros which bind a variable for each item of a list, or key of a map.
+fn comprehension(name: &str, target: Val, args: &[Expr], scope: &Scope) -> EvalResult<Val> {
+    let (var, body) = match args {
+        [Expr::Ident(var), body] => (var.as_str(), body),
+        _ => {
+            return Err(format!(
+                "{name}() expects a variable name and an expression"
+            ))
+        }
+    };
+    let items = match target {
+        Val::List(items) => items,
+        Val::Map(map) => map.into_keys().map(Val::Str).collect(),
+        other => return Err(format!("cannot use {name}() on {}", other.type_name())),
+    };
+    let mut results = Vec::with_capacity(items.len());
+    for item in &items {
+        results.push(eval(body, &Scope::Local(var, item, scope))?);
+    }
+    let truths = || results.iter().map(|r| r.as_bool());
+    match name {
+        "exists" => Ok(Val::Bool(
+            truths().collect::<EvalResult<Vec<_>>>()?.contains(&true),
+        )),
+        "all" => Ok(Val::Bool(
+            !truths().collect::<EvalResult<Vec<_>>>()?.contains(&false),
+        )),
+        "exists_one" => Ok(Val::Bool(
+            truths()
+                .collect::<EvalResult<Vec<_>>>()?
+                .iter()
+                .filter(|t| **t)
+                .count()
+                == 1,
+        )),
+        "filter" => {
+            let mut kept = vec![];
+            for (item, keep) in items.into_iter().zip(truths()) {
+                if keep? {
+                    kept.push(item);
+                }
+            }
+            Ok(Val::List(kept))
+        }
+        _ => Ok(Val::List(results)),
+    }
+}
+
+fn call(name: &str, target: Option<Val>, args: Vec<Val>) -> EvalResult<Val> {
+    // the receiver of a method is its first argument
+    let args: Vec<Val> = target.into_iter().chain(args).collect();
+    match (name, args.as_slice()) {
+        ("size", [Val::Str(s)]) => Ok(Val::Int(s.chars().count() as i64)),
+        ("size", [Val::List(l)]) => Ok(Val::Int(l.len() as i64)),
+        ("size", [Val::Map(m)]) => Ok(Val::Int(m.len() as i64)),
+        ("startsWith", [Val::Str(s), Val::Str(p)]) => Ok(Val::Bool(s.starts_with(p.as_str()))),
+        ("endsWith", [Val::Str(s), Val::Str(p)]) => Ok(Val::Bool(s.ends_with(p.as_str()))),
+        ("contains", [Val::Str(s), Val::Str(p)]) => Ok(Val::Bool(s.contains(p.as_str()))),
+        ("matches", [Val::Str(s), Val::Str(re)]) => Regex::new(re)
+            .map(|re| Val::Bool(re.is_match(s)))
+            .map_err(|e| format!("invalid regular expression {re:?}: {e}")),
+        ("lowerAscii", [Val::Str(s)]) => Ok(Val::Str(s.to_ascii_lowercase())),
+        ("upperAscii", [Val::Str(s)]) => Ok(Val::Str(s.to_ascii_uppercase())),
+        ("inCidrRange", [Val::Str(ip), Val::Str(cidr)]) => in_cidr(ip, cidr).map(Val::Bool),
+        ("inCidrRanges", [Val::Str(ip), Val::List(cidrs)]) => {
+            for cidr in cidrs {
+                if in_cidr(ip, cidr.as_str()?)? {
+                    return Ok(Val::Bool(true));
+                }
+            }
+            Ok(Val::Bool(false))
+        }
+        ("int", [Val::Int(i)]) => Ok(Val::Int(*i)),
+        ("int", [Val::Double(d)]) => Ok(Val::Int(*d as i64)),
+        ("int", [Val::Str(s)]) => s
+            .parse()
+            .map(Val::Int)
+            .map_err(|_| format!("cannot convert {s:?} to int")),
+        ("double", [v @ (Val::Int(_) | Val::Double(_))]) => {
+            Ok(Val::Double(v.as_f64().unwrap_or_default()))
+        }
+        ("double", [Val::Str(s)]) => s
+            .parse()
+            .map(Val::Double)
+            .map_err(|_| format!("cannot convert {s:?} to double")),
+        ("string", [Val::Str(s)]) => Ok(Val::Str(s.clone())),
+        ("string", [Val::Int(i)]) => Ok(Val::Str(i.to_string())),
+        ("string", [Val::Double(d)]) => Ok(Val::Str(d.to_string())),
+        ("string", [Val::Bool(b)]) => Ok(Val::Str(b.to_string())),
+        _ => Err(format!(
+            "no such function: {name}({})",
+            args.iter()
+                .map(|a| a.type_name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
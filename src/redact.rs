@@ -0,0 +1,101 @@
+use std::{
+    borrow::Cow,
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+};
+
+use lazy_static::lazy_static;
+use napi_derive::napi;
+use parking_lot::RwLock;
+use regex::Regex;
+
+const REDACTED: &str = "[REDACTED]";
+// shorter values would mask too much unrelated text, such as common words
+const MIN_SECRET_LEN: usize = 8;
+
+static REDACTION_ENABLED: AtomicBool = AtomicBool::new(true);
+
+lazy_static! {
+    // secret values given to the builders with the number of holders of each,
+    // longest first so they are masked whole
+    static ref SECRETS: RwLock<Vec<(String, usize)>> = RwLock::new(Vec::new());
+    // (pattern, replacement) for secrets which were never given to the builders
+    static ref PATTERNS: Vec<(Regex, &'static str)> = vec![
+        (
+            Regex::new(r"(?s)-----BEGIN [A-Z ]*PRIVATE KEY-----.*?-----END [A-Z ]*PRIVATE KEY-----")
+                .unwrap(),
+            "[REDACTED PRIVATE KEY]",
+        ),
+        // passwords in urls, such as proxy urls
+        (
+            Regex::new(r"(://[^/\s:@]+:)[^/\s@]+@").unwrap(),
+            "$1[REDACTED]@",
+        ),
+        (
+            Regex::new(r"(?i)\b((?:auth_?token|password|client_secret|secret)[\x22']?\s*[:=]\s*(?:Some\()?[\x22']?)[^\s\x22',;&)}]+").unwrap(),
+            "$1[REDACTED]",
+        ),
+        (
+            Regex::new(r"(?i)\b(bearer\s+)[A-Za-z0-9\-._~+/]+=*").unwrap(),
+            "$1[REDACTED]",
+        ),
+    ];
+}
+
+/// Turn masking of secrets in log events and error messages on or off.
+/// Authtokens, passwords, client secrets, webhook secrets and private keys
+/// are replaced with "[REDACTED]". On by default.
+#[napi]
+pub fn set_redaction(enabled: bool) {
+    REDACTION_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Remember a secret value so it is masked wherever it appears, until each
+/// registration of it is undone by [unregister_secret].
+pub(crate) fn register_secret(secret: impl AsRef<str>) {
+    let secret = secret.as_ref().trim();
+    if secret.len() < MIN_SECRET_LEN {
+        return;
+    }
+    let mut secrets = SECRETS.write();
+    match secrets.iter_mut().find(|(s, _)| s == secret) {
+        Some((_, holders)) => *holders += 1,
+        None => {
+            secrets.push((secret.to_string(), 1));
+            secrets.sort_by_key(|(s, _)| std::cmp::Reverse(s.len()));
+        }
+    }
+}
+
+/// Forget a secret value once it has been replaced or its session has closed.
+pub(crate) fn unregister_secret(secret: impl AsRef<str>) {
+    let secret = secret.as_ref().trim();
+    let mut secrets = SECRETS.write();
+    if let Some(position) = secrets.iter().position(|(s, _)| s == secret) {
+        secrets[position].1 -= 1;
+        if secrets[position].1 == 0 {
+            secrets.remove(position);
+        }
+    }
+}
+
+/// Mask the known secrets, and anything which looks like a secret, in the text.
+pub(crate) fn redact(text: &str) -> Cow<'_, str> {
+    if !REDACTION_ENABLED.load(Ordering::Relaxed) {
+        return Cow::Borrowed(text);
+    }
+    let mut text = Cow::Borrowed(text);
+    for (secret, _) in SECRETS.read().iter() {
+        if text.contains(secret.as_str()) {
+            text = Cow::Owned(text.replace(secret.as_str(), REDACTED));
+        }
+    }
+    for (pattern, replacement) in PATTERNS.iter() {
+        if let Cow::Owned(replaced) = pattern.replace_all(&text, *replacement) {
+            text = Cow::Owned(replaced);
+        }
+    }
+    text
+}
@@ -1,10 +1,24 @@
 use std::{
+    collections::HashMap,
     env,
-    sync::Arc,
-    time::Duration,
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+    str::FromStr,
+    sync::{
+        Arc,
+        Weak,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
 };
 
 use bytes::Bytes;
+use futures::future;
 use lazy_static::lazy_static;
 use napi::{
     bindgen_prelude::*,
@@ -20,6 +34,7 @@ use ngrok::{
     session::{
         default_connect,
         ConnectError,
+        Connector,
         SessionBuilder as NgrokSessionBuilder,
         Update,
     },
@@ -28,14 +43,36 @@ use ngrok::{
 };
 use parking_lot::Mutex as SyncMutex;
 use rustls::ClientConfig;
-use tokio::sync::Mutex;
+use tokio::{
+    net::TcpStream,
+    sync::Mutex,
+    time::timeout,
+};
 use tracing::{
     debug,
+    field,
     info,
+    info_span,
+    warn,
+    Instrument,
+    Span,
 };
+use url::Url;
 
+#[cfg(feature = "telemetry")]
+use crate::telemetry::record_sessions;
 use crate::{
+    connect::url_config,
+    events::{
+        register_session_events,
+        rename_session_events,
+        unregister_session_events,
+        SessionEvent,
+        SessionEventSender,
+        SessionEvents,
+    },
     listener::{
+        recreate_listeners,
         remove_global_listener,
         search_listeners,
         Listener,
@@ -48,38 +85,131 @@ use crate::{
     },
     napi_err,
     napi_ngrok_err,
+    pinning::{
+        ServerCertPinning,
+        ServerCertPins,
+        ServerRoots,
+    },
+    redact::{
+        register_secret,
+        unregister_secret,
+    },
 };
 
 const CLIENT_TYPE: &str = "ngrok-javascript";
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+// how long to wait on a tcp dial when measuring server address latency
+const LATENCY_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
 
 // appease clippy
-type TsfnOption = Option<Arc<Mutex<ThreadsafeFunction<Vec<String>, ErrorStrategy::Fatal>>>>;
+type Tsfn<A> = Arc<Mutex<ThreadsafeFunction<Vec<A>, ErrorStrategy::Fatal>>>;
+type TsfnOption = Option<Tsfn<String>>;
+// the error argument of connection handlers is undefined when connected
+type ConnectionArg = Either<String, Undefined>;
+// the id of a connected session, known only once it has connected
+type SessionIdSlot = Arc<SyncMutex<Option<String>>>;
 
 lazy_static! {
     // Allow user to store a default auth token to use for all sessions
     static ref AUTH_TOKEN: Mutex<Option<String>> = Mutex::new(None);
+    // auth tokens stored by account name, for sessions which ask for an account
+    static ref ACCOUNT_AUTH_TOKENS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+    // connected sessions, by id, so they can all be closed on shutdown. weak, so a session
+    // nothing else refers to can still drop, which removes it from here.
+    static ref SESSIONS: SyncMutex<HashMap<String, Weak<SessionShared>>> = SyncMutex::new(HashMap::new());
+}
+
+/// How to choose between multiple server addresses when (re)connecting.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum ServerAddrStrategy {
+    /// Try each address in the order given.
+    #[default]
+    Ordered,
+    /// Try each address in order of lowest measured TCP connect latency.
+    Latency,
+}
+
+impl FromStr for ServerAddrStrategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "ordered" => Ok(ServerAddrStrategy::Ordered),
+            "latency" => Ok(ServerAddrStrategy::Latency),
+            _ => Err(napi_err(format!("Unknown server address strategy: {s:?}"))),
+        }
+    }
+}
+
+/// Options for {@link authtoken} and {@link removeAuthtoken}.
+///
+/// @group Functions
+#[napi(object)]
+pub struct AuthtokenOptions {
+    /// The name of the ngrok account the authtoken belongs to. Sessions use it when
+    /// built with {@link SessionBuilder.account}, or by `forward()` with the `account` option.
+    pub account: Option<String>,
 }
 
-/// Set the default auth token to use for any future sessions.
+/// Set the default auth token to use for any future sessions, or the auth token of
+/// the named account.
 #[napi]
 #[allow(dead_code)]
-pub async fn authtoken(authtoken: String) {
-    let mut token = AUTH_TOKEN.lock().await;
-    token.replace(authtoken);
+pub async fn authtoken(authtoken: String, options: Option<AuthtokenOptions>) {
+    register_secret(&authtoken);
+    let replaced = match options.and_then(|o| o.account) {
+        Some(account) => ACCOUNT_AUTH_TOKENS.lock().await.insert(account, authtoken),
+        None => AUTH_TOKEN.lock().await.replace(authtoken),
+    };
+    if let Some(replaced) = replaced {
+        unregister_secret(replaced);
+    }
+}
+
+/// List the names of the accounts with a stored auth token.
+#[napi]
+pub async fn authtoken_accounts() -> Vec<String> {
+    let mut accounts: Vec<String> = ACCOUNT_AUTH_TOKENS.lock().await.keys().cloned().collect();
+    accounts.sort();
+    accounts
+}
+
+/// Remove the default auth token, or the auth token of the named account.
+/// Returns whether there was one to remove.
+#[napi]
+pub async fn remove_authtoken(options: Option<AuthtokenOptions>) -> bool {
+    let removed = match options.and_then(|o| o.account) {
+        Some(account) => ACCOUNT_AUTH_TOKENS.lock().await.remove(&account),
+        None => AUTH_TOKEN.lock().await.take(),
+    };
+    removed.map(unregister_secret).is_some()
 }
 
 /// The builder for an ngrok session.
 ///
 /// @group Listener and Sessions
-#[napi]
+#[napi(custom_finalize)]
 #[allow(dead_code)]
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub(crate) struct SessionBuilder {
     raw_builder: Arc<SyncMutex<NgrokSessionBuilder>>,
-    connect_handler: TsfnOption,
+    connect_handler: Option<Tsfn<ConnectionArg>>,
     disconnect_handler: TsfnOption,
     auth_token_set: bool,
+    // the authtoken configured on the builder, masked in logs while the builder is around
+    authtoken: Option<String>,
+    server_addrs: Vec<(String, u16)>,
+    server_addr_strategy: ServerAddrStrategy,
+    stop_handler: Option<Tsfn<()>>,
+    restart_handler: Option<Tsfn<()>>,
+    restart_listeners: bool,
+    update_handler: Option<Tsfn<UpdateRequest>>,
+    heartbeat_handler: Option<Tsfn<Option<u32>>>,
+    authtoken_provider: Option<Tsfn<()>>,
+    account: Option<String>,
+    server_cert_pins: ServerCertPins,
+    // the root CAs as configured on the upstream builder, which the pinning validates against
+    server_roots: ServerRoots,
 }
 
 #[napi]
@@ -110,8 +240,8 @@ impl SessionBuilder {
     /// [authtoken parameter in the ngrok docs]: https://ngrok.com/docs/ngrok-agent/config#authtoken
     #[napi]
     pub fn authtoken(&mut self, authtoken: String) -> &Self {
-        let mut builder = self.raw_builder.lock();
-        builder.authtoken(authtoken);
+        self.raw_builder.lock().authtoken(authtoken.clone());
+        self.replace_authtoken(authtoken);
         self.auth_token_set = true;
         self
     }
@@ -120,9 +250,9 @@ impl SessionBuilder {
     /// NGROK_AUTHTOKEN environment variable.
     #[napi]
     pub fn authtoken_from_env(&mut self) -> &Self {
-        let mut builder = self.raw_builder.lock();
-        builder.authtoken_from_env();
+        self.raw_builder.lock().authtoken_from_env();
         if let Ok(token) = env::var("NGROK_AUTHTOKEN") {
+            self.replace_authtoken(token.clone());
             if !token.is_empty() {
                 self.auth_token_set = true;
             }
@@ -130,6 +260,63 @@ impl SessionBuilder {
         self
     }
 
+    /// Shortcut for calling [SessionBuilder::authtoken] with the contents of the
+    /// file at the given path, such as a mounted Kubernetes secret.
+    #[napi]
+    pub fn authtoken_from_file(&mut self, path: String) -> Result<&Self> {
+        let token = fs::read_to_string(&path)
+            .map_err(|e| napi_err(format!("Failed to read authtoken file {path}: {e}")))?;
+        let token = token.trim();
+        if token.is_empty() {
+            return Err(napi_err(format!("Authtoken file {path} is empty")));
+        }
+        Ok(self.authtoken(token.to_string()))
+    }
+
+    /// Shortcut for calling [SessionBuilder::authtoken] with the authtoken saved in the
+    /// ngrok agent config file by `ngrok config add-authtoken`. The file is looked for in
+    /// its default locations, unless a path is given.
+    #[napi]
+    pub fn authtoken_from_agent_config(&mut self, path: Option<String>) -> Result<&Self> {
+        let paths = match path {
+            Some(path) => vec![PathBuf::from(path)],
+            None => agent_config_paths(),
+        };
+        let Some(path) = paths.iter().find(|path| path.is_file()) else {
+            let paths: Vec<_> = paths.iter().map(|p| p.display().to_string()).collect();
+            return Err(napi_err(format!(
+                "No ngrok agent config found, looked for {}",
+                paths.join(", ")
+            )));
+        };
+        let token = agent_config_authtoken(path)?;
+        Ok(self.authtoken(token))
+    }
+
+    /// Configures the session to authenticate with the authtoken stored for the
+    /// named account by {@link authtoken}, rather than the default one. Connecting
+    /// fails if no authtoken is stored for the account, or if an authtoken is also
+    /// configured on this builder, such as with {@link authtokenFromEnv}.
+    #[napi]
+    pub fn account(&mut self, name: String) -> &Self {
+        self.account = Some(name);
+        self
+    }
+
+    /// Configures an async function which provides the authtoken, such as one fetched
+    /// from a secrets vault. It is called when connecting, and again once the session
+    /// is disconnected, but not on each retry while reconnecting. When the token has been
+    /// rotated, the session is reconnected with the new token and its listeners are
+    /// recreated, as with {@link restartListeners}.
+    ///
+    /// The provided token takes precedence over any other authtoken configured.
+    #[napi(ts_args_type = "provider: () => Promise<string>")]
+    pub fn authtoken_provider(&mut self, env: Env, provider: JsFunction) -> &Self {
+        // create threadsafe function
+        self.authtoken_provider = Some(create_tsfn(env, provider));
+        self
+    }
+
     /// Add client type and version information for a client application.
     ///
     /// This is a way for applications and library consumers of this crate
@@ -210,6 +397,43 @@ impl SessionBuilder {
         builder
             .server_addr(addr)
             .map_err(|e| napi_err(format!("{e}")))?;
+        self.server_addrs.clear();
+        Ok(self)
+    }
+
+    /// Configures an ordered list of network addresses to dial to connect to the
+    /// ngrok service, for failover between self-hosted or multiple agent ingresses.
+    /// On connect, and on every reconnect, each address is tried until one succeeds.
+    ///
+    /// The strategy is one of "ordered", which tries the addresses in the order given,
+    /// or "latency", which measures the TCP connect time to each address and tries
+    /// the fastest first. Defaults to "ordered".
+    ///
+    /// The address in use is available from {@link Session.serverAddr}, and is passed
+    /// to the connection and disconnection handlers.
+    #[napi(ts_args_type = "addrs: Array<string>, strategy?: \"ordered\" | \"latency\"")]
+    pub fn server_addrs(&mut self, addrs: Vec<String>, strategy: Option<String>) -> Result<&Self> {
+        if addrs.is_empty() {
+            return Err(napi_err("At least one server address is required"));
+        }
+        let strategy = strategy
+            .map(|s| ServerAddrStrategy::from_str(&s))
+            .transpose()?
+            .unwrap_or_default();
+        let server_addrs = addrs
+            .iter()
+            .map(|addr| parse_server_addr(addr))
+            .collect::<Result<Vec<_>>>()?;
+
+        // the upstream builder validates the server name, and uses the first address by default
+        let mut builder = self.raw_builder.lock();
+        for addr in addrs.iter().rev() {
+            builder
+                .server_addr(addr)
+                .map_err(|e| napi_err(format!("{e}")))?;
+        }
+        self.server_addrs = server_addrs;
+        self.server_addr_strategy = strategy;
         Ok(self)
     }
 
@@ -225,7 +449,10 @@ impl SessionBuilder {
     pub fn root_cas(&mut self, root_cas: String) -> Result<&Self> {
         let mut builder = self.raw_builder.lock();
         builder
-            .root_cas(root_cas)
+            .root_cas(root_cas.clone())
+            .map_err(|e| napi_err(format!("{e}")))?;
+        self.server_roots
+            .root_cas(&root_cas)
             .map_err(|e| napi_err(format!("{e}")))?;
         Ok(self)
     }
@@ -243,13 +470,34 @@ impl SessionBuilder {
     pub fn ca_cert(&mut self, cert_bytes: Uint8Array) -> &Self {
         let mut builder = self.raw_builder.lock();
         builder.ca_cert(Bytes::from(cert_bytes.to_vec()));
+        self.server_roots.ca_cert(cert_bytes.to_vec());
         self
     }
 
+    /// Pins the certificate of the ngrok service, for deployments which should not
+    /// rely on certificate authorities alone. The session fails to connect unless the
+    /// certificate chain presented matches one of the pins, after being validated
+    /// against the root CAs as usual.
+    ///
+    /// Each pin is either the SHA-256 hash of a certificate's public key, in the form
+    /// "sha256/<base64>", which may pin any certificate in the chain, or the SHA-256
+    /// fingerprint of the leaf certificate in hex, colons between the bytes allowed.
+    /// The backup pins, such as those of a key which is not in use yet, are also
+    /// accepted, with a warning that the primary pins may need updating.
+    #[napi]
+    pub fn server_cert_pins(
+        &mut self,
+        pins: Vec<String>,
+        backup_pins: Option<Vec<String>>,
+    ) -> Result<&Self> {
+        self.server_cert_pins = ServerCertPins::new(pins, backup_pins.unwrap_or_default())?;
+        Ok(self)
+    }
+
     /// Configures a function which is called to after a disconnection to the
     /// ngrok service. In the event of network disruptions, it will be called each time
-    /// the session reconnects. The handler is given the address that will be used to
-    /// connect the session to, e.g. "example.com:443", and the message from the error
+    /// the session reconnects. The handler is given the address the session was
+    /// connected to, e.g. "example.com:443", and the message from the error
     /// that occurred. Returning true from the handler will cause the session to
     /// reconnect, returning false will cause the Session to throw an uncaught error.
     #[napi(ts_args_type = "handler: (addr: string, error: string) => boolean")]
@@ -257,76 +505,175 @@ impl SessionBuilder {
         // create threadsafe function
         let tsfn = create_tsfn(env, handler);
         self.disconnect_handler = Some(tsfn);
-        self.update_connector()
+        self
     }
 
+    /// Configures a function which is called each time a connection to the ngrok
+    /// service is attempted. The handler is given the status, either "connected" or
+    /// "closed", the message from the error that occurred, if any, and the server
+    /// address that was dialed, e.g. "example.com:443".
+    #[napi(
+        ts_args_type = "handler: (status: string, error: string | undefined, addr: string) => void"
+    )]
     pub fn handle_connection(&mut self, env: Env, handler: JsFunction) -> &Self {
         // create threadsafe function
         let tsfn = create_tsfn(env, handler);
         self.connect_handler = Some(tsfn);
-        self.update_connector()
+        self
     }
 
-    /// Update the connector callback in the upstream rust sdk.
-    fn update_connector(&mut self) -> &Self {
-        // register connect handler. this needs the return value, so cannot use call_tsfn().
-        let mut builder = self.raw_builder.lock();
+    /// Build the connector callback for the upstream rust sdk, recording the
+    /// server address in use into `in_use`. The session is restarted on reconnect
+    /// if the authtoken provider no longer returns `authtoken`.
+    fn connector(
+        &self,
+        in_use: Arc<SyncMutex<Option<String>>>,
+        events: SessionEventSender,
+        session_id: SessionIdSlot,
+        authtoken: Option<String>,
+    ) -> impl Connector {
         // clone for move to connector function
         let connect_handler = self.connect_handler.clone();
         let disconnect_handler = self.disconnect_handler.clone();
-        builder.connector(
-            move |host: String,
-                  port: u16,
-                  tls_config: Arc<ClientConfig>,
-                  err: Option<AcceptError>| {
-                // clone for async move out of environment
-                let conn_tsfn = connect_handler.clone();
-                let disconn_tsfn = disconnect_handler.clone();
-                async move {
+        let server_addrs = self.server_addrs.clone();
+        let strategy = self.server_addr_strategy;
+        let pinning = ServerCertPinning::new(&self.server_cert_pins, &self.server_roots);
+        let builder = self.clone();
+        move |host: String, port: u16, tls_config: Arc<ClientConfig>, err: Option<AcceptError>| {
+            // clone for async move out of environment
+            let conn_tsfn = connect_handler.clone();
+            let disconn_tsfn = disconnect_handler.clone();
+            let in_use = in_use.clone();
+            let events = events.clone();
+            let builder = builder.clone();
+            let session_id = session_id.clone();
+            let authtoken = authtoken.clone();
+            let pinning = pinning.clone();
+            let server_addrs = if server_addrs.is_empty() {
+                vec![(host, port)]
+            } else {
+                server_addrs.clone()
+            };
+            async move {
+                if let Some(err) = err.clone() {
+                    let prev_addr = in_use.lock().clone().unwrap_or_else(|| {
+                        let (host, port) = &server_addrs[0];
+                        format!("{host}:{port}")
+                    });
+                    events.send(SessionEvent::disconnected(
+                        prev_addr.clone(),
+                        err.to_string(),
+                    ));
                     // call disconnect javascript handler
                     if let Some(handler) = disconn_tsfn {
-                        if let Some(err) = err.clone() {
-                            // call javascript handler
-                            let resp: Option<bool> = handler
-                                .clone()
-                                .lock()
-                                .await
-                                .call_async(vec![format!("{host}:{port}"), err.to_string()])
-                                .await
-                                .map_err(|_e| ConnectError::Canceled)?;
-
-                            if let Some(reconnect) = resp {
-                                if !reconnect {
-                                    info!("Aborting connection to {host}:{port}");
-                                    println!("Aborting connection to {host}:{port}"); // still shown if this takes down the process
-                                    return Err(ConnectError::Canceled);
-                                }
-                            }
-                        };
-                    }
-                    // call the upstream connector
-                    let res = default_connect(host, port, tls_config, err).await;
-
-                    // call connect handler
-                    if let Some(handler) = conn_tsfn {
-                        let args = match &res {
-                            Ok(_) => vec!["connected".to_string()],
-                            Err(err) => vec!["closed".to_string(), err.to_string()],
-                        };
-                        // call javascript handler
-                        handler
+                        // call javascript handler. this needs the return value, so cannot use call_tsfn().
+                        let resp: Option<bool> = handler
                             .clone()
                             .lock()
                             .await
-                            .call_async(args)
+                            .call_async(vec![prev_addr.clone(), err.to_string()])
                             .await
-                            .map_err(|_e| ConnectError::Canceled)?;
+                            .map_err(|_e| {
+                                events.end();
+                                ConnectError::Canceled
+                            })?;
+
+                        if let Some(reconnect) = resp {
+                            if !reconnect {
+                                info!("Aborting connection to {prev_addr}");
+                                println!("Aborting connection to {prev_addr}"); // still shown if this takes down the process
+                                                                                // the session is over without being closed
+                                events.end();
+                                return Err(ConnectError::Canceled);
+                            }
+                        }
+                    };
+
+                    // the upstream sdk reconnects with the token it was built with,
+                    // so a rotated token needs a new session. the provider is asked once
+                    // per disconnect, rather than again on every retry.
+                    let retrying = matches!(err, AcceptError::Reconnect(_));
+                    let rotated = if retrying {
+                        None
+                    } else {
+                        builder.rotated_authtoken(authtoken.as_deref()).await
+                    };
+                    let session = session_id.lock().as_ref().and_then(|id| find_session(id));
+                    if let (Some(token), Some(session)) = (rotated, session) {
+                        let old_id = session.id();
+                        info!("Authtoken rotated, restarting session {old_id:?}");
+                        // restart outside of the reconnect, which ends here for the old session
+                        tokio::spawn(async move {
+                            match builder.restart(&session, Some(token)).await {
+                                Ok(()) => {}
+                                // the old session is disconnected, so closing it may fail
+                                Err(e) if session.id() != old_id => debug!("{}", e.reason),
+                                Err(e) => warn!(
+                                    "Failed to restart session {old_id:?} with the rotated authtoken: {}",
+                                    e.reason
+                                ),
+                            }
+                        });
+                        return Err(ConnectError::Canceled);
                     }
-                    res
                 }
-            },
-        );
-        self
+
+                // pins are checked while validating the certificate in the handshake
+                let tls_config = match &pinning {
+                    Some(pinning) => pinning.tls_config(&tls_config)?,
+                    None => tls_config,
+                };
+
+                // call the upstream connector for each candidate until one succeeds
+                let mut res = Err(ConnectError::Canceled);
+                let mut addr = String::new();
+                for (host, port) in order_server_addrs(server_addrs, strategy).await {
+                    addr = format!("{host}:{port}");
+                    debug!("Connecting to server address {addr}");
+                    res = default_connect(host, port, tls_config.clone(), err.clone()).await;
+                    match &res {
+                        Ok(_) => {
+                            info!("Connected to server address {addr}");
+                            in_use.lock().replace(addr.clone());
+                            events.send(SessionEvent::connected(addr.clone()));
+                            break;
+                        }
+                        Err(e) => warn!(
+                            "Failed to connect to server address {addr}: {}",
+                            connect_error_message(e)
+                        ),
+                    }
+                }
+
+                // call connect handler
+                if let Some(handler) = conn_tsfn {
+                    let args = match &res {
+                        Ok(_) => vec![
+                            Either::A("connected".to_string()),
+                            Either::B(()),
+                            Either::A(addr),
+                        ],
+                        Err(err) => vec![
+                            Either::A("closed".to_string()),
+                            Either::A(err.to_string()),
+                            Either::A(addr),
+                        ],
+                    };
+                    // call javascript handler
+                    handler
+                        .clone()
+                        .lock()
+                        .await
+                        .call_async(args)
+                        .await
+                        .map_err(|_e| {
+                            events.end();
+                            ConnectError::Canceled
+                        })?;
+                }
+                res
+            }
+        }
     }
 
     /// Configures a function which is called when the ngrok service requests that
@@ -342,10 +689,7 @@ impl SessionBuilder {
     #[napi(ts_args_type = "handler: () => void")]
     pub fn handle_stop_command(&mut self, env: Env, handler: JsFunction) -> &Self {
         // create threadsafe function
-        let tsfn = create_tsfn(env, handler);
-        // register stop handler
-        let mut builder = self.raw_builder.lock();
-        builder.handle_stop_command(move |_req| call_tsfn(tsfn.clone(), vec![()]));
+        self.stop_handler = Some(create_tsfn(env, handler));
         self
     }
 
@@ -363,10 +707,20 @@ impl SessionBuilder {
     #[napi(ts_args_type = "handler: () => void")]
     pub fn handle_restart_command(&mut self, env: Env, handler: JsFunction) -> &Self {
         // create threadsafe function
-        let tsfn = create_tsfn(env, handler);
-        // register restart handler
-        let mut builder = self.raw_builder.lock();
-        builder.handle_restart_command(move |_req| call_tsfn(tsfn.clone(), vec![()]));
+        self.restart_handler = Some(create_tsfn(env, handler));
+        self
+    }
+
+    /// Handle restart commands from the ngrok service by reconnecting the [Session]
+    /// and recreating its listeners with the configuration they were built with,
+    /// resuming any forwarding. Existing Listener objects refer to the recreated
+    /// listeners afterwards, whose new ids are reported by "listener-restarted"
+    /// session events.
+    ///
+    /// A handler configured with {@link handleRestartCommand} is still called.
+    #[napi]
+    pub fn restart_listeners(&mut self) -> &Self {
+        self.restart_listeners = true;
         self
     }
 
@@ -384,16 +738,7 @@ impl SessionBuilder {
     #[napi(ts_args_type = "handler: (update: UpdateRequest) => void")]
     pub fn handle_update_command(&mut self, env: Env, handler: JsFunction) -> &Self {
         // create threadsafe function
-        let tsfn = create_tsfn(env, handler);
-        // register update handler
-        let mut builder = self.raw_builder.lock();
-        builder.handle_update_command(move |req: Update| {
-            let update = UpdateRequest {
-                version: req.version,
-                permit_major_version: req.permit_major_version,
-            };
-            call_tsfn(tsfn.clone(), vec![update])
-        });
+        self.update_handler = Some(create_tsfn(env, handler));
         self
     }
 
@@ -405,42 +750,266 @@ impl SessionBuilder {
     #[napi(ts_args_type = "handler: (latency: number) => void")]
     pub fn handle_heartbeat(&mut self, env: Env, handler: JsFunction) -> &Self {
         // create threadsafe function
-        let tsfn = create_tsfn(env, handler);
+        self.heartbeat_handler = Some(create_tsfn(env, handler));
+        self
+    }
 
-        // register heartbeat handler
-        let mut builder = self.raw_builder.lock();
+    /// Set the builder's authtoken, masking it in logs instead of the one it replaces.
+    fn replace_authtoken(&mut self, token: String) {
+        register_secret(&token);
+        if let Some(replaced) = self.authtoken.replace(token) {
+            unregister_secret(replaced);
+        }
+    }
+
+    /// Fetch the current authtoken from the provider, if there is one.
+    async fn provide_authtoken(&self) -> Result<Option<String>> {
+        let Some(provider) = self.authtoken_provider.clone() else {
+            return Ok(None);
+        };
+        let token: Promise<String> = provider
+            .lock()
+            .await
+            .call_async(vec![])
+            .await
+            .map_err(|e| napi_err(format!("Failed to call authtoken provider: {e}")))?;
+        let token = token
+            .await
+            .map_err(|e| napi_err(format!("Authtoken provider failed: {}", e.reason)))?;
+        Ok(Some(token))
+    }
+
+    /// The authtoken the provider now gives, if it is different than `current`.
+    /// Provider errors are logged and treated as no change.
+    async fn rotated_authtoken(&self, current: Option<&str>) -> Option<String> {
+        match self.provide_authtoken().await {
+            Ok(token) => token.filter(|token| Some(token.as_str()) != current),
+            Err(e) => {
+                warn!("{}", e.reason);
+                None
+            }
+        }
+    }
+
+    /// Register the heartbeat and command handlers in the upstream rust sdk,
+    /// sending each to the session's event stream as well as the javascript handler.
+    fn register_handlers(
+        &self,
+        builder: &mut NgrokSessionBuilder,
+        events: &SessionEventSender,
+        session_id: &SessionIdSlot,
+    ) {
+        // heartbeats are always reported to the event stream
+        let tsfn = self.heartbeat_handler.clone();
+        let tx = events.clone();
         builder.handle_heartbeat(move |latency: Option<Duration>| {
-            call_tsfn(
-                tsfn.clone(),
-                vec![latency.map(|d| u32::try_from(d.as_millis()).ok())],
-            )
+            let latency = latency.and_then(|d| u32::try_from(d.as_millis()).ok());
+            tx.send(SessionEvent::heartbeat(latency));
+            let tsfn = tsfn.clone();
+            async move {
+                match tsfn {
+                    Some(tsfn) => call_tsfn(tsfn, vec![latency]).await,
+                    None => Ok(()),
+                }
+            }
         });
-        self
+
+        // commands are only accepted when a handler is registered
+        if let Some(tsfn) = self.stop_handler.clone() {
+            let tx = events.clone();
+            builder.handle_stop_command(move |_req| {
+                tx.send(SessionEvent::command("stop", None));
+                // the session is expected to stop, so this is the last event
+                tx.end();
+                call_tsfn(tsfn.clone(), vec![()])
+            });
+        }
+        if self.restart_listeners || self.restart_handler.is_some() {
+            let tsfn = self.restart_handler.clone();
+            let tx = events.clone();
+            let restart = self
+                .restart_listeners
+                .then(|| (self.clone(), session_id.clone()));
+            builder.handle_restart_command(move |_req| {
+                tx.send(SessionEvent::command("restart", None));
+                if let Some((builder, session_id)) = restart.clone() {
+                    // restart in the background, so the command is answered right away
+                    tokio::spawn(async move {
+                        let session = session_id.lock().as_ref().and_then(|id| find_session(id));
+                        if let Some(session) = session {
+                            if let Err(e) = builder.restart(&session, None).await {
+                                warn!("{}", e.reason);
+                            }
+                        }
+                    });
+                }
+                let tsfn = tsfn.clone();
+                async move {
+                    match tsfn {
+                        Some(tsfn) => call_tsfn(tsfn, vec![()]).await,
+                        None => Ok(()),
+                    }
+                }
+            });
+        }
+        if let Some(tsfn) = self.update_handler.clone() {
+            let tx = events.clone();
+            builder.handle_update_command(move |req: Update| {
+                let update = UpdateRequest {
+                    version: req.version,
+                    permit_major_version: req.permit_major_version,
+                };
+                tx.send(SessionEvent::command("update", Some(update.clone())));
+                call_tsfn(tsfn.clone(), vec![update])
+            });
+        }
     }
 
     /// Attempt to establish an ngrok session using the current configuration.
     #[napi]
     pub async fn connect(&self) -> Result<Session> {
+        let server_addr = Arc::new(SyncMutex::new(None));
+        let events = SessionEventSender::default();
+        let session_id = SessionIdSlot::default();
+        let provided_auth_token = self.provide_authtoken().await?;
+        let (s, authtoken) = self
+            .connect_raw(&server_addr, &events, &session_id, provided_auth_token)
+            .await?;
+        let id = s.id();
+        session_id.lock().replace(id.clone());
+        register_session_events(id.clone(), events.clone());
+        let session = Session {
+            shared: Arc::new(SessionShared {
+                raw_session: SyncMutex::new(s),
+                authtoken: SyncMutex::new(authtoken),
+                server_addr,
+                events,
+            }),
+        };
+        SESSIONS.lock().insert(id, Arc::downgrade(&session.shared));
+        #[cfg(feature = "telemetry")]
+        record_sessions(1);
+        Ok(session)
+    }
+
+    /// Connect the upstream session, reporting to the given server address and event stream,
+    /// with the authtoken from the provider if there is one.
+    /// Also returns the authtoken it connected with, which is masked in logs until the
+    /// session is done with it.
+    async fn connect_raw(
+        &self,
+        server_addr: &Arc<SyncMutex<Option<String>>>,
+        events: &SessionEventSender,
+        session_id: &SessionIdSlot,
+        provided_auth_token: Option<String>,
+    ) -> Result<(NgrokSession, Option<String>)> {
+        // either would be silently ignored for the other, connecting with the wrong account
+        if let (true, Some(account)) = (self.auth_token_set, &self.account) {
+            return Err(napi_err(format!(
+                "Both an authtoken and the account {account:?} were configured, use only one of them"
+            )));
+        }
         let mut builder = self.raw_builder.lock().clone();
+        builder.connector(self.connector(
+            server_addr.clone(),
+            events.clone(),
+            session_id.clone(),
+            provided_auth_token.clone(),
+        ));
+        self.register_handlers(&mut builder, events, session_id);
         // set default auth token if it exists
         let default_auth_token = AUTH_TOKEN.lock().await;
         let mut auth_token_set = self.auth_token_set;
-        if default_auth_token.is_some() && !self.auth_token_set {
-            builder.authtoken(default_auth_token.as_ref().unwrap());
+        let mut authtoken = None;
+        if let Some(token) = &provided_auth_token {
+            builder.authtoken(token);
+            auth_token_set = true;
+            authtoken = provided_auth_token.clone();
+        } else if self.auth_token_set {
+            // configured on the builder
+            authtoken = self.authtoken.clone();
+        } else if let Some(account) = &self.account {
+            let tokens = ACCOUNT_AUTH_TOKENS.lock().await;
+            let token = tokens
+                .get(account)
+                .ok_or_else(|| napi_err(format!("No authtoken stored for account {account:?}")))?;
+            builder.authtoken(token);
             auth_token_set = true;
+            authtoken = Some(token.clone());
+        } else if let Some(token) = default_auth_token.as_ref() {
+            builder.authtoken(token);
+            auth_token_set = true;
+            authtoken = Some(token.clone());
+        }
+        if let Some(token) = &authtoken {
+            register_secret(token);
         }
-        // connect to ngrok
+        // connect to ngrok, the id is only known once connected
+        let span = info_span!("session", session_id = field::Empty);
         builder
             .connect()
+            .instrument(span.clone())
             .await
-            .map(|s| {
+            .inspect(|s| {
+                span.record("session_id", field::display(s.id()));
                 let maybe_with = if auth_token_set { "with" } else { "without" };
-                info!("Session created {:?}, {maybe_with} auth token", s.id());
-                Session {
-                    raw_session: Arc::new(SyncMutex::new(s)),
+                span.in_scope(|| {
+                    info!(session_id = %s.id(), "Session created {:?}, {maybe_with} auth token", s.id())
+                });
+            })
+            .map(|s| (s, authtoken.clone()))
+            .map_err(|e| {
+                if let Some(token) = &authtoken {
+                    unregister_secret(token);
+                }
+                match e {
+                    ConnectError::Tls(_) => napi_err(format!(
+                        "failed to connect session: {}",
+                        connect_error_message(&e)
+                    )),
+                    _ => napi_ngrok_err("failed to connect session", &e),
                 }
             })
-            .map_err(|e| napi_ngrok_err("failed to connect session", &e))
+    }
+
+    /// Reconnect the session for a restart command or a rotated authtoken, recreating its
+    /// listeners on the new connection before closing the old one. The authtoken is fetched
+    /// from the provider, unless it was already.
+    async fn restart(&self, session: &Session, provided_auth_token: Option<String>) -> Result<()> {
+        let provided_auth_token = match provided_auth_token {
+            Some(token) => Some(token),
+            None => self.provide_authtoken().await?,
+        };
+        let session_id = SessionIdSlot::default();
+        let (raw, authtoken) = self
+            .connect_raw(
+                &session.shared.server_addr,
+                &session.shared.events,
+                &session_id,
+                provided_auth_token,
+            )
+            .await?;
+        let new_id = raw.id();
+        session_id.lock().replace(new_id.clone());
+        let mut old = std::mem::replace(&mut *session.shared.raw_session.lock(), raw);
+        let old_authtoken = std::mem::replace(&mut *session.shared.authtoken.lock(), authtoken);
+        if let Some(token) = old_authtoken {
+            unregister_secret(token);
+        }
+        let old_id = old.id();
+        info!("Session {old_id:?} restarted as {new_id:?}");
+        {
+            let mut sessions = SESSIONS.lock();
+            sessions.remove(&old_id);
+            sessions.insert(new_id.clone(), Arc::downgrade(&session.shared));
+        }
+        rename_session_events(&old_id, new_id);
+
+        recreate_listeners(&old_id, session).await;
+        old.close()
+            .instrument(session_span(&old_id))
+            .await
+            .map_err(|e| napi_ngrok_err("failed to close restarted session", &e))
     }
 }
 
@@ -448,9 +1017,36 @@ impl SessionBuilder {
 ///
 /// @group Listener and Sessions
 #[napi(custom_finalize)]
+#[derive(Clone)]
 pub(crate) struct Session {
-    #[allow(dead_code)]
-    raw_session: Arc<SyncMutex<NgrokSession>>,
+    shared: Arc<SessionShared>,
+}
+
+/// The state shared by all handles to a session.
+struct SessionShared {
+    raw_session: SyncMutex<NgrokSession>,
+    // the authtoken the session connected with, until it is closed
+    authtoken: SyncMutex<Option<String>>,
+    server_addr: Arc<SyncMutex<Option<String>>>,
+    events: SessionEventSender,
+}
+
+impl Drop for SessionShared {
+    fn drop(&mut self) {
+        // the session can no longer be closed explicitly, so end its event streams here
+        self.events.end();
+        if let Some(token) = self.authtoken.get_mut().take() {
+            unregister_secret(token);
+        }
+        let id = self.raw_session.get_mut().id();
+        let mut sessions = SESSIONS.lock();
+        // a dead entry is this session's, unless it was already closed
+        if sessions.get(&id).is_some_and(|s| s.strong_count() == 0) {
+            sessions.remove(&id);
+            #[cfg(feature = "telemetry")]
+            record_sessions(-1);
+        }
+    }
 }
 
 #[napi]
@@ -459,42 +1055,91 @@ impl Session {
     /// Start building a listener backing an HTTP endpoint.
     #[napi]
     pub fn http_endpoint(&self) -> HttpListenerBuilder {
-        let session = self.raw_session.lock().clone();
+        let session = self.shared.raw_session.lock().clone();
         HttpListenerBuilder::new(session.clone(), session.http_endpoint())
     }
 
     /// Start building a listener backing a TCP endpoint.
     #[napi]
     pub fn tcp_endpoint(&self) -> TcpListenerBuilder {
-        let session = self.raw_session.lock().clone();
+        let session = self.shared.raw_session.lock().clone();
         TcpListenerBuilder::new(session.clone(), session.tcp_endpoint())
     }
 
     /// Start building a listener backing a TLS endpoint.
     #[napi]
     pub fn tls_endpoint(&self) -> TlsListenerBuilder {
-        let session = self.raw_session.lock().clone();
+        let session = self.shared.raw_session.lock().clone();
         TlsListenerBuilder::new(session.clone(), session.tls_endpoint())
     }
 
+    /// Start building a listener for the endpoint with the given url, inferring the kind of
+    /// listener from its scheme, and the domain or TCP address from the rest of it, e.g.
+    /// "https://example.ngrok.app", "tls://example.ngrok.app" or "tcp://1.tcp.ngrok.io:12345".
+    #[napi]
+    pub fn endpoint(
+        &self,
+        url: String,
+    ) -> Result<Either3<HttpListenerBuilder, TcpListenerBuilder, TlsListenerBuilder>> {
+        let config = url_config(&url)?;
+        Ok(match config.proto.as_deref() {
+            Some("tcp") => {
+                let mut bld = self.tcp_endpoint();
+                bld.apply_config(&config)?;
+                Either3::B(bld)
+            }
+            Some("tls") => {
+                let mut bld = self.tls_endpoint();
+                bld.apply_config(&config)?;
+                Either3::C(bld)
+            }
+            _ => {
+                let mut bld = self.http_endpoint();
+                bld.apply_config(&config)?;
+                Either3::A(bld)
+            }
+        })
+    }
+
     /// Start building a labeled listener.
     #[napi]
     pub fn labeled_listener(&self) -> LabeledListenerBuilder {
-        let session = self.raw_session.lock().clone();
+        let session = self.shared.raw_session.lock().clone();
         LabeledListenerBuilder::new(session.clone(), session.labeled_tunnel())
     }
 
+    /// The server address this session is currently connected to, e.g. "connect.ngrok-agent.com:443".
+    #[napi]
+    pub fn server_addr(&self) -> Option<String> {
+        self.shared.server_addr.lock().clone()
+    }
+
+    /// Subscribe to this session's events, such as reconnects, heartbeats, commands
+    /// from the ngrok service, and closed listeners. The returned object is an async
+    /// iterator, yielding events in the order they occurred from the time of this call
+    /// until the session is closed, stopped by the ngrok service, fails to reconnect,
+    /// or is no longer referenced:
+    ///
+    /// for await (const event of session.events()) { console.log(event.type); }
+    ///
+    /// Command events are only sent for commands with a registered handler,
+    /// e.g. via {@link SessionBuilder.handleStopCommand}.
+    #[napi]
+    pub fn events(&self) -> SessionEvents {
+        self.shared.events.subscribe()
+    }
+
     /// Retrieve a list of this session's non-closed listeners, in no particular order.
     #[napi]
     pub async fn listeners(&self) -> Vec<Listener> {
-        let session_id = self.raw_session.lock().id();
+        let session_id = self.shared.raw_session.lock().id();
         search_listeners(Some(session_id), None).await
     }
 
     /// Close a listener with the given ID.
     #[napi]
     pub async fn close_listener(&self, id: String) -> Result<()> {
-        let session = self.raw_session.lock().clone();
+        let session = self.shared.raw_session.lock().clone();
         // close listener
         let res = session
             .close_tunnel(id.clone())
@@ -504,6 +1149,7 @@ impl Session {
         if res.is_ok() {
             // remove our reference to allow it to drop
             remove_global_listener(&id).await;
+            self.shared.events.send(SessionEvent::listener_closed(id));
         }
         res
     }
@@ -511,11 +1157,78 @@ impl Session {
     /// Close the ngrok session.
     #[napi]
     pub async fn close(&self) -> Result<()> {
-        let mut session = self.raw_session.lock().clone();
-        session
+        let mut session = self.shared.raw_session.lock().clone();
+        let span = session_span(&session.id());
+        let res = session
             .close()
+            .instrument(span)
             .await
-            .map_err(|e| napi_ngrok_err("failed to close session", &e))
+            .map_err(|e| napi_ngrok_err("failed to close session", &e));
+        unregister_session_events(&session.id());
+        if let Some(token) = self.shared.authtoken.lock().take() {
+            unregister_secret(token);
+        }
+        if SESSIONS.lock().remove(&session.id()).is_some() {
+            #[cfg(feature = "telemetry")]
+            record_sessions(-1);
+        }
+        res
+    }
+}
+
+/// The tracing span for the session with the given id, which listener spans are nested in.
+pub(crate) fn session_span(id: &str) -> Span {
+    info_span!("session", session_id = %id)
+}
+
+/// The number of connected sessions.
+#[cfg(feature = "telemetry")]
+pub(crate) fn session_count() -> usize {
+    SESSIONS.lock().len()
+}
+
+/// The connected session with the given id, if it is still around.
+fn find_session(id: &str) -> Option<Session> {
+    let shared = SESSIONS.lock().get(id).and_then(Weak::upgrade)?;
+    Some(Session { shared })
+}
+
+/// Close every connected session.
+pub(crate) async fn close_sessions() {
+    let sessions: Vec<Session> = SESSIONS
+        .lock()
+        .values()
+        .filter_map(Weak::upgrade)
+        .map(|shared| Session { shared })
+        .collect();
+    for res in future::join_all(sessions.iter().map(Session::close)).await {
+        if let Err(e) = res {
+            warn!("{}", e.reason);
+        }
+    }
+}
+
+impl Session {
+    /// The id of the current upstream session.
+    pub(crate) fn id(&self) -> String {
+        self.shared.raw_session.lock().id()
+    }
+}
+
+impl SessionBuilder {
+    /// Stop masking the builder's authtoken, once the builder is no longer used.
+    /// Its sessions mask the token they connected with for as long as they need it.
+    pub(crate) fn release_authtoken(&mut self) {
+        if let Some(token) = self.authtoken.take() {
+            unregister_secret(token);
+        }
+    }
+}
+
+impl ObjectFinalize for SessionBuilder {
+    fn finalize(mut self, mut _env: Env) -> Result<()> {
+        self.release_authtoken();
+        Ok(())
     }
 }
 
@@ -526,6 +1239,94 @@ impl ObjectFinalize for Session {
     }
 }
 
+/// The message of an error connecting to the ngrok service, with the cause of a failed
+/// TLS handshake, such as a certificate which matched none of the pins.
+fn connect_error_message(e: &ConnectError) -> String {
+    match e {
+        ConnectError::Tls(source) => format!("{e}: {source}"),
+        e => e.to_string(),
+    }
+}
+
+/// Split a server address into host and port, validating it the same way as the upstream builder.
+fn parse_server_addr(addr: &str) -> Result<(String, u16)> {
+    let server_uri = Url::parse(&format!("http://{addr}"))
+        .map_err(|e| napi_err(format!("invalid server address: {addr}, error: {e}")))?;
+    let host = server_uri
+        .host_str()
+        .ok_or_else(|| napi_err(format!("invalid server address: {addr}")))?;
+    Ok((host.to_string(), server_uri.port().unwrap_or(443)))
+}
+
+/// Where the ngrok agent keeps its config file, newest location first.
+fn agent_config_paths() -> Vec<PathBuf> {
+    let home = env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+    let mut paths = vec![];
+    if cfg!(target_os = "windows") {
+        if let Some(local) = env::var_os("LOCALAPPDATA") {
+            paths.push(PathBuf::from(local).join("ngrok"));
+        }
+    } else if cfg!(target_os = "macos") {
+        if let Some(ref home) = home {
+            paths.push(home.join("Library/Application Support/ngrok"));
+        }
+    } else {
+        match env::var_os("XDG_CONFIG_HOME") {
+            Some(config) => paths.push(PathBuf::from(config).join("ngrok")),
+            None => paths.extend(home.as_ref().map(|home| home.join(".config/ngrok"))),
+        }
+    }
+    // where version 2 of the agent kept it
+    paths.extend(home.map(|home| home.join(".ngrok2")));
+    paths.into_iter().map(|dir| dir.join("ngrok.yml")).collect()
+}
+
+/// Read the authtoken from an agent config file, in either the version 2 or 3 format.
+fn agent_config_authtoken(path: &Path) -> Result<String> {
+    let display = path.display();
+    let contents = fs::read_to_string(path)
+        .map_err(|e| napi_err(format!("Failed to read ngrok agent config {display}: {e}")))?;
+    let config: serde_yaml::Value = serde_yaml::from_str(&contents)
+        .map_err(|e| napi_err(format!("Failed to parse ngrok agent config {display}: {e}")))?;
+    config
+        .get("agent")
+        .and_then(|agent| agent.get("authtoken"))
+        .or_else(|| config.get("authtoken"))
+        .and_then(|token| token.as_str())
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| napi_err(format!("No authtoken in ngrok agent config {display}")))
+}
+
+/// Order the server addresses to try according to the strategy.
+async fn order_server_addrs(
+    addrs: Vec<(String, u16)>,
+    strategy: ServerAddrStrategy,
+) -> Vec<(String, u16)> {
+    if strategy == ServerAddrStrategy::Ordered || addrs.len() < 2 {
+        return addrs;
+    }
+    // measure all addresses concurrently, unreachable addresses go last
+    let latencies = future::join_all(addrs.iter().map(|(host, port)| async move {
+        let start = Instant::now();
+        let dial = TcpStream::connect((host.as_str(), *port));
+        match timeout(LATENCY_PROBE_TIMEOUT, dial).await {
+            Ok(Ok(_)) => Some(start.elapsed()),
+            _ => None,
+        }
+    }))
+    .await;
+    let mut measured: Vec<_> = addrs.into_iter().zip(latencies).collect();
+    // stable sort keeps the configured order for ties and unreachable addresses
+    measured.sort_by_key(|(_, latency)| latency.unwrap_or(Duration::MAX));
+    for ((host, port), latency) in measured.iter() {
+        debug!("Server address {host}:{port} latency: {latency:?}");
+    }
+    measured.into_iter().map(|(addr, _)| addr).collect()
+}
+
 /// Container for UpdateRequest information.
 #[derive(Clone)]
 #[napi]
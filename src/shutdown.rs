@@ -0,0 +1,162 @@
+use std::{
+    io,
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+    time::Duration,
+};
+
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use tracing::{
+    info,
+    warn,
+};
+
+use crate::{
+    connect::SESSION,
+    listener::drain_listeners,
+    napi_err,
+    session::{
+        call_tsfn,
+        close_sessions,
+        create_tsfn,
+    },
+};
+
+// how long to wait for active connections when no timeout is given
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+static SHUTDOWN_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Options for {@link shutdownOnSignals}.
+///
+/// @group Functions
+#[napi(object)]
+pub struct ShutdownOptions {
+    /// How long to wait in milliseconds for active connections to finish before
+    /// cutting them off, defaults to 10 seconds.
+    pub timeout: Option<u32>,
+    /// Whether to exit the process once shut down, defaults to true.
+    /// When false, control is handed back to nodejs after the cleanup hook has run.
+    pub exit: Option<bool>,
+}
+
+/// Gracefully shut down on SIGINT or SIGTERM (Ctrl-C on Windows): drain and close every
+/// listener, close every session, then run the optional cleanup hook and exit.
+/// A second signal during shutdown exits immediately, as does a signal after a shutdown
+/// which did not exit.
+///
+/// The signals are caught natively, so this should not be combined with
+/// `process.on("SIGINT")` or `process.on("SIGTERM")` handlers, which take precedence once registered.
+#[napi(
+    ts_args_type = "options?: ShutdownOptions, cleanup?: (signal: string) => void | Promise<void>"
+)]
+pub fn shutdown_on_signals(
+    env: Env,
+    options: Option<ShutdownOptions>,
+    on_shutdown: Option<JsFunction>,
+) -> Result<()> {
+    if SHUTDOWN_ENABLED.swap(true, Ordering::SeqCst) {
+        return Err(napi_err("Shutdown on signals is already enabled"));
+    }
+    let wait = options
+        .and_then(|o| o.timeout)
+        .map(|t| Duration::from_millis(t.into()))
+        .unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT);
+    let on_shutdown = on_shutdown.map(|f| create_tsfn::<String>(env, f));
+
+    // install the signal handlers now, rather than whenever the task first runs
+    let mut signals = within_runtime_if_available(Signals::new).map_err(|e| {
+        SHUTDOWN_ENABLED.store(false, Ordering::SeqCst);
+        napi_err(format!("failed to listen for signals: {e}"))
+    })?;
+
+    spawn(async move {
+        let signal = signals.recv().await;
+        info!("Received {signal}, shutting down");
+        tokio::select! {
+            _ = shutdown(wait) => {}
+            second = signals.recv() => {
+                warn!("Received {second} during shutdown, exiting");
+                std::process::exit(1);
+            }
+        }
+        if let Some(tsfn) = on_shutdown {
+            let _ = call_tsfn::<_, ()>(tsfn, vec![signal.to_string()]).await;
+        }
+        // when the process carries on, the handlers can't be uninstalled, so a later
+        // signal has to do what it otherwise would have done
+        let signal = signals.recv().await;
+        info!("Received {signal} after shutdown, exiting");
+        std::process::exit(exit_code(signal));
+    });
+    Ok(())
+}
+
+async fn shutdown(wait: Duration) {
+    let cut_off = drain_listeners(Some(wait)).await;
+    if cut_off > 0 {
+        warn!("Shutdown cut off {cut_off} connections");
+    }
+    close_sessions().await;
+    // the forward() sessions are closed with the rest
+    SESSION.lock().await.clear();
+    info!("Shutdown complete");
+}
+
+/// The exit code of a process killed by the signal.
+fn exit_code(signal: &str) -> i32 {
+    match signal {
+        "SIGTERM" => 143,
+        _ => 130,
+    }
+}
+
+/// The shutdown signals of the platform.
+struct Signals {
+    #[cfg(unix)]
+    interrupt: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+    #[cfg(windows)]
+    ctrl_c: tokio::signal::windows::CtrlC,
+}
+
+impl Signals {
+    #[cfg(unix)]
+    fn new() -> io::Result<Self> {
+        use tokio::signal::unix::{
+            signal,
+            SignalKind,
+        };
+        Ok(Signals {
+            interrupt: signal(SignalKind::interrupt())?,
+            terminate: signal(SignalKind::terminate())?,
+        })
+    }
+
+    #[cfg(windows)]
+    fn new() -> io::Result<Self> {
+        Ok(Signals {
+            ctrl_c: tokio::signal::windows::ctrl_c()?,
+        })
+    }
+
+    /// Wait for the next signal, returning its name.
+    #[cfg(unix)]
+    async fn recv(&mut self) -> &'static str {
+        tokio::select! {
+            _ = self.interrupt.recv() => "SIGINT",
+            _ = self.terminate.recv() => "SIGTERM",
+        }
+    }
+
+    /// Wait for the next signal, returning its name.
+    #[cfg(windows)]
+    async fn recv(&mut self) -> &'static str {
+        self.ctrl_c.recv().await;
+        "SIGINT"
+    }
+}
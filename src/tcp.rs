@@ -12,7 +12,8 @@ impl TcpListenerBuilder {
     #[napi]
     pub fn remote_addr(&mut self, remote_addr: String) -> &Self {
         let mut builder = self.listener_builder.lock();
-        builder.remote_addr(remote_addr);
+        builder.remote_addr(remote_addr.clone());
+        self.config.lock().remote_addr = Some(remote_addr);
         self
     }
 
@@ -21,6 +22,7 @@ impl TcpListenerBuilder {
     pub fn pooling_enabled(&mut self, pooling_enabled: bool) -> &Self {
         let mut builder = self.listener_builder.lock();
         builder.pooling_enabled(pooling_enabled);
+        self.config.lock().pooling_enabled = Some(pooling_enabled);
         self
     }
 }
@@ -0,0 +1,442 @@
+use std::{
+    collections::HashMap,
+    time::Duration,
+};
+
+use lazy_static::lazy_static;
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use opentelemetry::{
+    metrics::{
+        Counter,
+        MeterProvider,
+        ObservableUpDownCounter,
+        UpDownCounter,
+    },
+    trace::TracerProvider,
+    KeyValue,
+};
+use opentelemetry_otlp::{
+    MetricExporter,
+    SpanExporter,
+    WithExportConfig,
+};
+use opentelemetry_sdk::{
+    metrics::{
+        periodic_reader_with_async_runtime::PeriodicReader,
+        SdkMeterProvider,
+    },
+    runtime,
+    trace::{
+        span_processor_with_async_runtime::BatchSpanProcessor,
+        SdkTracerProvider,
+    },
+    Resource,
+};
+use parking_lot::Mutex;
+use tracing::{
+    field::{
+        Field,
+        Visit,
+    },
+    span,
+    Event,
+};
+use tracing_subscriber::{
+    layer::Context,
+    Layer,
+    Registry,
+};
+
+use crate::{
+    forwarding::active_connections,
+    listener::listener_count,
+    logging::{
+        init_subscriber,
+        set_sink,
+    },
+    napi_err,
+    redact::redact,
+    session::session_count,
+};
+
+const SINK_NAME: &str = "opentelemetry";
+const INSTRUMENTATION_NAME: &str = "ngrok-javascript";
+const DEFAULT_SERVICE_NAME: &str = "ngrok-javascript";
+const DEFAULT_METRICS_INTERVAL: Duration = Duration::from_secs(60);
+// the most fields a callsite has, as with the tracing macros
+const MAX_FIELDS: usize = 32;
+
+lazy_static! {
+    // the running export, kept to flush and shut it down
+    static ref PROVIDERS: Mutex<Option<(SdkTracerProvider, SdkMeterProvider)>> = Mutex::new(None);
+    // the instruments are only created once exporting, so recording is free otherwise
+    static ref METRICS: Mutex<Option<Metrics>> = Mutex::new(None);
+}
+
+/// Options for {@link exportTelemetry}.
+///
+/// @group Functions
+#[napi(object)]
+#[derive(Default)]
+pub struct TelemetryOptions {
+    /// The OTLP endpoint of the collector, e.g. "http://localhost:4317" for grpc, or
+    /// "http://localhost:4318" for http. Defaults to the `OTEL_EXPORTER_OTLP_ENDPOINT`
+    /// environment variable, or the standard local endpoint.
+    pub endpoint: Option<String>,
+    /// The OTLP protocol, "grpc" or "http" (protobuf over http). Defaults to "grpc".
+    pub protocol: Option<String>,
+    /// The `service.name` resource attribute, defaults to "ngrok-javascript".
+    pub service_name: Option<String>,
+    /// Other resource attributes to describe this process with, e.g. `deployment.environment`.
+    pub resource_attributes: Option<HashMap<String, String>>,
+    /// How often to export metrics in milliseconds, defaults to 60 seconds.
+    pub metrics_interval: Option<u32>,
+}
+
+/// The session, listener and connection metrics.
+struct Metrics {
+    sessions: UpDownCounter<i64>,
+    listeners: UpDownCounter<i64>,
+    connections: Counter<u64>,
+    // observed from the listeners' forwarding runtimes, which know when connections finish
+    _active_connections: ObservableUpDownCounter<i64>,
+}
+
+/// Export the tracing spans of sessions, listeners and connections, and metrics about them,
+/// to an OpenTelemetry collector over OTLP. The spans are only exported for the enabled
+/// log levels, as set with {@link setLogLevel}. Secrets in their fields and events are
+/// masked as in the logs, see {@link setRedaction}.
+/// Calling this again replaces the previous export.
+#[napi]
+pub async fn export_telemetry(options: Option<TelemetryOptions>) -> Result<()> {
+    init_subscriber(None)?;
+    let (tracer_provider, meter_provider) = providers(options.unwrap_or_default())?;
+
+    let layer =
+        tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer(INSTRUMENTATION_NAME));
+    set_sink(SINK_NAME, Some(Box::new(Redacted(layer))), None)?;
+    let metrics = Metrics::new(&meter_provider.meter(INSTRUMENTATION_NAME));
+    // the gauges start from what is already open
+    metrics.sessions.add(session_count() as i64, &[]);
+    metrics.listeners.add(listener_count().await as i64, &[]);
+    METRICS.lock().replace(metrics);
+    let previous = PROVIDERS.lock().replace((tracer_provider, meter_provider));
+    if let Some(previous) = previous {
+        shutdown(previous).await?;
+    }
+    Ok(())
+}
+
+/// Stop exporting to the OpenTelemetry collector, after sending any spans and metrics
+/// which have not been exported yet.
+#[napi]
+pub async fn stop_telemetry() -> Result<()> {
+    let Some(providers) = PROVIDERS.lock().take() else {
+        return Ok(());
+    };
+    set_sink(SINK_NAME, None, None)?;
+    METRICS.lock().take();
+    shutdown(providers).await
+}
+
+/// Create the providers exporting spans and metrics as configured.
+/// Must be called on the tokio runtime, which the grpc and http clients run on.
+fn providers(options: TelemetryOptions) -> Result<(SdkTracerProvider, SdkMeterProvider)> {
+    let http = match options.protocol.as_deref().unwrap_or("grpc") {
+        "grpc" => false,
+        "http" => true,
+        protocol => {
+            return Err(napi_err(format!(
+                "Unknown telemetry protocol {protocol:?}, expected \"grpc\" or \"http\""
+            )))
+        }
+    };
+    let endpoint = |path: &str| {
+        options.endpoint.as_ref().map(|endpoint| {
+            if http {
+                // http endpoints are per signal
+                format!("{}{path}", endpoint.trim_end_matches('/'))
+            } else {
+                endpoint.clone()
+            }
+        })
+    };
+    let span_exporter = if http {
+        let mut builder = SpanExporter::builder().with_http();
+        if let Some(endpoint) = endpoint("/v1/traces") {
+            builder = builder.with_endpoint(endpoint);
+        }
+        builder.build()
+    } else {
+        let mut builder = SpanExporter::builder().with_tonic();
+        if let Some(endpoint) = endpoint("") {
+            builder = builder.with_endpoint(endpoint);
+        }
+        builder.build()
+    }
+    .map_err(|e| napi_err(format!("Failed to create span exporter, {e}")))?;
+    let metric_exporter = if http {
+        let mut builder = MetricExporter::builder().with_http();
+        if let Some(endpoint) = endpoint("/v1/metrics") {
+            builder = builder.with_endpoint(endpoint);
+        }
+        builder.build()
+    } else {
+        let mut builder = MetricExporter::builder().with_tonic();
+        if let Some(endpoint) = endpoint("") {
+            builder = builder.with_endpoint(endpoint);
+        }
+        builder.build()
+    }
+    .map_err(|e| napi_err(format!("Failed to create metric exporter, {e}")))?;
+
+    let resource = Resource::builder()
+        .with_service_name(
+            options
+                .service_name
+                .unwrap_or_else(|| DEFAULT_SERVICE_NAME.to_string()),
+        )
+        .with_attributes(
+            options
+                .resource_attributes
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(key, value)| KeyValue::new(key, value)),
+        )
+        .build();
+
+    let tracer_provider = SdkTracerProvider::builder()
+        .with_span_processor(BatchSpanProcessor::builder(span_exporter, runtime::Tokio).build())
+        .with_resource(resource.clone())
+        .build();
+    let interval = options
+        .metrics_interval
+        .map(|ms| Duration::from_millis(ms.into()))
+        .unwrap_or(DEFAULT_METRICS_INTERVAL);
+    let meter_provider = SdkMeterProvider::builder()
+        .with_reader(
+            PeriodicReader::builder(metric_exporter, runtime::Tokio)
+                .with_interval(interval)
+                .build(),
+        )
+        .with_resource(resource)
+        .build();
+
+    Ok((tracer_provider, meter_provider))
+}
+
+async fn shutdown(
+    (tracer_provider, meter_provider): (SdkTracerProvider, SdkMeterProvider),
+) -> Result<()> {
+    // shutting down blocks until the exporting tasks on the runtime have finished
+    let (traces, metrics) = tokio::task::spawn_blocking(move || {
+        (tracer_provider.shutdown(), meter_provider.shutdown())
+    })
+    .await
+    .map_err(|e| napi_err(format!("Failed to stop telemetry, {e}")))?;
+    traces.map_err(|e| napi_err(format!("Failed to export spans, {e}")))?;
+    metrics.map_err(|e| napi_err(format!("Failed to export metrics, {e}")))
+}
+
+impl Metrics {
+    fn new(meter: &opentelemetry::metrics::Meter) -> Self {
+        Metrics {
+            sessions: meter
+                .i64_up_down_counter("ngrok.sessions.active")
+                .with_description("The number of connected sessions")
+                .build(),
+            listeners: meter
+                .i64_up_down_counter("ngrok.listeners.active")
+                .with_description("The number of open listeners")
+                .build(),
+            connections: meter
+                .u64_counter("ngrok.connections")
+                .with_description("The number of connections forwarded by listeners")
+                .build(),
+            _active_connections: meter
+                .i64_observable_up_down_counter("ngrok.connections.active")
+                .with_description("The number of connections currently being forwarded")
+                .with_callback(|observer| observer.observe(active_connections() as i64, &[]))
+                .build(),
+        }
+    }
+}
+
+/// Count a session being connected, or closed if negative.
+pub(crate) fn record_sessions(delta: i64) {
+    if let Some(metrics) = METRICS.lock().as_ref() {
+        metrics.sessions.add(delta, &[]);
+    }
+}
+
+/// Count a listener being opened, or closed if negative.
+pub(crate) fn record_listeners(delta: i64) {
+    if let Some(metrics) = METRICS.lock().as_ref() {
+        metrics.listeners.add(delta, &[]);
+    }
+}
+
+/// Count a connection being accepted for forwarding.
+pub(crate) fn record_connection_started() {
+    if let Some(metrics) = METRICS.lock().as_ref() {
+        metrics.connections.add(1, &[]);
+    }
+}
+
+/// Passes spans and events on to the layer with the secrets in their fields masked,
+/// as the OpenTelemetry layer records them directly rather than through a writer.
+struct Redacted<L>(L);
+
+impl<L: Layer<Registry>> Layer<Registry> for Redacted<L> {
+    fn register_callsite(
+        &self,
+        metadata: &'static tracing::Metadata<'static>,
+    ) -> tracing::subscriber::Interest {
+        self.0.register_callsite(metadata)
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, Registry>) {
+        let values = RedactedValues::new(attrs.metadata(), attrs);
+        let Some(fields) = values.fields() else {
+            return self.0.on_new_span(attrs, id, ctx);
+        };
+        let values = attrs.metadata().fields().value_set(&fields);
+        let attrs = match attrs.parent() {
+            Some(parent) => span::Attributes::child_of(parent.clone(), attrs.metadata(), &values),
+            None if attrs.is_root() => span::Attributes::new_root(attrs.metadata(), &values),
+            None => span::Attributes::new(attrs.metadata(), &values),
+        };
+        self.0.on_new_span(&attrs, id, ctx);
+    }
+
+    fn on_record(&self, id: &span::Id, record: &span::Record<'_>, ctx: Context<'_, Registry>) {
+        let Some(metadata) = ctx.metadata(id) else {
+            return;
+        };
+        let values = RedactedValues::new(metadata, record);
+        let Some(fields) = values.fields() else {
+            return self.0.on_record(id, record, ctx);
+        };
+        let values = metadata.fields().value_set(&fields);
+        self.0.on_record(id, &span::Record::new(&values), ctx);
+    }
+
+    fn on_follows_from(&self, id: &span::Id, follows: &span::Id, ctx: Context<'_, Registry>) {
+        self.0.on_follows_from(id, follows, ctx);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, Registry>) {
+        let values = RedactedValues::new(event.metadata(), event);
+        let Some(fields) = values.fields() else {
+            return self.0.on_event(event, ctx);
+        };
+        let values = event.metadata().fields().value_set(&fields);
+        let event = if event.is_contextual() {
+            Event::new(event.metadata(), &values)
+        } else {
+            Event::new_child_of(event.parent().cloned(), event.metadata(), &values)
+        };
+        self.0.on_event(&event, ctx);
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, Registry>) {
+        self.0.on_enter(id, ctx);
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, Registry>) {
+        self.0.on_exit(id, ctx);
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, Registry>) {
+        self.0.on_close(id, ctx);
+    }
+
+    fn on_id_change(&self, old: &span::Id, new: &span::Id, ctx: Context<'_, Registry>) {
+        self.0.on_id_change(old, new, ctx);
+    }
+}
+
+/// A copy of the values of spans and events, with the strings among them masked.
+struct RedactedValues {
+    values: Vec<(Field, Box<dyn tracing::Value>)>,
+    // any field of the callsite, to fill the unused slots of a value set with
+    padding: Option<Field>,
+}
+
+trait RecordFields {
+    fn record(&self, visitor: &mut dyn Visit);
+}
+
+impl RecordFields for span::Attributes<'_> {
+    fn record(&self, visitor: &mut dyn Visit) {
+        self.record(visitor)
+    }
+}
+
+impl RecordFields for span::Record<'_> {
+    fn record(&self, visitor: &mut dyn Visit) {
+        self.record(visitor)
+    }
+}
+
+impl RecordFields for Event<'_> {
+    fn record(&self, visitor: &mut dyn Visit) {
+        self.record(visitor)
+    }
+}
+
+impl RedactedValues {
+    fn new(metadata: &tracing::Metadata<'_>, fields: &impl RecordFields) -> Self {
+        let mut values = RedactedValues {
+            values: Vec::new(),
+            padding: metadata.fields().iter().next(),
+        };
+        fields.record(&mut values);
+        values
+    }
+
+    /// The values to build a value set from, padded to a fixed length with empty values,
+    /// or none if the callsite has no fields to mask.
+    fn fields(&self) -> Option<[(&Field, Option<&dyn tracing::Value>); MAX_FIELDS]> {
+        let mut fields = [(self.padding.as_ref()?, None); MAX_FIELDS];
+        for (slot, (field, value)) in fields.iter_mut().zip(&self.values) {
+            *slot = (field, Some(value.as_ref()));
+        }
+        Some(fields)
+    }
+
+    fn push(&mut self, field: &Field, value: impl tracing::Value + 'static) {
+        self.values.push((field.clone(), Box::new(value)));
+    }
+}
+
+impl Visit for RedactedValues {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field, value);
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, value);
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, value);
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, value);
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, redact(value).into_owned());
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.push(field, redact(&value.to_string()).into_owned());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.push(field, redact(&format!("{value:?}")).into_owned());
+    }
+}
@@ -2,7 +2,11 @@ use bytes::Bytes;
 use napi::bindgen_prelude::Uint8Array;
 use napi_derive::napi;
 
-use crate::listener_builder::TlsListenerBuilder;
+use crate::{
+    config::Certificate,
+    listener_builder::TlsListenerBuilder,
+    redact::register_secret,
+};
 
 #[napi]
 #[allow(dead_code)]
@@ -15,7 +19,8 @@ impl TlsListenerBuilder {
     #[napi]
     pub fn domain(&mut self, domain: String) -> &Self {
         let mut builder = self.listener_builder.lock();
-        builder.domain(domain);
+        builder.domain(domain.clone());
+        self.config.lock().domain = Some(domain);
         self
     }
     /// Certificates to use for client authentication at the ngrok edge.
@@ -26,6 +31,11 @@ impl TlsListenerBuilder {
     pub fn mutual_tlsca(&mut self, mutual_tlsca: Uint8Array) -> &Self {
         let mut builder = self.listener_builder.lock();
         builder.mutual_tlsca(Bytes::from(mutual_tlsca.to_vec()));
+        self.config
+            .lock()
+            .mutual_tls_cas
+            .get_or_insert_with(Vec::new)
+            .push(Certificate(mutual_tlsca.to_vec()));
         self
     }
 
@@ -34,6 +44,7 @@ impl TlsListenerBuilder {
     pub fn pooling_enabled(&mut self, pooling_enabled: bool) -> &Self {
         let mut builder = self.listener_builder.lock();
         builder.pooling_enabled(pooling_enabled);
+        self.config.lock().pooling_enabled = Some(pooling_enabled);
         self
     }
 
@@ -48,6 +59,11 @@ impl TlsListenerBuilder {
             Bytes::from(cert_pem.to_vec()),
             Bytes::from(key_pem.to_vec()),
         );
+        let key = String::from_utf8_lossy(&key_pem).into_owned();
+        register_secret(&key);
+        let mut config = self.config.lock();
+        config.crt = Some(String::from_utf8_lossy(&cert_pem).into_owned());
+        config.key = Some(key);
         self
     }
 }
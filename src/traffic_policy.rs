@@ -0,0 +1,470 @@
+use core::result::Result as CoreResult;
+use std::{
+    collections::HashMap,
+    fs,
+};
+
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use serde_json::{
+    json,
+    Value,
+};
+
+use crate::napi_err;
+
+/// The phases of a traffic policy, as named in the policy document.
+pub(crate) const PHASES: [&str; 3] = ["on_http_request", "on_http_response", "on_tcp_connect"];
+
+/// A rule of a {@link TrafficPolicy}: the actions to run when all of its expressions match.
+///
+/// @group Listener Builders
+#[napi(object)]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TrafficPolicyRule {
+    /// A name for the rule, to identify it in logs and dashboards.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// CEL expressions which must all match for the actions to run, e.g. "req.url.path.startsWith('/api')".
+    /// The actions always run if there are none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expressions: Option<Vec<String>>,
+    /// The actions to run, in order.
+    pub actions: Vec<TrafficPolicyAction>,
+}
+
+/// An action of a {@link TrafficPolicyRule}.
+/// See [Traffic Policy Actions] in the ngrok docs for the action types and their configuration.
+///
+/// [Traffic Policy Actions]: https://ngrok.com/docs/traffic-policy/actions/
+///
+/// @group Listener Builders
+#[napi(object)]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TrafficPolicyAction {
+    /// The type of action, e.g. "restrict-ips" or "custom-response".
+    #[napi(js_name = "type")]
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The configuration of the action.
+    #[napi(ts_type = "Record<string, any>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<Value>,
+}
+
+/// The rules of each phase, in the shape of the policy document.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub(crate) struct PolicyDocument {
+    #[serde(default, alias = "inbound", skip_serializing_if = "Vec::is_empty")]
+    pub(crate) on_http_request: Vec<TrafficPolicyRule>,
+    #[serde(default, alias = "outbound", skip_serializing_if = "Vec::is_empty")]
+    pub(crate) on_http_response: Vec<TrafficPolicyRule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) on_tcp_connect: Vec<TrafficPolicyRule>,
+}
+
+impl PolicyDocument {
+    fn phase(&mut self, phase: &str) -> Result<&mut Vec<TrafficPolicyRule>> {
+        match phase {
+            "on_http_request" => Ok(&mut self.on_http_request),
+            "on_http_response" => Ok(&mut self.on_http_response),
+            "on_tcp_connect" => Ok(&mut self.on_tcp_connect),
+            _ => Err(napi_err(format!(
+                "Unknown traffic policy phase {phase:?}, expected one of {PHASES:?}"
+            ))),
+        }
+    }
+}
+
+/// A traffic policy, built from typed rules and presets for common needs.
+/// Pass it to {@link HttpListenerBuilder.trafficPolicy} or the `traffic_policy`
+/// option of {@link forward}, or call `toString()` for the policy document.
+/// See [Traffic Policy] in the ngrok docs for additional details.
+///
+/// [Traffic Policy]: https://ngrok.com/docs/traffic-policy/
+///
+/// @group Listener Builders
+#[napi]
+#[derive(Default)]
+pub struct TrafficPolicy {
+    document: PolicyDocument,
+}
+
+#[napi]
+impl TrafficPolicy {
+    /// Create an empty traffic policy.
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add a rule for HTTP requests, before they are forwarded to the listener.
+    #[napi]
+    pub fn on_http_request(&mut self, rule: TrafficPolicyRule) -> &Self {
+        self.document.on_http_request.push(rule);
+        self
+    }
+
+    /// Add a rule for HTTP responses, before they are returned to the client.
+    #[napi]
+    pub fn on_http_response(&mut self, rule: TrafficPolicyRule) -> &Self {
+        self.document.on_http_response.push(rule);
+        self
+    }
+
+    /// Add a rule for TCP connections, as they are established.
+    #[napi]
+    pub fn on_tcp_connect(&mut self, rule: TrafficPolicyRule) -> &Self {
+        self.document.on_tcp_connect.push(rule);
+        self
+    }
+
+    /// Only allow connections from the `allow` CIDR ranges, and reject those from the `deny` ranges.
+    /// Applies to HTTP requests, or to the given phase, e.g. "on_tcp_connect" for TCP and TLS endpoints.
+    #[napi]
+    pub fn restrict_ips(
+        &mut self,
+        allow: Option<Vec<String>>,
+        deny: Option<Vec<String>>,
+        phase: Option<String>,
+    ) -> Result<&Self> {
+        let phase = phase.as_deref().unwrap_or("on_http_request");
+        let rule = preset_rule(
+            "restrict ips",
+            None,
+            "restrict-ips",
+            json!({
+                "enforce": true,
+                "allow": allow.unwrap_or_default(),
+                "deny": deny.unwrap_or_default(),
+            }),
+        );
+        self.document.phase(phase)?.push(rule);
+        Ok(self)
+    }
+
+    /// Limit each client IP to `capacity` HTTP requests per `rate`, e.g. `rateLimit(100, "60s")`.
+    /// Requests can be bucketed by other keys instead, e.g. ["req.headers['x-api-key']"].
+    #[napi]
+    pub fn rate_limit(
+        &mut self,
+        capacity: u32,
+        rate: String,
+        bucket_key: Option<Vec<String>>,
+    ) -> &Self {
+        let rule = preset_rule(
+            "rate limit",
+            None,
+            "rate-limit",
+            json!({
+                "name": "rate limit",
+                "algorithm": "sliding_window",
+                "capacity": capacity,
+                "rate": rate,
+                "bucket_key": bucket_key.unwrap_or_else(|| vec!["conn.client_ip".to_string()]),
+            }),
+        );
+        self.document.on_http_request.push(rule);
+        self
+    }
+
+    /// Require HTTP requests to carry a bearer JWT in the Authorization header, issued by
+    /// `issuer` for `audience`, and signed by a key from the JSON Web Key Set at `jwksUrl`.
+    #[napi]
+    pub fn validate_jwt(&mut self, issuer: String, audience: String, jwks_url: String) -> &Self {
+        let rule = preset_rule(
+            "validate jwt",
+            None,
+            "jwt-validation",
+            json!({
+                "issuer": { "allow_list": [{ "value": issuer }] },
+                "audience": { "allow_list": [{ "value": audience }] },
+                "http": {
+                    "tokens": [{
+                        "type": "jwt",
+                        "method": "header",
+                        "name": "Authorization",
+                        "prefix": "Bearer ",
+                    }],
+                },
+                "jws": {
+                    "allowed_algorithms": ["RS256"],
+                    "keys": { "sources": { "additional_jkus": [jwks_url] } },
+                },
+            }),
+        );
+        self.document.on_http_request.push(rule);
+        self
+    }
+
+    /// Respond to HTTP requests from the edge instead of forwarding them, for example
+    /// to serve a maintenance page. Only requests matching all of the expressions are
+    /// answered, or every request if there are none.
+    #[napi]
+    pub fn custom_response(
+        &mut self,
+        status_code: u32,
+        content: Option<String>,
+        headers: Option<HashMap<String, String>>,
+        expressions: Option<Vec<String>>,
+    ) -> &Self {
+        let mut config = json!({ "status_code": status_code });
+        if let Some(content) = content {
+            config["content"] = json!(content);
+        }
+        if let Some(headers) = headers {
+            config["headers"] = json!(headers);
+        }
+        let rule = preset_rule("custom response", expressions, "custom-response", config);
+        self.document.on_http_request.push(rule);
+        self
+    }
+
+    /// The policy document as a JSON string, as accepted by `trafficPolicy`.
+    #[napi(js_name = "toString")]
+    pub fn serialize(&self) -> Result<String> {
+        serde_json::to_string(&self.document)
+            .map_err(|e| napi_err(format!("failed to serialize traffic policy: {e}")))
+    }
+}
+
+fn preset_rule(
+    name: &str,
+    expressions: Option<Vec<String>>,
+    kind: &str,
+    config: Value,
+) -> TrafficPolicyRule {
+    TrafficPolicyRule {
+        name: Some(name.to_string()),
+        expressions,
+        actions: vec![TrafficPolicyAction {
+            kind: kind.to_string(),
+            config: Some(config),
+        }],
+    }
+}
+
+/// Read a traffic policy file in YAML or JSON, checking it locally so that mistakes are
+/// reported with their line and column, rather than as an error from the ngrok service.
+pub(crate) fn load_traffic_policy_file(path: &str) -> Result<String> {
+    let policy = fs::read_to_string(path)
+        .map_err(|e| napi_err(format!("failed to read traffic policy file {path}: {e}")))?;
+    lint_traffic_policy(&policy)
+        .map_err(|e| napi_err(format!("invalid traffic policy file {path}: {e}")))?;
+    Ok(policy)
+}
+
+/// Check a policy document against the known phases, and the required fields of the known
+/// action types. Other action types are only warned about.
+/// JSON is a subset of YAML, so both are parsed as YAML.
+pub(crate) fn lint_traffic_policy(policy: &str) -> CoreResult<(), serde_yaml::Error> {
+    serde_yaml::from_str::<lint::Document>(policy).map(|_| ())
+}
+
+/// The shapes a policy document is checked against, which are never read.
+/// Unknown fields are errors, except within action configuration, which has many optional fields.
+mod lint {
+    #![allow(dead_code)]
+
+    use std::{
+        collections::HashMap,
+        fmt,
+    };
+
+    use serde::{
+        de::{
+            self,
+            DeserializeSeed,
+            IgnoredAny,
+            MapAccess,
+            Visitor,
+        },
+        Deserialize,
+        Deserializer,
+    };
+    use tracing::warn;
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub(super) struct Document {
+        #[serde(default)]
+        on_http_request: Vec<Rule>,
+        #[serde(default)]
+        on_http_response: Vec<Rule>,
+        #[serde(default)]
+        on_tcp_connect: Vec<Rule>,
+        // the deprecated names of the http phases
+        #[serde(default)]
+        inbound: Vec<Rule>,
+        #[serde(default)]
+        outbound: Vec<Rule>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Rule {
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        expressions: Option<Vec<String>>,
+        actions: Vec<Action>,
+    }
+
+    /// An action, whose configuration is checked when its type is one of those known here.
+    /// The service knows more action types than these, so others are let through with a warning.
+    struct Action;
+
+    impl<'de> Deserialize<'de> for Action {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_map(ActionVisitor)
+        }
+    }
+
+    struct ActionVisitor;
+
+    impl<'de> Visitor<'de> for ActionVisitor {
+        type Value = Action;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an action with a type and optional config")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Action, A::Error> {
+            let mut kind: Option<String> = None;
+            let mut config = None;
+            let mut checked = false;
+            while let Some(key) = map.next_key::<String>()? {
+                match key.as_str() {
+                    "type" => kind = Some(map.next_value()?),
+                    // checked in place when the type is known by now, so errors keep their location
+                    "config" => match kind {
+                        Some(ref kind) => {
+                            map.next_value_seed(ConfigSeed(kind))?;
+                            checked = true;
+                        }
+                        None => config = Some(map.next_value::<serde_yaml::Value>()?),
+                    },
+                    other => return Err(de::Error::unknown_field(other, &["type", "config"])),
+                }
+            }
+            let kind = kind.ok_or_else(|| de::Error::missing_field("type"))?;
+            if !checked {
+                check_config(&kind, config.unwrap_or_default()).map_err(de::Error::custom)?;
+            }
+            if !KNOWN_ACTIONS.contains(&kind.as_str()) {
+                warn!("Unknown traffic policy action type {kind:?}, leaving it to the ngrok service to check");
+            }
+            Ok(Action)
+        }
+    }
+
+    const KNOWN_ACTIONS: [&str; 19] = [
+        "add-headers",
+        "basic-auth",
+        "compress-response",
+        "custom-response",
+        "deny",
+        "forward-internal",
+        "http-request",
+        "jwt-validation",
+        "log",
+        "oauth",
+        "openid-connect",
+        "rate-limit",
+        "redirect",
+        "remove-headers",
+        "restrict-ips",
+        "set-vars",
+        "terminate-tls",
+        "url-rewrite",
+        "verify-webhook",
+    ];
+
+    struct ConfigSeed<'a>(&'a str);
+
+    impl<'de> DeserializeSeed<'de> for ConfigSeed<'_> {
+        type Value = ();
+
+        fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+            check_config(self.0, deserializer)
+        }
+    }
+
+    /// Check the configuration of an action against the required fields of its type.
+    fn check_config<'de, D: Deserializer<'de>>(kind: &str, config: D) -> Result<(), D::Error> {
+        match kind {
+            "add-headers" => AddHeadersConfig::deserialize(config).map(drop),
+            "basic-auth" => BasicAuthConfig::deserialize(config).map(drop),
+            "custom-response" => CustomResponseConfig::deserialize(config).map(drop),
+            "forward-internal" => ForwardInternalConfig::deserialize(config).map(drop),
+            "oauth" => OauthConfig::deserialize(config).map(drop),
+            "rate-limit" => RateLimitConfig::deserialize(config).map(drop),
+            "redirect" | "url-rewrite" => RewriteConfig::deserialize(config).map(drop),
+            "remove-headers" => RemoveHeadersConfig::deserialize(config).map(drop),
+            "restrict-ips" => RestrictIpsConfig::deserialize(config).map(drop),
+            "verify-webhook" => VerifyWebhookConfig::deserialize(config).map(drop),
+            _ => IgnoredAny::deserialize(config).map(drop),
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct AddHeadersConfig {
+        headers: HashMap<String, String>,
+    }
+
+    #[derive(Deserialize)]
+    struct BasicAuthConfig {
+        credentials: Vec<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct CustomResponseConfig {
+        status_code: u16,
+    }
+
+    #[derive(Deserialize)]
+    struct ForwardInternalConfig {
+        url: String,
+    }
+
+    #[derive(Deserialize)]
+    struct OauthConfig {
+        provider: String,
+    }
+
+    #[derive(Deserialize)]
+    struct RateLimitConfig {
+        algorithm: String,
+        capacity: u32,
+        rate: String,
+        bucket_key: Vec<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct RewriteConfig {
+        to: String,
+    }
+
+    #[derive(Deserialize)]
+    struct RemoveHeadersConfig {
+        headers: Vec<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct RestrictIpsConfig {
+        #[serde(default)]
+        allow: Vec<String>,
+        #[serde(default)]
+        deny: Vec<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct VerifyWebhookConfig {
+        provider: String,
+        secret: String,
+    }
+}
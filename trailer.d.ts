@@ -13,3 +13,7 @@ export function listen(server: import("net").Server, listener?: Listener): Liste
  * Optionally set the logging level to one of ERROR, WARN, INFO, DEBUG, or TRACE.
  */
 export function consoleLog(level?: String): void;
+/**
+ * Session events are consumed as an async iterator, e.g. `for await (const event of session.events())`.
+ */
+export interface SessionEvents extends AsyncIterableIterator<SessionEvent> {}